@@ -84,7 +84,7 @@ fn bench_get_inclusion_proof_latest_root(c: &mut Criterion) {
         b.iter_batched(
             || (tree_data.clone(), random_identity),
             |(tree_data, random_identity)| {
-                tree_data.get_inclusion_proof(random_identity, None);
+                let _ = tree_data.get_inclusion_proof(random_identity, None);
             },
             BatchSize::SmallInput,
         );
@@ -114,7 +114,8 @@ fn bench_get_inclusion_proof_historical_root(c: &mut Criterion) {
         b.iter_batched(
             || (tree_data.clone(), identity, oldest_root),
             |(tree_data, identity, oldest_root)| {
-                tree_data.get_inclusion_proof(identity, Some(oldest_root));
+                let _ =
+                    tree_data.get_inclusion_proof(identity, Some(oldest_root));
             },
             BatchSize::SmallInput,
         );
@@ -1,102 +1,1031 @@
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use axum::http;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use ethers::prelude::{JsonRpcError, RetryPolicy};
-use common::shutdown_tracer_provider;
-use ethers::providers::{Http, Provider, RetryClientBuilder};
-use ethers_throttle::ThrottledProvider;
+use common::tracing::{init_datadog_subscriber, init_stdout_subscriber};
+use ethers::providers::{
+    Http, Middleware, Provider, RetryClient, RetryClientBuilder,
+    RetryClientError, RpcError, Ws,
+};
+use ethers_circuit_breaker::{CircuitBreaker, CircuitBreakerProvider};
+use ethers_failover::FailoverProvider;
+#[cfg(feature = "record-replay")]
+use ethers_record_replay::RecordingProvider;
+use ethers_retry_budget::RetryBudgetProvider;
+use ethers_throttle::{Throttle, ThrottledProvider};
+use ethers_transport::{Transport, TransportError};
 use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 use governor::Jitter;
-use serde::Deserialize;
-use world_tree::tree::config::ServiceConfig;
+use reqwest::header::HeaderMap;
+use sea_orm::{Database, DatabaseConnection};
+use semaphore::lazy_merkle_tree::Canonical;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use url::Url;
+use world_tree::audit::AuditLog;
+use world_tree::claims::ClaimStorage;
+use world_tree::tree::attestation::RootSigner;
+use world_tree::tree::batch_client::RpcBatchClient;
+use world_tree::tree::block_scanner::is_size_limit_response;
+use world_tree::tree::config::{
+    ProviderConfig, ServiceConfig, WorldTreeConfig,
+};
+use world_tree::tree::db_writer::DbWriter;
+use world_tree::tree::leaf_transform::LeafTransform;
+use world_tree::tree::metrics::install_prometheus_recorder;
 use world_tree::tree::service::TreeAvailabilityService;
+use world_tree::tree::supervisor::supervise;
+use world_tree::tree::{db, preflight, Hash, PoseidonTree, WorldTree};
 use ethers::providers::HttpClientError;
 /// This service syncs the state of the World Tree and spawns a server that can deliver inclusion proofs for a given identity.
 #[derive(Parser, Debug)]
 #[clap(name = "Tree Availability Service")]
 #[clap(version)]
 struct Opts {
-    /// Path to the configuration file
-    #[clap(short, long)]
+    /// Path to the configuration file. Without a file, the configuration is read from `WLD__` prefixed environment variables only, which also override the values of the file
+    #[clap(short, long, env = "WORLD_TREE_CONFIG")]
     config: Option<PathBuf>,
 
     /// Enable datadog backend for instrumentation
     #[clap(long, env)]
     datadog: bool,
+
+    /// Record metrics in memory and expose them in the Prometheus text format on the internal `/metrics` route
+    #[clap(long, env)]
+    prometheus: bool,
+
+    /// Rebuild the tree from the insertions and deletions persisted in the database and resume syncing from the latest persisted batch, instead of re-scanning the chain
+    #[clap(long)]
+    rebuild_from_db: bool,
+
+    /// Cache the logs fetched per block window in this directory, so that repeated syncs skip the windows already fetched. Overrides `world_tree.log_cache_dir`
+    #[clap(long)]
+    log_cache_dir: Option<PathBuf>,
+
+    /// Validate the configuration against the provider and exit without syncing the tree. Checks that the contract is deployed at `world_id_contract_address` and that the chain head has reached `creation_block`
+    #[clap(long)]
+    validate: bool,
+
+    /// Verify the snapshot at this path and exit without syncing the tree. Checks that the root recomputed from the snapshot leaves matches the recorded root and the onchain `latestRoot()` at the snapshot block
+    #[clap(long)]
+    verify_snapshot: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compares the leaves persisted in the database against the tree derived from onchain events and reports the first divergence
+    DiffOnchain {
+        /// First leaf index to compare
+        #[clap(long, default_value_t = 0)]
+        start: usize,
+        /// Leaf index to stop comparing at (exclusive). Defaults to the number of leaves in the database
+        #[clap(long)]
+        end: Option<usize>,
+    },
+    /// Syncs the tree from the contract creation block up to a target block, persisting the indexed records to the database and streaming NDJSON progress to stdout
+    Backfill {
+        /// Last block to backfill (inclusive). Defaults to the chain head
+        #[clap(long)]
+        to_block: Option<u64>,
+        /// Interval in seconds between progress lines
+        #[clap(long, default_value_t = 10)]
+        progress_interval_secs: u64,
+    },
+    /// Re-scans a block range for claims and persists them to the database, without affecting the claims synced by a running service
+    BackfillClaims {
+        /// First block to backfill
+        #[clap(long)]
+        from_block: u64,
+        /// Last block to backfill (inclusive). Defaults to the chain head
+        #[clap(long)]
+        to_block: Option<u64>,
+    },
+    /// Syncs the tree to the chain head, prints the inclusion proof of a single identity commitment as JSON and exits, without serving the API. With `--rebuild-from-db`, the tree is restored from the database before syncing
+    Proof {
+        /// Identity commitment to generate the inclusion proof for
+        commitment: Hash,
+    },
+}
+
+/// Progress of a backfill, emitted as a single NDJSON line on stdout.
+#[derive(Serialize, Debug)]
+struct BackfillProgress {
+    processed_blocks: u64,
+    inserted: u64,
+    deleted: u64,
+    current_block: u64,
 }
 
 #[tokio::main]
 pub async fn main() -> eyre::Result<()> {
     dotenv::dotenv().ok();
-    let config = ServiceConfig::load(Some(Path::new("/home/atris/world-tree/default_config.json")))?;
-
-    // construct a subscriber that prints formatted traces to stdout
-    let subscriber = tracing_subscriber::FmtSubscriber::new();
-    // use that subscriber to process traces emitted after this point
-    tracing::subscriber::set_global_default(subscriber)?;
-
-    let http_provider = Http::new(config.provider.rpc_endpoint);
-
-    let throttled_http_provider = ThrottledProvider::new(
-        http_provider,
-        config.provider.throttle.unwrap_or(u32::MAX),
-        Some(Jitter::new(
-            Duration::from_millis(50),
-            Duration::from_millis(500),
-        )),
+    let opts = Opts::parse();
+
+    // Spans are flushed when the handle is dropped at the end of `main`. Installed before the config is loaded, so that warnings about the config are reported
+    let _tracing_handle = if opts.datadog {
+        init_datadog_subscriber(SERVICE_NAME)?
+    } else {
+        init_stdout_subscriber()?
+    };
+
+    if let Some(config_path) = &opts.config {
+        if !config_path.is_file() {
+            eyre::bail!("Config file {} does not exist", config_path.display());
+        }
+    }
+    let mut config = ServiceConfig::load(opts.config.as_deref())?;
+    if let Some(log_cache_dir) = opts.log_cache_dir {
+        config.world_tree.log_cache_dir = Some(log_cache_dir);
+    }
+
+    let audit_log =
+        config.audit_log.as_ref().map(AuditLog::open).transpose()?;
+
+    let transport =
+        connect(config.provider.rpc_endpoint.clone(), &config.provider).await?;
+    let mut transports = vec![transport.clone()];
+    for fallback_rpc_endpoint in &config.provider.fallback_rpc_endpoints {
+        transports.push(
+            connect(fallback_rpc_endpoint.clone(), &config.provider).await?,
+        );
+    }
+    let (provider, throttle, circuit_breaker) =
+        build_provider(transports, &config.provider)?;
+    let middleware = Arc::new(provider);
+
+    if opts.validate {
+        return validate(&config, middleware).await;
+    }
+
+    if let Some(path) = &opts.verify_snapshot {
+        return verify_snapshot(&config, middleware, path).await;
+    }
+
+    if let Some(expected_block) = &config.provider.expected_block {
+        preflight::verify_expected_block(middleware.as_ref(), expected_block)
+            .await?;
+    }
+
+    if config.mode.indexes_tree() {
+        preflight::verify_creation_block(
+            middleware.as_ref(),
+            config.world_tree.creation_block,
+            config.world_tree.wait_for_creation_block,
+        )
+        .await?;
+
+        preflight::verify_contract_version(
+            middleware.clone(),
+            config.world_tree.world_id_contract_address,
+            config.world_tree.contract_version,
+        )
+        .await?;
+    }
+
+    match opts.command {
+        Some(Command::DiffOnchain { start, end }) => {
+            return diff_onchain(&config, middleware, start, end).await;
+        }
+        Some(Command::Backfill {
+            to_block,
+            progress_interval_secs,
+        }) => {
+            return backfill(
+                &config,
+                middleware,
+                to_block,
+                Duration::from_secs(progress_interval_secs),
+            )
+            .await;
+        }
+        Some(Command::BackfillClaims {
+            from_block,
+            to_block,
+        }) => {
+            return backfill_claims(&config, middleware, from_block, to_block)
+                .await;
+        }
+        Some(Command::Proof { commitment }) => {
+            return proof(
+                &config,
+                middleware,
+                commitment,
+                opts.rebuild_from_db,
+            )
+            .await;
+        }
+        None => {}
+    }
+
+    let db = match config.database.url() {
+        Some(database_url) => Some(Database::connect(database_url).await?),
+        None => None,
+    };
+
+    let shutdown = CancellationToken::new();
+    let mut handles = FuturesUnordered::new();
+    let mut db_writer = None;
+    let mut snapshot_tree = None;
+
+    if config.mode.indexes_tree() {
+        let mut service = TreeAvailabilityService::new(
+            &config.world_tree,
+            middleware.clone(),
+        )
+        .with_api_config(config.api.clone())
+        .with_circuit_breaker(circuit_breaker)
+        .with_restart_policy(config.restart);
+
+        if let Some(rate_limit) = config.rate_limit {
+            service = service.with_rate_limit(rate_limit);
+        }
+
+        if let Some(audit_log) = &audit_log {
+            service = service.with_audit_log(audit_log.clone());
+        }
+
+        if opts.prometheus {
+            service = service.with_prometheus(install_prometheus_recorder()?);
+        }
+
+        match &db {
+            Some(db) => {
+                let writer = Arc::new(DbWriter::spawn(
+                    db.clone(),
+                    config.database.write_retry,
+                ));
+                service = service
+                    .with_db_writer(writer.clone())
+                    .with_database(db.clone());
+                db_writer = Some(writer);
+            }
+            None => {
+                tracing::warn!(
+                    "No database configured, indexed records will not be persisted"
+                );
+            }
+        }
+
+        if let Some(secondary_rpc_endpoint) =
+            &config.provider.secondary_rpc_endpoint
+        {
+            // The secondary endpoint has no fallbacks, and only requests to the primary endpoint are recorded
+            let secondary_config = ProviderConfig {
+                fallback_rpc_endpoints: Vec::new(),
+                record_path: None,
+                ..config.provider.clone()
+            };
+            let secondary_transport =
+                connect(secondary_rpc_endpoint.clone(), &secondary_config)
+                    .await?;
+            let (secondary_provider, _, _) =
+                build_provider(vec![secondary_transport], &secondary_config)?;
+            service = service
+                .with_secondary_middleware(Arc::new(secondary_provider));
+        }
+
+        if let Some(signing) = &config.signing {
+            service = service.with_root_signer(RootSigner::new(
+                signing,
+                config.world_tree.world_id_contract_address,
+            )?);
+        }
+
+        let snapshot = config
+            .world_tree
+            .snapshot
+            .as_ref()
+            .filter(|snapshot| snapshot.path.exists());
+
+        if let Some(snapshot) = snapshot {
+            service.world_tree.load_snapshot(&snapshot.path).await?;
+        } else if opts.rebuild_from_db {
+            let db = db.as_ref().ok_or_else(|| {
+                eyre::eyre!("A database is required to rebuild the tree")
+            })?;
+            service =
+                rebuild_from_db(&config, service, db, middleware.clone())
+                    .await?;
+        }
+
+        if let Transport::Ws(ws) = &transport {
+            tracing::info!("Subscribing to `TreeChanged` logs");
+            service = service.with_log_subscriber(Provider::new(ws.clone()));
+        }
+
+        if let Some(max_batch_size) = config.provider.max_batch_size {
+            if matches!(transport, Transport::Ws(_)) {
+                eyre::bail!(
+                    "provider.max_batch_size requires an http(s) endpoint"
+                );
+            }
+
+            let batch_client = RpcBatchClient::new(
+                config.provider.rpc_endpoint.clone(),
+                max_batch_size,
+                Some(throttle),
+            )
+            .with_headers(HeaderMap::try_from(&config.provider.headers)?);
+            service = service.with_rpc_batch_client(batch_client);
+        }
+
+        if let Some(snapshot) = &config.world_tree.snapshot {
+            let world_tree = service.world_tree.clone();
+            let path = snapshot.path.clone();
+            let interval = Duration::from_secs(snapshot.interval_secs);
+            let snapshot_shutdown = shutdown.clone();
+
+            let handle = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(interval);
+                // The first tick completes immediately
+                interval.tick().await;
+
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = snapshot_shutdown.cancelled() => break,
+                    }
+
+                    if let Err(err) = world_tree.save_snapshot(&path).await {
+                        tracing::error!(?err, "Failed to save snapshot");
+                    }
+                }
+            });
+
+            snapshot_tree = Some((service.world_tree.clone(), handle));
+        }
+
+        let binds = config.world_tree.binds();
+        for handle in service.serve(binds, shutdown.clone()) {
+            handles.push(
+                async move {
+                    handle.await??;
+                    eyre::Ok(())
+                }
+                .boxed(),
+            );
+        }
+    }
+
+    if config.mode.indexes_claims() {
+        let claims = config.claims.as_ref().ok_or_else(|| {
+            eyre::eyre!("A claims config is required to index claims")
+        })?;
+        let db = db.clone().ok_or_else(|| {
+            eyre::eyre!("A database is required to index claims")
+        })?;
+
+        if let Some(bind) = claims.bind {
+            let handle = world_tree::claims::service::serve(
+                bind,
+                db.clone(),
+                shutdown.clone(),
+            );
+            handles.push(
+                async move {
+                    handle.await??;
+                    eyre::Ok(())
+                }
+                .boxed(),
+            );
+        }
+
+        let claim_storage = ClaimStorage::new(claims, middleware);
+        let sync_shutdown = shutdown.clone();
+        let handle =
+            supervise("claims", config.restart, shutdown.clone(), move || {
+                claim_storage.spawn(db.clone(), sync_shutdown.clone())
+            });
+        handles.push(
+            async move {
+                handle.await??;
+                eyre::Ok(())
+            }
+            .boxed(),
+        );
+    }
+
+    let result = tokio::select! {
+        result = async {
+            while let Some(result) = handles.next().await {
+                if let Err(err) = &result {
+                    tracing::error!(?err, "Indexer task failed");
+                }
+                result?;
+            }
+
+            eyre::Ok(())
+        } => result,
+        _ = tokio::signal::ctrl_c() => {
+            tracing::info!("Received shutdown signal");
+            Ok(())
+        }
+    };
+
+    // Let the remaining tasks finish the sync in progress, so that the snapshot and database are not written mid-sync
+    shutdown.cancel();
+    let mut failed = result.is_err();
+    let stopped = tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+        while let Some(result) = handles.next().await {
+            if let Err(err) = result {
+                tracing::error!(?err, "Indexer task failed during shutdown");
+                failed = true;
+            }
+        }
+    })
+    .await;
+
+    if stopped.is_err() {
+        tracing::warn!("Timed out waiting for indexer tasks to stop");
+    }
+
+    // Snapshot the tree on shutdown so that the next start resumes from the latest synced block. A periodic save still in progress is awaited first, so that the two never write the snapshot at once. After a task failure the tree may not be trusted, so the previous snapshot is kept
+    if let (Some((world_tree, handle)), Some(snapshot)) =
+        (snapshot_tree, &config.world_tree.snapshot)
+    {
+        if let Err(err) = handle.await {
+            tracing::error!(?err, "Snapshot task failed");
+        }
+
+        if failed {
+            tracing::warn!("Skipping snapshot after a task failure");
+        } else if let Err(err) = world_tree.save_snapshot(&snapshot.path).await
+        {
+            tracing::error!(?err, "Failed to save snapshot");
+        }
+    }
+
+    // Flush records indexed before the shutdown so they aren't lost
+    if let Some(db_writer) = db_writer {
+        db_writer
+            .shutdown(Duration::from_millis(config.database.drain_timeout_ms))
+            .await;
+    }
+
+    // Wait for the audit records of the final sync to be written
+    if let Some(audit_log) = &audit_log {
+        audit_log.flush().await;
+    }
+
+    result
+}
+
+type ServiceClient = CircuitBreakerProvider<
+    RetryBudgetProvider<
+        FailoverProvider<RetryClient<ThrottledProvider<Transport>>>,
+    >,
+>;
+
+#[cfg(not(feature = "record-replay"))]
+type ServiceProvider = Provider<ServiceClient>;
+
+#[cfg(feature = "record-replay")]
+type ServiceProvider = Provider<RecordingProvider<ServiceClient>>;
+
+/// Name the service reports its traces under in Datadog.
+const SERVICE_NAME: &str = "tree-availability-service";
+
+/// Maximum time to wait on shutdown for the indexer tasks to finish the sync in progress.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of times a dropped WebSocket connection is re-established before requests fail.
+const WS_RECONNECTS: usize = 10;
+
+/// Connects to an RPC endpoint over WebSocket for `ws` and `wss` URLs, and over HTTP otherwise.
+async fn connect(
+    rpc_endpoint: Url,
+    config: &ProviderConfig,
+) -> eyre::Result<Transport> {
+    if matches!(rpc_endpoint.scheme(), "ws" | "wss") {
+        if !config.headers.is_empty() {
+            eyre::bail!("provider.headers requires an http(s) endpoint");
+        }
+
+        let ws =
+            Ws::connect_with_reconnects(rpc_endpoint.as_str(), WS_RECONNECTS)
+                .await?;
+
+        return Ok(ws.into());
+    }
+
+    // Configured headers are sent with every request, keeping credentials out of the endpoint URL
+    let client = ethers_reqwest::Client::builder()
+        .default_headers(ethers_reqwest::header::HeaderMap::try_from(
+            &config.headers,
+        )?)
+        .build()?;
+
+    Ok(Http::new_with_client(rpc_endpoint, client).into())
+}
+
+/// Builds the throttled, retrying provider stack over the transports of the endpoints failed over to in order, returning the provider along with the rate limiter of the first endpoint and the circuit breaker shared by its requests.
+fn build_provider(
+    transports: Vec<Transport>,
+    config: &ProviderConfig,
+) -> eyre::Result<(ServiceProvider, Arc<Throttle>, Arc<CircuitBreaker>)> {
+    // A direct provider keeps the layers of the stack but configures them to pass requests through, so that failures surface immediately
+    if config.direct {
+        tracing::info!("Bypassing throttling, retries and circuit breaker");
+    }
+
+    let (requests_per_second, jitter) = if config.direct {
+        (u32::MAX, None)
+    } else {
+        (
+            config.throttle.unwrap_or(u32::MAX),
+            Some(Jitter::new(
+                Duration::from_millis(50),
+                Duration::from_millis(500),
+            )),
+        )
+    };
+    let (rate_limit_retries, timeout_retries) = if config.direct {
+        (0, 0)
+    } else {
+        (
+            config.retry.rate_limit_retries,
+            config.retry.timeout_retries,
+        )
+    };
+    let retry_policy =
+        CustomRetryPolicy::new(config.retry.retryable_error_codes.clone());
+
+    // Each endpoint is throttled and retried on its own, so that the failover only kicks in once retries against an endpoint are exhausted
+    let mut throttles = Vec::with_capacity(transports.len());
+    let retry_providers = transports
+        .into_iter()
+        .map(|transport| {
+            let throttled_provider =
+                ThrottledProvider::new(transport, requests_per_second, jitter);
+            throttles.push(throttled_provider.throttle());
+
+            RetryClientBuilder::default()
+                .rate_limit_retries(rate_limit_retries)
+                .timeout_retries(timeout_retries)
+                .initial_backoff(Duration::from_millis(
+                    config.retry.initial_backoff_ms,
+                ))
+                .build(throttled_provider, Box::new(retry_policy.clone()))
+        })
+        .collect();
+    let throttle = throttles.swap_remove(0);
+
+    let failover_provider =
+        FailoverProvider::new(retry_providers, move |err| {
+            should_failover(&retry_policy, err)
+        });
+
+    let retry_budget_provider = RetryBudgetProvider::new(
+        failover_provider,
+        Duration::from_millis(config.retry_budget_ms),
     );
-    let retry_provider = RetryClientBuilder::default()
-        .rate_limit_retries(10)
-        .timeout_retries(3)
-        .initial_backoff(Duration::from_millis(500))
-        .build(throttled_http_provider, Box::from(CustomRetryPolicy));
 
-    let middleware = Arc::new(Provider::new(retry_provider));
+    // The circuit never opens for a direct provider
+    let failure_threshold = if config.direct {
+        u32::MAX
+    } else {
+        config.circuit_breaker.failure_threshold
+    };
+    let circuit_breaker_provider = CircuitBreakerProvider::new(
+        retry_budget_provider,
+        failure_threshold,
+        Duration::from_millis(config.circuit_breaker.cool_down_ms),
+    );
+    let circuit_breaker = circuit_breaker_provider.breaker();
 
-    let handles = TreeAvailabilityService::new(
-        config.world_tree.tree_depth,
-        config.world_tree.dense_prefix_depth,
-        config.world_tree.tree_history_size,
+    #[cfg(feature = "record-replay")]
+    let client = match &config.record_path {
+        Some(record_path) => {
+            tracing::info!(?record_path, "Recording provider responses");
+            RecordingProvider::new(circuit_breaker_provider, record_path)?
+        }
+        None => RecordingProvider::passthrough(circuit_breaker_provider),
+    };
+
+    #[cfg(not(feature = "record-replay"))]
+    let client = {
+        if config.record_path.is_some() {
+            eyre::bail!(
+                "provider.record_path requires the record-replay feature"
+            );
+        }
+        circuit_breaker_provider
+    };
+
+    Ok((Provider::new(client), throttle, circuit_breaker))
+}
+
+/// Rebuilds the tree from the leaves persisted in the database and verifies the rebuilt root against the onchain `latestRoot()` at the latest persisted batch before the tree is served.
+async fn rebuild_from_db<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    service: TreeAvailabilityService<M>,
+    db: &DatabaseConnection,
+    middleware: Arc<M>,
+) -> eyre::Result<TreeAvailabilityService<M>> {
+    let Some(block) = db::latest_batch_block(db).await? else {
+        tracing::info!("No batches persisted, syncing from chain");
+        return Ok(service);
+    };
+
+    let leaves = db::load_leaves(db).await?;
+    tracing::info!(?block, leaves = leaves.len(), "Rebuilding tree from db");
+
+    let service = service.with_restored_leaves(&leaves, block);
+    let root = service.world_tree.tree_data.read().await.tree.root();
+
+    preflight::verify_root(
+        middleware,
         config.world_tree.world_id_contract_address,
+        root,
+        block,
+    )
+    .await?;
+
+    Ok(service)
+}
+
+/// Validates the configuration against the provider without syncing the tree, for operators to check a config before a long sync. Returns an error if the provider does not serve the expected chain, if no contract is deployed at `world_id_contract_address`, if the chain head has not reached `creation_block` or if the deployed contract version differs from the configured one.
+async fn validate<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    middleware: Arc<M>,
+) -> eyre::Result<()> {
+    let address = config.world_tree.world_id_contract_address;
+
+    if let Some(expected_block) = &config.provider.expected_block {
+        preflight::verify_expected_block(middleware.as_ref(), expected_block)
+            .await?;
+    }
+
+    preflight::verify_contract_code(middleware.as_ref(), address).await?;
+
+    preflight::verify_creation_block(
+        middleware.as_ref(),
         config.world_tree.creation_block,
-        config.world_tree.window_size,
+        false,
+    )
+    .await?;
+
+    preflight::verify_contract_version(
         middleware,
+        address,
+        config.world_tree.contract_version,
     )
-        .serve(config.world_tree.socket_address);
+    .await?;
+
+    tracing::info!("Configuration is valid");
 
-    let mut handles = handles.into_iter().collect::<FuturesUnordered<_>>();
-    while let Some(result) = handles.next().await {
-        tracing::error!("TreeAvailabilityError: {:?}", result);
-        result??;
+    Ok(())
+}
+
+/// Restores a fresh tree from the snapshot at `path` and verifies it, for operators to check a snapshot before loading it into a live service. Returns an error if the snapshot does not match the configured tree, if its leaves do not hash to its root or if its root differs from the onchain `latestRoot()` at the block it was taken at.
+async fn verify_snapshot<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    middleware: Arc<M>,
+    path: &Path,
+) -> eyre::Result<()> {
+    let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+        config.world_tree.tree_depth,
+        config.world_tree.dense_prefix_depth,
+        &Hash::ZERO,
+    );
+    // Historical roots are not needed to verify the latest root
+    let world_tree_config = WorldTreeConfig {
+        tree_history_size: 0,
+        ..config.world_tree.clone()
+    };
+    let world_tree =
+        WorldTree::new(tree, &world_tree_config, middleware.clone());
+
+    world_tree.load_snapshot(path).await?;
+    let root = world_tree.verify_integrity().await?;
+
+    let block = world_tree
+        .tree_updater
+        .latest_synced_block
+        .load(Ordering::SeqCst);
+    preflight::verify_root(
+        middleware,
+        config.world_tree.world_id_contract_address,
+        root,
+        block,
+    )
+    .await?;
+
+    tracing::info!(?path, ?block, ?root, "Snapshot is valid");
+
+    Ok(())
+}
+
+/// Syncs a fresh tree from onchain events without persisting anything and compares its leaves against the leaves persisted in the database, reporting the first divergence.
+async fn diff_onchain<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    middleware: Arc<M>,
+    start: usize,
+    end: Option<usize>,
+) -> eyre::Result<()> {
+    let database_url = config.database.url().ok_or_else(|| {
+        eyre::eyre!("A database is required to diff against onchain state")
+    })?;
+    let db = Database::connect(database_url).await?;
+    let local_leaves = db::load_leaves(&db).await?;
+
+    let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+        config.world_tree.tree_depth,
+        config.world_tree.dense_prefix_depth,
+        &Hash::ZERO,
+    );
+    // Historical roots are not needed to compare leaves at the chain head
+    let world_tree_config = WorldTreeConfig {
+        tree_history_size: 0,
+        ..config.world_tree.clone()
+    };
+    let world_tree = WorldTree::new(tree, &world_tree_config, middleware);
+
+    tracing::info!("Syncing tree from onchain events");
+    world_tree
+        .tree_updater
+        .sync_to_head(&world_tree.tree_data, None)
+        .await
+        .map_err(|err| eyre::eyre!("Failed to sync tree: {err:?}"))?;
+
+    let end = end.unwrap_or(local_leaves.len());
+    let divergence = world_tree
+        .tree_data
+        .read()
+        .await
+        .first_divergence(&local_leaves, start..end);
+
+    match divergence {
+        Some(divergence) => {
+            println!("{}", serde_json::to_string_pretty(&divergence)?);
+            eyre::bail!(
+                "Local tree diverges from onchain state at leaf index {}",
+                divergence.index
+            );
+        }
+        None => {
+            tracing::info!(?start, ?end, "No divergence found");
+            Ok(())
+        }
+    }
+}
+
+/// Syncs a fresh tree to the chain head and prints the inclusion proof of `commitment` against the latest root, as served by `/inclusionProof`.
+///
+/// If `rebuild_from_db` is set, the tree is restored from the leaves persisted in the database and only the blocks after the latest persisted batch are synced.
+async fn proof<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    middleware: Arc<M>,
+    commitment: Hash,
+    rebuild_from_db: bool,
+) -> eyre::Result<()> {
+    let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+        config.world_tree.tree_depth,
+        config.world_tree.dense_prefix_depth,
+        &Hash::ZERO,
+    );
+    // Only the latest root is needed for a single proof
+    let world_tree_config = WorldTreeConfig {
+        tree_history_size: 0,
+        ..config.world_tree.clone()
+    };
+    let world_tree = WorldTree::new(tree, &world_tree_config, middleware);
+
+    if rebuild_from_db {
+        let database_url = config.database.url().ok_or_else(|| {
+            eyre::eyre!("A database is required to rebuild the tree")
+        })?;
+        let db = Database::connect(database_url).await?;
+
+        if let Some(block) = db::latest_batch_block(&db).await? {
+            let leaves = db::load_leaves(&db).await?;
+            tracing::info!(
+                ?block,
+                leaves = leaves.len(),
+                "Rebuilding tree from db"
+            );
+
+            let mut tree_data = world_tree.tree_data.write().await;
+            tree_data.restore_leaves(&leaves);
+            tree_data.latest_root_block = block;
+            world_tree.tree_updater.resume_from(block);
+        }
     }
 
-    shutdown_tracer_provider();
+    tracing::info!("Syncing tree to the chain head");
+    world_tree
+        .tree_updater
+        .sync_to_head(&world_tree.tree_data, None)
+        .await
+        .map_err(|err| eyre::eyre!("Failed to sync tree: {err:?}"))?;
+
+    let leaf = world_tree.tree_updater.leaf_transform.transform(commitment);
+    let inclusion_proof = world_tree
+        .tree_data
+        .read()
+        .await
+        .get_inclusion_proof(leaf, None)
+        .map_err(|err| eyre::eyre!("Failed to generate proof: {err:?}"))?
+        .ok_or_else(|| {
+            eyre::eyre!("Identity commitment {commitment} not found in tree")
+        })?;
+
+    println!("{}", serde_json::to_string_pretty(&inclusion_proof)?);
 
     Ok(())
 }
 
+/// Re-scans `from_block..=to_block` for claims and persists them to the database.
+async fn backfill_claims<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    middleware: Arc<M>,
+    from_block: u64,
+    to_block: Option<u64>,
+) -> eyre::Result<()> {
+    let claims = config.claims.as_ref().ok_or_else(|| {
+        eyre::eyre!("A claims config is required to backfill claims")
+    })?;
+    let database_url = config.database.url().ok_or_else(|| {
+        eyre::eyre!("A database is required to backfill claims")
+    })?;
+    let db = Database::connect(database_url).await?;
+
+    let to_block = match to_block {
+        Some(to_block) => to_block,
+        None => middleware.get_block_number().await?.as_u64(),
+    };
+
+    ClaimStorage::new(claims, middleware)
+        .claim_updater
+        .backfill(&db, from_block, to_block)
+        .await
+        .map_err(|err| eyre::eyre!("Failed to backfill claims: {err:?}"))
+}
+
+/// Syncs a fresh tree from the contract creation block up to `to_block`, persisting the indexed records to the database.
+///
+/// Progress is printed to stdout as NDJSON lines of `{processed_blocks, inserted, deleted, current_block}` every `progress_interval`, and once more when the backfill completes, so that it can be tracked by automation independently of the tracing output.
+async fn backfill<M: Middleware + 'static>(
+    config: &ServiceConfig,
+    middleware: Arc<M>,
+    to_block: Option<u64>,
+    progress_interval: Duration,
+) -> eyre::Result<()> {
+    let database_url = config.database.url().ok_or_else(|| {
+        eyre::eyre!("A database is required to backfill")
+    })?;
+    let db = Database::connect(database_url).await?;
+    let db_writer = DbWriter::spawn(db, config.database.write_retry);
+
+    let to_block = match to_block {
+        Some(to_block) => to_block,
+        None => middleware.get_block_number().await?.as_u64(),
+    };
+
+    let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+        config.world_tree.tree_depth,
+        config.world_tree.dense_prefix_depth,
+        &Hash::ZERO,
+    );
+    // Historical roots are not served while backfilling
+    let world_tree_config = WorldTreeConfig {
+        tree_history_size: 0,
+        ..config.world_tree.clone()
+    };
+    let world_tree = WorldTree::new(tree, &world_tree_config, middleware);
+    let tree_updater = world_tree.tree_updater.clone();
+    let creation_block = config.world_tree.creation_block;
+
+    let progress = move || {
+        let current_block =
+            tree_updater.latest_synced_block.load(Ordering::SeqCst);
+
+        BackfillProgress {
+            processed_blocks: current_block.saturating_sub(creation_block),
+            inserted: tree_updater.identities_inserted.load(Ordering::SeqCst),
+            deleted: tree_updater.identities_deleted.load(Ordering::SeqCst),
+            current_block,
+        }
+    };
+
+    let reporter = tokio::spawn({
+        let progress = progress.clone();
+        async move {
+            let mut interval = tokio::time::interval(progress_interval);
+            loop {
+                interval.tick().await;
+                print_progress(&progress());
+            }
+        }
+    });
+
+    tracing::info!(?creation_block, ?to_block, "Starting backfill");
+
+    // Sync one window at a time so that progress is reported as the logs are applied
+    let mut result = Ok(());
+    loop {
+        let last_synced_block = world_tree
+            .tree_updater
+            .latest_synced_block
+            .load(Ordering::SeqCst);
+        if last_synced_block >= to_block {
+            break;
+        }
+
+        let window_end =
+            (last_synced_block + config.world_tree.window_size).min(to_block);
+        if let Err(err) = world_tree
+            .tree_updater
+            .sync_to_block(&world_tree.tree_data, Some(&db_writer), window_end)
+            .await
+        {
+            result = Err(eyre::eyre!("Failed to backfill tree: {err:?}"));
+            break;
+        }
+    }
+
+    reporter.abort();
+    print_progress(&progress());
+
+    db_writer
+        .shutdown(Duration::from_millis(config.database.drain_timeout_ms))
+        .await;
+
+    result
+}
+
+/// Prints a backfill progress line to stdout.
+fn print_progress(progress: &BackfillProgress) {
+    match serde_json::to_string(progress) {
+        Ok(line) => println!("{line}"),
+        Err(err) => tracing::error!(?err, "Failed to serialize progress"),
+    }
+}
+
+/// Fails over to the next endpoint once retries against an endpoint are exhausted, unless the endpoint returned a JSON-RPC error response that `CustomRetryPolicy` does not classify as retryable, such as a size limit error. Such errors are caused by the request itself, so another endpoint would fail them as well. Timeouts, connection errors and malformed responses, e.g. the body of a 5xx response, fail over.
+fn should_failover(
+    retry_policy: &CustomRetryPolicy,
+    err: &RetryClientError,
+) -> bool {
+    match err {
+        RetryClientError::ProviderError(err) => {
+            err.as_error_response().map_or(true, |err| {
+                RetryPolicy::<HttpClientError>::should_retry(
+                    retry_policy,
+                    &HttpClientError::JsonRpcError(err.clone()),
+                )
+            })
+        }
+        RetryClientError::TimeoutError => true,
+        RetryClientError::SerdeJson(_) => false,
+    }
+}
 
 /// Implements [RetryPolicy] that will retry requests that errored with
 /// status code 429 i.e. TOO_MANY_REQUESTS
 ///
 /// Infura often fails with a `"header not found"` rpc error which is apparently linked to load
 /// balancing, which are retried as well.
-#[derive(Debug, Default)]
-pub struct CustomRetryPolicy;
+#[derive(Debug, Default, Clone)]
+pub struct CustomRetryPolicy {
+    /// JSON-RPC error codes retried in addition to the built-in ones
+    retryable_error_codes: Vec<i64>,
+}
+
+impl CustomRetryPolicy {
+    pub fn new(retryable_error_codes: Vec<i64>) -> Self {
+        Self {
+            retryable_error_codes,
+        }
+    }
+}
 
 impl RetryPolicy<HttpClientError> for CustomRetryPolicy {
     fn should_retry(&self, error: &HttpClientError) -> bool {
-        fn should_retry_json_rpc_error(err: &JsonRpcError) -> bool {
+        let should_retry_json_rpc_error = |err: &JsonRpcError| -> bool {
             let JsonRpcError { code, message, .. } = err;
             // alchemy throws it this way
             if *code == 429 {
                 return true
             }
 
+            // Size limit errors reach the block scanner unchanged, which retries them over a smaller window. Some providers return them with a code that is otherwise retried
+            if is_size_limit_response(err) {
+                return false
+            }
+
+            if self.retryable_error_codes.contains(code) {
+                return true
+            }
+
             if *code == -32603 {
                 return true
             }
@@ -118,7 +1047,7 @@ impl RetryPolicy<HttpClientError> for CustomRetryPolicy {
                 "daily request count exceeded, request rate limited" => true,
                 _ => false,
             }
-        }
+        };
 
         match error {
             HttpClientError::ReqwestError(err) => {
@@ -160,3 +1089,136 @@ impl RetryPolicy<HttpClientError> for CustomRetryPolicy {
         None
     }
 }
+
+impl RetryPolicy<TransportError> for CustomRetryPolicy {
+    fn should_retry(&self, error: &TransportError) -> bool {
+        match error {
+            TransportError::Http(err) => {
+                RetryPolicy::<HttpClientError>::should_retry(self, err)
+            }
+            // Errors returned over WebSocket are retried on the same JSON-RPC errors as over HTTP
+            TransportError::Ws(err) => {
+                err.as_error_response().is_some_and(|err| {
+                    RetryPolicy::<HttpClientError>::should_retry(
+                        self,
+                        &HttpClientError::JsonRpcError(err.clone()),
+                    )
+                })
+            }
+        }
+    }
+
+    fn backoff_hint(&self, error: &TransportError) -> Option<Duration> {
+        match error {
+            TransportError::Http(err) => {
+                RetryPolicy::<HttpClientError>::backoff_hint(self, err)
+            }
+            TransportError::Ws(err) => {
+                let err = err.as_error_response()?.clone();
+                RetryPolicy::<HttpClientError>::backoff_hint(
+                    self,
+                    &HttpClientError::JsonRpcError(err),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use async_trait::async_trait;
+    use ethers::providers::JsonRpcClient;
+    use ethers::types::U64;
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    fn size_limit_error() -> JsonRpcError {
+        JsonRpcError {
+            code: -32005,
+            message: "query returned more than 10000 results".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Client failing every request with a size limit error, counting the requests it receives.
+    #[derive(Debug)]
+    struct SizeLimitClient {
+        requests: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for SizeLimitClient {
+        type Error = HttpClientError;
+
+        async fn request<T, R>(
+            &self,
+            _method: &str,
+            _params: T,
+        ) -> Result<R, Self::Error>
+        where
+            T: std::fmt::Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+
+            Err(HttpClientError::JsonRpcError(size_limit_error()))
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_error_is_retried() {
+        let err = HttpClientError::JsonRpcError(JsonRpcError {
+            code: -32005,
+            message: "Too many requests, rate limit exceeded".to_owned(),
+            data: None,
+        });
+
+        assert!(RetryPolicy::<HttpClientError>::should_retry(
+            &CustomRetryPolicy::default(),
+            &err
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_size_limit_error_passes_through() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let retry_policy = CustomRetryPolicy::default();
+
+        let retry_providers = (0..2)
+            .map(|_| {
+                RetryClientBuilder::default()
+                    .rate_limit_retries(3)
+                    .timeout_retries(3)
+                    .initial_backoff(Duration::from_millis(1))
+                    .build(
+                        SizeLimitClient {
+                            requests: requests.clone(),
+                        },
+                        Box::new(retry_policy.clone()),
+                    )
+            })
+            .collect();
+        let failover_provider =
+            FailoverProvider::new(retry_providers, move |err| {
+                should_failover(&retry_policy, err)
+            });
+        let provider = RetryBudgetProvider::new(
+            failover_provider,
+            Duration::from_secs(10),
+        );
+
+        let err = provider
+            .request::<_, U64>("eth_getLogs", ())
+            .await
+            .unwrap_err();
+        let response = err.as_error_response().unwrap();
+        assert_eq!(response.code, size_limit_error().code);
+        assert_eq!(response.message, size_limit_error().message);
+
+        // The error is neither retried nor failed over
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
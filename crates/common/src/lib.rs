@@ -1,2 +1,3 @@
 pub mod test_utilities;
+pub mod tracing;
 pub use opentelemetry::global::shutdown_tracer_provider;
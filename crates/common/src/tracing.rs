@@ -0,0 +1,51 @@
+use opentelemetry::global::shutdown_tracer_provider;
+use opentelemetry_datadog::ApiVersion;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Endpoint of the local Datadog agent receiving traces
+pub const DATADOG_AGENT_ENDPOINT: &str = "http://127.0.0.1:8126";
+
+/// Flushes the spans buffered by the tracer provider when dropped. Must be held until the service exits, so that spans recorded during shutdown are exported.
+#[must_use = "spans are flushed when the handle is dropped"]
+pub struct TracingShutdownHandle;
+
+impl Drop for TracingShutdownHandle {
+    fn drop(&mut self) {
+        shutdown_tracer_provider();
+    }
+}
+
+/// Installs a global subscriber writing formatted traces to stdout.
+pub fn init_stdout_subscriber() -> eyre::Result<TracingShutdownHandle> {
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .try_init()?;
+
+    Ok(TracingShutdownHandle)
+}
+
+/// Installs a global subscriber exporting spans to the local Datadog agent and writing JSON formatted traces to stdout.
+///
+/// # Arguments
+///
+/// * `service_name` - Name the spans are reported under in Datadog.
+pub fn init_datadog_subscriber(
+    service_name: &str,
+) -> eyre::Result<TracingShutdownHandle> {
+    let tracer = opentelemetry_datadog::new_pipeline()
+        .with_service_name(service_name)
+        .with_api_version(ApiVersion::Version05)
+        .with_agent_endpoint(DATADOG_AGENT_ENDPOINT)
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .with(tracing_subscriber::fmt::layer().json())
+        .try_init()?;
+
+    Ok(TracingShutdownHandle)
+}
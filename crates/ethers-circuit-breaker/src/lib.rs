@@ -0,0 +1,365 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent to the provider
+    Closed,
+    /// Requests fail fast without calling the provider
+    Open,
+    /// A single trial request is sent to test whether the provider recovered
+    HalfOpen,
+}
+
+impl CircuitState {
+    fn as_gauge(&self) -> f64 {
+        match self {
+            CircuitState::Closed => 0.0,
+            CircuitState::HalfOpen => 1.0,
+            CircuitState::Open => 2.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+/// Tracks consecutive provider failures. After `failure_threshold` consecutive failures the circuit opens and requests fail fast for `cool_down`, after which a single trial request is let through to test recovery.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    inner: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cool_down: Duration) -> Self {
+        metrics::gauge!(
+            "provider.circuit_breaker.state",
+            CircuitState::Closed.as_gauge()
+        );
+
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            cool_down,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                trial_in_flight: false,
+            }),
+        }
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    /// Returns whether a request may be sent to the provider, transitioning an open circuit to half-open once the cool-down has elapsed.
+    fn try_acquire(&self) -> bool {
+        let mut inner = self.lock();
+
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => {
+                let cooled_down = match inner.opened_at {
+                    Some(opened_at) => opened_at.elapsed() >= self.cool_down,
+                    None => true,
+                };
+
+                if cooled_down {
+                    tracing::info!(
+                        "Circuit half-open, testing provider recovery"
+                    );
+                    Self::transition(&mut inner, CircuitState::HalfOpen);
+                    inner.trial_in_flight = true;
+                }
+
+                cooled_down
+            }
+            CircuitState::HalfOpen => {
+                if inner.trial_in_flight {
+                    false
+                } else {
+                    inner.trial_in_flight = true;
+                    true
+                }
+            }
+        }
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.lock();
+
+        inner.consecutive_failures = 0;
+        inner.trial_in_flight = false;
+
+        if inner.state != CircuitState::Closed {
+            tracing::info!("Provider recovered, circuit closed");
+            Self::transition(&mut inner, CircuitState::Closed);
+        }
+    }
+
+    fn record_failure(&self) {
+        let mut inner = self.lock();
+
+        inner.consecutive_failures += 1;
+        inner.trial_in_flight = false;
+        metrics::increment_counter!("provider.circuit_breaker.failure");
+
+        let should_open = inner.state == CircuitState::HalfOpen
+            || inner.consecutive_failures >= self.failure_threshold;
+
+        if should_open {
+            if inner.state != CircuitState::Open {
+                tracing::warn!(
+                    consecutive_failures = inner.consecutive_failures,
+                    cool_down = ?self.cool_down,
+                    "Circuit opened"
+                );
+                metrics::increment_counter!("provider.circuit_breaker.opened");
+            }
+
+            Self::transition(&mut inner, CircuitState::Open);
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Frees the trial slot of a half-open circuit held by a request that was dropped before completing, so that a later request can test the provider instead. The abandoned request tells nothing about the provider, so it is counted as neither a success nor a failure.
+    fn release_trial(&self) {
+        let mut inner = self.lock();
+
+        if inner.state == CircuitState::HalfOpen {
+            inner.trial_in_flight = false;
+        }
+    }
+
+    fn transition(inner: &mut BreakerState, state: CircuitState) {
+        inner.state = state;
+        metrics::gauge!("provider.circuit_breaker.state", state.as_gauge());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.inner.lock().expect("Circuit breaker lock poisoned")
+    }
+}
+
+/// Releases the trial slot of a half-open circuit when dropped before `complete` is called, e.g. when the caller of a request times out or disconnects. Otherwise the circuit would stay half-open and reject every request.
+struct TrialGuard<'a> {
+    breaker: &'a CircuitBreaker,
+    completed: bool,
+}
+
+impl TrialGuard<'_> {
+    fn complete(mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for TrialGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.breaker.release_trial();
+        }
+    }
+}
+
+/// Wraps a provider in a `CircuitBreaker`, failing fast while the circuit is open. Only transport failures count towards opening the circuit, since JSON-RPC error responses indicate the provider is reachable.
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerProvider<P: JsonRpcClient> {
+    breaker: Arc<CircuitBreaker>,
+    inner: P,
+}
+
+impl<P: JsonRpcClient> CircuitBreakerProvider<P> {
+    pub fn new(
+        provider: P,
+        failure_threshold: u32,
+        cool_down: Duration,
+    ) -> Self {
+        CircuitBreakerProvider {
+            breaker: Arc::new(CircuitBreaker::new(
+                failure_threshold,
+                cool_down,
+            )),
+            inner: provider,
+        }
+    }
+
+    /// Returns the circuit breaker shared by all requests sent through this provider.
+    pub fn breaker(&self) -> Arc<CircuitBreaker> {
+        self.breaker.clone()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CircuitBreakerError<E> {
+    #[error(transparent)]
+    Inner(E),
+    #[error("Circuit open, provider request rejected")]
+    CircuitOpen,
+}
+
+impl<E: RpcError> RpcError for CircuitBreakerError<E> {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            CircuitBreakerError::Inner(err) => err.as_error_response(),
+            CircuitBreakerError::CircuitOpen => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            CircuitBreakerError::Inner(err) => err.as_serde_error(),
+            CircuitBreakerError::CircuitOpen => None,
+        }
+    }
+}
+
+impl<E: RpcError + 'static> From<CircuitBreakerError<E>> for ProviderError {
+    fn from(err: CircuitBreakerError<E>) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> JsonRpcClient for CircuitBreakerProvider<P>
+where
+    P::Error: 'static,
+{
+    type Error = CircuitBreakerError<P::Error>;
+
+    /// Sends a request with the provided JSON-RPC and parameters serialized as JSON, failing fast while the circuit is open
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if !self.breaker.try_acquire() {
+            metrics::increment_counter!("provider.circuit_breaker.rejected");
+            return Err(CircuitBreakerError::CircuitOpen);
+        }
+
+        let guard = TrialGuard {
+            breaker: &self.breaker,
+            completed: false,
+        };
+        let result = self.inner.request(method, params).await;
+        guard.complete();
+
+        match result {
+            Ok(response) => {
+                self.breaker.record_success();
+                Ok(response)
+            }
+            Err(err) => {
+                if err.as_error_response().is_some() {
+                    self.breaker.record_success();
+                } else {
+                    self.breaker.record_failure();
+                }
+
+                Err(CircuitBreakerError::Inner(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::U64;
+
+    use super::*;
+
+    /// Client whose requests never complete.
+    #[derive(Debug)]
+    struct PendingClient;
+
+    #[async_trait]
+    impl JsonRpcClient for PendingClient {
+        type Error = ProviderError;
+
+        async fn request<T, R>(
+            &self,
+            _method: &str,
+            _params: T,
+        ) -> Result<R, Self::Error>
+        where
+            T: std::fmt::Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            std::future::pending().await
+        }
+    }
+
+    #[test]
+    fn test_circuit_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::ZERO);
+
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // The cool-down has elapsed, so a single trial request is let through
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(!breaker.try_acquire());
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_failed_trial_reopens_circuit() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure();
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_trial_releases_circuit() {
+        let provider =
+            CircuitBreakerProvider::new(PendingClient, 1, Duration::ZERO);
+        provider.breaker.record_failure();
+        assert_eq!(provider.breaker.state(), CircuitState::Open);
+
+        // The trial request is abandoned by its caller
+        let trial = provider.request::<_, U64>("eth_blockNumber", ());
+        assert!(tokio::time::timeout(Duration::from_millis(10), trial)
+            .await
+            .is_err());
+        assert_eq!(provider.breaker.state(), CircuitState::HalfOpen);
+
+        // Another request may test the provider instead
+        assert!(provider.breaker.try_acquire());
+    }
+}
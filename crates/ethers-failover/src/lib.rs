@@ -0,0 +1,183 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Sends requests to the current provider of an ordered list, failing over to the next provider when a request fails with an error classified by `should_failover`.
+///
+/// The current provider is sticky: once a request fails over, subsequent requests are sent to the new provider until it fails in turn, wrapping around to the first provider after the last one. A single request tries each provider at most once.
+pub struct FailoverProvider<P: JsonRpcClient> {
+    providers: Vec<P>,
+    current: AtomicUsize,
+    should_failover: Box<dyn Fn(&P::Error) -> bool + Send + Sync>,
+}
+
+impl<P: JsonRpcClient> FailoverProvider<P> {
+    /// # Panics
+    ///
+    /// Panics if `providers` is empty.
+    pub fn new(
+        providers: Vec<P>,
+        should_failover: impl Fn(&P::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        assert!(!providers.is_empty(), "At least one provider is required");
+
+        FailoverProvider {
+            providers,
+            current: AtomicUsize::new(0),
+            should_failover: Box::new(should_failover),
+        }
+    }
+
+    /// Index of the provider requests are currently sent to.
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::SeqCst)
+    }
+}
+
+impl<P: JsonRpcClient> std::fmt::Debug for FailoverProvider<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailoverProvider")
+            .field("providers", &self.providers)
+            .field("current", &self.current)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FailoverError<E> {
+    #[error(transparent)]
+    Inner(E),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+}
+
+impl<E: RpcError> RpcError for FailoverError<E> {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FailoverError::Inner(err) => err.as_error_response(),
+            FailoverError::SerdeJson(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FailoverError::Inner(err) => err.as_serde_error(),
+            FailoverError::SerdeJson(err) => Some(err),
+        }
+    }
+}
+
+impl<E: RpcError + 'static> From<FailoverError<E>> for ProviderError {
+    fn from(err: FailoverError<E>) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> JsonRpcClient for FailoverProvider<P>
+where
+    P::Error: 'static,
+{
+    type Error = FailoverError<P::Error>;
+
+    /// Sends a request with the provided JSON-RPC and parameters serialized as JSON, failing over to the next provider on errors classified by `should_failover`
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // Parameters are serialized once, so that they can be re-sent to each provider
+        let params = serde_json::to_value(params)?;
+
+        let start = self.current();
+        let mut index = start;
+
+        loop {
+            let err = match self.providers[index]
+                .request(method, params.clone())
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+
+            let next = (index + 1) % self.providers.len();
+            if next == start || !(self.should_failover)(&err) {
+                return Err(FailoverError::Inner(err));
+            }
+
+            tracing::warn!(
+                ?err,
+                from = index,
+                to = next,
+                "Failing over to the next provider"
+            );
+            metrics::increment_counter!("provider.failover");
+
+            // Concurrent requests may already have failed over
+            let _ = self.current.compare_exchange(
+                index,
+                next,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+            index = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::MockProvider;
+    use ethers::types::U64;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fails_over_to_next_provider() {
+        // The first provider has no response queued, so its requests fail
+        let failing = MockProvider::new();
+        let healthy = MockProvider::new();
+        healthy.push(U64::from(1)).unwrap();
+        healthy.push(U64::from(2)).unwrap();
+
+        let provider = FailoverProvider::new(vec![failing, healthy], |_| true);
+
+        let block: U64 = provider.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block, U64::from(1));
+        assert_eq!(provider.current(), 1);
+
+        // Subsequent requests stick to the provider failed over to
+        let block: U64 = provider.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block, U64::from(2));
+
+        // Once every provider failed, the last error is returned
+        assert!(provider
+            .request::<_, U64>("eth_blockNumber", ())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unclassified_errors_do_not_fail_over() {
+        let failing = MockProvider::new();
+        let healthy = MockProvider::new();
+        healthy.push(U64::from(1)).unwrap();
+
+        let provider = FailoverProvider::new(vec![failing, healthy], |_| false);
+
+        assert!(provider
+            .request::<_, U64>("eth_blockNumber", ())
+            .await
+            .is_err());
+        assert_eq!(provider.current(), 0);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// A JSON-RPC request along with the response it received, recorded as a single line of the recording file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub params: Value,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// Response to a recorded request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Result(Value),
+    Error(JsonRpcError),
+}
+
+/// Records every request sent through the inner provider along with its response, one JSON object per line, so that the session can later be replayed offline with a `ReplayProvider`.
+///
+/// Failures that are not JSON-RPC error responses (e.g. transport errors) are not recorded, since they are not part of the chain state being captured.
+#[derive(Debug)]
+pub struct RecordingProvider<P: JsonRpcClient> {
+    inner: P,
+    /// Recording file. If `None`, requests are passed through without being recorded.
+    writer: Option<Mutex<BufWriter<File>>>,
+}
+
+impl<P: JsonRpcClient> RecordingProvider<P> {
+    /// Initializes a new `RecordingProvider`, truncating the recording file if it already exists.
+    pub fn new(provider: P, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+
+        Ok(RecordingProvider {
+            inner: provider,
+            writer: Some(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// Initializes a `RecordingProvider` passing requests through without recording them.
+    pub fn passthrough(provider: P) -> Self {
+        RecordingProvider {
+            inner: provider,
+            writer: None,
+        }
+    }
+
+    fn record(&self, interaction: &Interaction) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+
+        let mut writer = writer.lock().expect("Recording lock poisoned");
+
+        let result = serde_json::to_writer(&mut *writer, interaction)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writer.write_all(b"\n"))
+            .and_then(|()| writer.flush());
+
+        if let Err(err) = result {
+            tracing::error!(
+                ?err,
+                method = %interaction.method,
+                "Failed to record interaction"
+            );
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RecordingError<E> {
+    #[error(transparent)]
+    Inner(E),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+}
+
+impl<E: RpcError> RpcError for RecordingError<E> {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RecordingError::Inner(err) => err.as_error_response(),
+            RecordingError::SerdeJson(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RecordingError::Inner(err) => err.as_serde_error(),
+            RecordingError::SerdeJson(err) => Some(err),
+        }
+    }
+}
+
+impl<E: RpcError + 'static> From<RecordingError<E>> for ProviderError {
+    fn from(err: RecordingError<E>) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> JsonRpcClient for RecordingProvider<P>
+where
+    P::Error: 'static,
+{
+    type Error = RecordingError<P::Error>;
+
+    /// Sends a request through the inner provider and records it along with its response
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+
+        match self.inner.request::<_, Value>(method, &params).await {
+            Ok(result) => {
+                self.record(&Interaction {
+                    method: method.to_string(),
+                    params,
+                    outcome: Outcome::Result(result.clone()),
+                });
+
+                Ok(serde_json::from_value(result)?)
+            }
+            Err(err) => {
+                if let Some(error) = err.as_error_response() {
+                    self.record(&Interaction {
+                        method: method.to_string(),
+                        params,
+                        outcome: Outcome::Error(error.clone()),
+                    });
+                }
+
+                Err(RecordingError::Inner(err))
+            }
+        }
+    }
+}
+
+/// Serves the responses recorded by a `RecordingProvider` without a live provider, so that a sync can be reproduced deterministically in tests.
+///
+/// Requests are matched by method and parameters. Identical requests are answered with their recorded responses in the order they were recorded.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    interactions: Mutex<HashMap<(String, String), VecDeque<Outcome>>>,
+}
+
+impl ReplayProvider {
+    /// Loads the interactions recorded at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+
+        let mut interactions: HashMap<_, VecDeque<_>> = HashMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let interaction: Interaction = serde_json::from_str(&line)?;
+            let key = (interaction.method, interaction.params.to_string());
+            interactions
+                .entry(key)
+                .or_default()
+                .push_back(interaction.outcome);
+        }
+
+        Ok(ReplayProvider {
+            interactions: Mutex::new(interactions),
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error(transparent)]
+    JsonRpcError(JsonRpcError),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("No recorded response left for {method} with params {params}")]
+    MissingInteraction { method: String, params: String },
+}
+
+impl RpcError for ReplayError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            ReplayError::JsonRpcError(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            ReplayError::SerdeJson(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReplayError> for ProviderError {
+    fn from(err: ReplayError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for ReplayProvider {
+    type Error = ReplayError;
+
+    /// Answers the request with the next recorded response to the same method and parameters
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let key = (
+            method.to_string(),
+            serde_json::to_value(params)?.to_string(),
+        );
+
+        let outcome = self
+            .interactions
+            .lock()
+            .expect("Replay lock poisoned")
+            .get_mut(&key)
+            .and_then(VecDeque::pop_front);
+
+        match outcome {
+            Some(Outcome::Result(result)) => {
+                Ok(serde_json::from_value(result)?)
+            }
+            Some(Outcome::Error(err)) => Err(ReplayError::JsonRpcError(err)),
+            None => Err(ReplayError::MissingInteraction {
+                method: key.0,
+                params: key.1,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::MockProvider;
+    use ethers::types::U64;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replays_recorded_responses() {
+        let path = std::env::temp_dir().join(format!(
+            "ethers-record-replay-{}.ndjson",
+            std::process::id()
+        ));
+
+        let mock = MockProvider::new();
+        mock.push(U64::from(2)).unwrap();
+        mock.push(U64::from(1)).unwrap();
+
+        let recorder = RecordingProvider::new(mock, &path).unwrap();
+        let first: U64 = recorder.request("eth_blockNumber", ()).await.unwrap();
+        let second: U64 =
+            recorder.request("eth_blockNumber", ()).await.unwrap();
+        drop(recorder);
+
+        let replay = ReplayProvider::from_file(&path).unwrap();
+        let replayed: U64 =
+            replay.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(replayed, first);
+        let replayed: U64 =
+            replay.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(replayed, second);
+
+        // Every recorded response has been replayed
+        let err = replay
+            .request::<_, U64>("eth_blockNumber", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ReplayError::MissingInteraction { .. }));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
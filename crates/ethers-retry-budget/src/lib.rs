@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Bounds the total time spent on a single request, including every retry and rate limit wait performed by the inner provider layers.
+///
+/// Retry layers compound, so without a shared budget a provider outage can turn a single request into a minutes-long hang. Wrapping the outermost layer of the provider stack in a `RetryBudgetProvider` caps the time spent regardless of how many layers retry beneath it.
+#[derive(Clone, Debug)]
+pub struct RetryBudgetProvider<P: JsonRpcClient> {
+    budget: Duration,
+    inner: P,
+}
+
+impl<P: JsonRpcClient> RetryBudgetProvider<P> {
+    pub fn new(provider: P, budget: Duration) -> Self {
+        RetryBudgetProvider {
+            budget,
+            inner: provider,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RetryBudgetError<E> {
+    #[error(transparent)]
+    Inner(E),
+    #[error("Retry budget of {0:?} exhausted")]
+    BudgetExhausted(Duration),
+}
+
+impl<E: RpcError> RpcError for RetryBudgetError<E> {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RetryBudgetError::Inner(err) => err.as_error_response(),
+            RetryBudgetError::BudgetExhausted(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RetryBudgetError::Inner(err) => err.as_serde_error(),
+            RetryBudgetError::BudgetExhausted(_) => None,
+        }
+    }
+}
+
+impl<E: RpcError + 'static> From<RetryBudgetError<E>> for ProviderError {
+    fn from(err: RetryBudgetError<E>) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> JsonRpcClient for RetryBudgetProvider<P>
+where
+    P::Error: 'static,
+{
+    type Error = RetryBudgetError<P::Error>;
+
+    /// Sends a request with the provided JSON-RPC and parameters serialized as JSON, failing once the retry budget is exhausted
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        tokio::time::timeout(self.budget, self.inner.request(method, params))
+            .await
+            .map_err(|_| RetryBudgetError::BudgetExhausted(self.budget))?
+            .map_err(RetryBudgetError::Inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::{MockProvider, MockResponse};
+    use ethers::types::U64;
+
+    use super::*;
+
+    /// Client whose requests never complete.
+    #[derive(Debug)]
+    struct PendingClient;
+
+    #[async_trait]
+    impl JsonRpcClient for PendingClient {
+        type Error = ProviderError;
+
+        async fn request<T, R>(
+            &self,
+            _method: &str,
+            _params: T,
+        ) -> Result<R, Self::Error>
+        where
+            T: std::fmt::Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_exhausts_budget() {
+        let budget = Duration::from_millis(10);
+        let provider = RetryBudgetProvider::new(PendingClient, budget);
+
+        let err = provider
+            .request::<_, U64>("eth_blockNumber", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RetryBudgetError::BudgetExhausted(exhausted) if exhausted == budget
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_request_within_budget_passes_through() {
+        let mock = MockProvider::new();
+        let provider =
+            RetryBudgetProvider::new(mock.clone(), Duration::from_secs(10));
+
+        mock.push(U64::from(1)).unwrap();
+        let block: U64 = provider.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block, U64::from(1));
+
+        // Errors of the inner provider are returned unchanged
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32005,
+            message: "query returned more than 10000 results".to_owned(),
+            data: None,
+        }));
+        let err = provider
+            .request::<_, U64>("eth_getLogs", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RetryBudgetError::Inner(_)));
+
+        let response = err.as_error_response().unwrap();
+        assert_eq!(response.code, -32005);
+        assert_eq!(response.message, "query returned more than 10000 results");
+    }
+}
@@ -41,6 +41,11 @@ impl<P: JsonRpcClient> ThrottledProvider<P> {
             inner: provider,
         }
     }
+
+    /// Returns the rate limiter shared by all requests sent through this provider.
+    pub fn throttle(&self) -> Arc<Throttle> {
+        self.throttle.clone()
+    }
 }
 
 #[async_trait]
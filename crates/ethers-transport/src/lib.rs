@@ -0,0 +1,81 @@
+use async_trait::async_trait;
+use ethers::providers::{
+    Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError,
+    RpcError, Ws, WsClientError,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+/// JSON-RPC transport over either HTTP or WebSocket, so that a single provider stack can be built for both kinds of endpoints.
+#[derive(Clone, Debug)]
+pub enum Transport {
+    Http(Http),
+    Ws(Ws),
+}
+
+impl From<Http> for Transport {
+    fn from(http: Http) -> Self {
+        Transport::Http(http)
+    }
+}
+
+impl From<Ws> for Transport {
+    fn from(ws: Ws) -> Self {
+        Transport::Ws(ws)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TransportError {
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    #[error(transparent)]
+    Ws(#[from] WsClientError),
+}
+
+impl RpcError for TransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            TransportError::Http(err) => err.as_error_response(),
+            TransportError::Ws(err) => err.as_error_response(),
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            TransportError::Http(err) => err.as_serde_error(),
+            TransportError::Ws(err) => err.as_serde_error(),
+        }
+    }
+}
+
+impl From<TransportError> for ProviderError {
+    fn from(err: TransportError) -> Self {
+        match err {
+            TransportError::Http(err) => err.into(),
+            TransportError::Ws(err) => err.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for Transport {
+    type Error = TransportError;
+
+    /// Sends a request with the provided JSON-RPC and parameters serialized as JSON over the underlying transport
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Transport::Http(http) => Ok(http.request(method, params).await?),
+            Transport::Ws(ws) => Ok(ws.request(method, params).await?),
+        }
+    }
+}
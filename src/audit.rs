@@ -0,0 +1,256 @@
+//! Append-only audit log recording every root observed and every batch applied, independent of the tracing subscriber.
+//!
+//! Records are written as JSON lines to the configured file by a dedicated blocking task, so that file I/O never stalls tree syncing. Events are recorded through an [`AuditLog`] handle.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use ethers::types::{H256, U256};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditLogConfig {
+    /// Path of the JSONL file to append audit records to
+    pub path: PathBuf,
+    /// Whether to fsync the file after every record
+    #[serde(default)]
+    pub fsync: bool,
+}
+
+/// An event recorded in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A `TreeChanged` event was observed onchain
+    RootObserved {
+        pre_root: U256,
+        post_root: U256,
+        block_number: Option<u64>,
+        tx_hash: Option<H256>,
+    },
+    /// A batch of insertions or deletions was applied to the tree
+    BatchApplied {
+        kind: BatchKind,
+        pre_root: U256,
+        post_root: U256,
+        batch_size: usize,
+        block_number: Option<u64>,
+        tx_hash: H256,
+    },
+    /// Synced blocks were reorged out and the tree was rolled back to the fork point
+    ReorgDetected {
+        previous_tip: u64,
+        fork_block: Option<u64>,
+        depth: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchKind {
+    Insertion,
+    Deletion,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord {
+    timestamp: u64,
+    #[serde(flatten)]
+    event: AuditEvent,
+}
+
+/// Messages consumed by the audit log writer task.
+enum AuditMessage {
+    Record(AuditRecord),
+    /// Acknowledged once every record queued before it has been written
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle to the audit log, queueing records to be appended by the writer task. The writer task stops once every handle is dropped.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: mpsc::UnboundedSender<AuditMessage>,
+}
+
+impl AuditLog {
+    /// Opens the audit log file for appending and spawns the writer task appending records to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Path of the audit log and whether to fsync it after every record.
+    pub fn open(config: &AuditLogConfig) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let fsync = config.fsync;
+        tokio::task::spawn_blocking(move || {
+            write_records(file, fsync, receiver)
+        });
+
+        tracing::info!(path = ?config.path, "Audit log initialized");
+
+        Ok(Self { sender })
+    }
+
+    /// Queues an event to be appended to the audit log. Write failures are logged by the writer task rather than propagated so that auditing never halts syncing.
+    pub fn record(&self, event: AuditEvent) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+
+        let record = AuditRecord { timestamp, event };
+        if self.sender.send(AuditMessage::Record(record)).is_err() {
+            tracing::error!("Audit log writer stopped, dropping record");
+        }
+    }
+
+    /// Waits until every event recorded so far has been written to the audit log file.
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+        if self.sender.send(AuditMessage::Flush(sender)).is_ok() {
+            let _ = receiver.await;
+        }
+    }
+}
+
+/// Appends queued records to `file` until every `AuditLog` handle is dropped.
+fn write_records(
+    mut file: File,
+    fsync: bool,
+    mut receiver: mpsc::UnboundedReceiver<AuditMessage>,
+) {
+    while let Some(message) = receiver.blocking_recv() {
+        match message {
+            AuditMessage::Record(record) => {
+                if let Err(err) = append(&mut file, fsync, &record) {
+                    tracing::error!(
+                        ?err,
+                        ?record,
+                        "Failed to write audit record"
+                    );
+                }
+            }
+            AuditMessage::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn append(
+    file: &mut File,
+    fsync: bool,
+    record: &AuditRecord,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+
+    file.write_all(&line)?;
+
+    if fsync {
+        file.sync_data()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn audit_log_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "world-tree-audit-{name}-{}.jsonl",
+            std::process::id()
+        ))
+    }
+
+    fn root_observed(post_root: u64) -> AuditEvent {
+        AuditEvent::RootObserved {
+            pre_root: U256::zero(),
+            post_root: U256::from(post_root),
+            block_number: Some(post_root),
+            tx_hash: None,
+        }
+    }
+
+    fn read_lines(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_record_format() {
+        let path = audit_log_path("format");
+        let _ = std::fs::remove_file(&path);
+        let config = AuditLogConfig {
+            path: path.clone(),
+            fsync: true,
+        };
+
+        let audit_log = AuditLog::open(&config).unwrap();
+        audit_log.record(root_observed(1));
+        audit_log.record(AuditEvent::BatchApplied {
+            kind: BatchKind::Deletion,
+            pre_root: U256::from(1),
+            post_root: U256::from(2),
+            batch_size: 3,
+            block_number: Some(2),
+            tx_hash: H256::zero(),
+        });
+        audit_log.flush().await;
+
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(lines[0]["event"], "root_observed");
+        assert_eq!(lines[0]["block_number"], 1);
+        assert!(lines[0]["timestamp"].as_u64().unwrap() > 0);
+
+        assert_eq!(lines[1]["event"], "batch_applied");
+        assert_eq!(lines[1]["kind"], "deletion");
+        assert_eq!(lines[1]["batch_size"], 3);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_appends_to_existing_log() {
+        let path = audit_log_path("append");
+        let _ = std::fs::remove_file(&path);
+        let config = AuditLogConfig {
+            path: path.clone(),
+            fsync: false,
+        };
+
+        let audit_log = AuditLog::open(&config).unwrap();
+        audit_log.record(root_observed(1));
+        audit_log.flush().await;
+        drop(audit_log);
+
+        // Reopening the log, e.g. after a restart, keeps the records written before
+        let audit_log = AuditLog::open(&config).unwrap();
+        audit_log.record(root_observed(2));
+        audit_log.flush().await;
+
+        let lines = read_lines(&path);
+        let blocks = lines
+            .iter()
+            .map(|line| line["block_number"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![1, 2]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
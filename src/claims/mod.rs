@@ -1,33 +1,48 @@
 /* Module to handle indexing all WLD airdrop claim events */
 
-use std::collections::BTreeMap;
+pub mod query;
+pub mod service;
+
+use std::collections::{BTreeMap, HashMap};
 use ethers::contract::EthEvent;
 use std::ops::DerefMut;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
-use ethers::abi::AbiDecode;
+use ethers::abi::{AbiDecode, AbiEncode};
+use ethers::contract::parse_log;
 use ethers::middleware::Middleware;
-use ethers::prelude::{Filter, H160, Selector, Transaction, U64, ValueOrArray};
-use ethers::core::types::U256;
+use ethers::prelude::{Filter, H160, H256, Log, Selector, Transaction, U64, ValueOrArray};
 use futures::StreamExt;
 use sea_orm::ActiveValue::Set;
-use sea_orm::{Database, DatabaseConnection, EntityTrait};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{DatabaseConnection, EntityTrait};
 use sea_orm::prelude::DateTime;
 use futures::stream::{FuturesUnordered, iter};
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 use crate::abi::{ClaimCall, DeleteIdentitiesCall, DeleteIdentitiesWithDeletionProofAndBatchSizeAndPackedDeletionIndicesAndPreRootCall, GrantClaimedFilter, RegisterIdentitiesCall, TreeChangedFilter, TransferFilter};
 use crate::entities::batches;
-use crate::entities::prelude::{Batches, Deletions, Insertions};
+use crate::entities::claims::{
+    ActiveModel as ClaimActiveModel, Column as ClaimColumn,
+};
+use crate::entities::prelude::{Batches, Claims, Deletions, Insertions};
 use crate::tree::block_scanner::BlockScanner;
+use crate::tree::config::ClaimsConfig;
 use crate::tree::error::{GrantClaimedError, TreeAvailabilityError};
 use crate::tree::{Hash, SYNC_TO_HEAD_SLEEP_SECONDS};
 use crate::tree::service::synced;
 use crate::tree::tree_data::TreeData;
 use crate::tree::tree_updater::{TreeUpdater, unpack_indices};
 
+/// Maximum number of claims inserted by a single statement, keeping it under the Postgres limit on bind parameters.
+const CLAIMS_INSERT_CHUNK_SIZE: usize = 1000;
+
+/// Maximum number of claim transactions fetched concurrently to decode their nullifier hashes.
+const CLAIM_TRANSACTION_CONCURRENCY: usize = 16;
+
 /// Manages the synchronization of the World Tree with it's onchain representation.
 pub struct ClaimUpdater<M: Middleware> {
     /// Contract address of the `RecurringGrantDrop`.
@@ -47,9 +62,11 @@ impl<M: Middleware> ClaimUpdater<M> {
         window_size: u64,
         middleware: Arc<M>,
     ) -> Self {
+        let topics =
+            vec![TransferFilter::signature(), GrantClaimedFilter::signature()];
         let filter = Filter::new()
             .address(address)
-            .topic0(ValueOrArray::Value(TransferFilter::signature()));
+            .topic0(ValueOrArray::Array(topics));
 
         Self {
             address,
@@ -72,6 +89,9 @@ impl<M: Middleware> ClaimUpdater<M> {
     ) -> Result<(), GrantClaimedError<M>> {
         tracing::info!("Syncing claims to chain head");
 
+        let last_synced_block =
+            self.block_scanner.last_synced_block.load(Ordering::SeqCst);
+
         let logs = self
             .block_scanner
             .next()
@@ -83,12 +103,210 @@ impl<M: Middleware> ClaimUpdater<M> {
             return Ok(());
         }
 
+        if let Err(err) = self.persist_claims(db, logs).await {
+            // The scanner has moved past the window, which must be scanned again by the next sync so that its claims are not skipped. Claims are upserted, so those persisted before the failure are written again harmlessly
+            self.block_scanner.rewind_to(last_synced_block);
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Re-scans `from_block..=to_block` (inclusive) and persists the claims found, e.g. to fill in a range missed by an earlier sync. Claims are upserted, so the range may overlap blocks that are already indexed, and `latest_synced_block` is left untouched so that a backfill can run alongside `sync_to_head`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - Database to persist the claims to.
+    /// * `from_block` - First block to backfill.
+    /// * `to_block` - Last block to backfill (inclusive).
+    #[instrument(skip(self, db))]
+    pub async fn backfill(
+        &self,
+        db: &DatabaseConnection,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<(), GrantClaimedError<M>> {
+        tracing::info!("Backfilling claims");
+
+        // Claims are persisted one window at a time, so that a long range is not held in memory and an interrupted backfill keeps its progress
+        let mut window_start = from_block;
+        while window_start <= to_block {
+            let window_end = window_start
+                .saturating_add(self.block_scanner.window_size())
+                .min(to_block);
+
+            let logs = self
+                .block_scanner
+                .scan_range(window_start, window_end)
+                .await
+                .map_err(GrantClaimedError::MiddlewareError)?;
+
+            self.persist_claims(db, logs).await?;
+
+            window_start = window_end + 1;
+        }
+
+        tracing::info!("Claims backfilled");
+
+        Ok(())
+    }
+
+    /// Decodes the claims from `Transfer` logs and upserts them into the database. Transfers made by a `claim` are persisted along with the grant and nullifier hash of the claim, decoded from the `GrantClaimed` log and calldata of the same transaction.
+    async fn persist_claims(
+        &self,
+        db: &DatabaseConnection,
+        logs: Vec<Log>,
+    ) -> Result<(), GrantClaimedError<M>> {
+        if logs.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!(logs = logs.len(), "Processing claims");
+
+        let mut transfers = Vec::new();
+        let mut grants: HashMap<H256, Vec<GrantClaimedFilter>> = HashMap::new();
+
         for log in logs {
-            println!("Claimed {} WLD to {}", U256::decode(log.data).unwrap(), log.topics[1])
+            let block = log
+                .block_number
+                .ok_or(GrantClaimedError::BlockNumberNotFound)?;
+            let tx_hash = log
+                .transaction_hash
+                .ok_or(GrantClaimedError::TransactionHashNotFound)?;
+            let log_index =
+                log.log_index.ok_or(GrantClaimedError::LogIndexNotFound)?;
+
+            // A log that fails to decode, e.g. with fewer topics than its event, is skipped rather than halting the indexer
+            let decoded = if log.topics.first()
+                == Some(&GrantClaimedFilter::signature())
+            {
+                parse_log::<GrantClaimedFilter>(log).map(|grant| {
+                    grants.entry(tx_hash).or_default().push(grant);
+                })
+            } else {
+                parse_log::<TransferFilter>(log).map(|transfer| {
+                    transfers.push((block, tx_hash, log_index, transfer));
+                })
+            };
+
+            if let Err(err) = decoded {
+                tracing::warn!(
+                    ?err,
+                    ?tx_hash,
+                    ?log_index,
+                    "Skipping undecodable claim log"
+                );
+                metrics::increment_counter!(
+                    "claims.claim_updater.skipped_logs"
+                );
+            }
+        }
+
+        // The nullifier hash is not emitted, so it is decoded from the calldata of each transaction that claimed a grant
+        let claim_calls: HashMap<H256, ClaimCall> =
+            iter(grants.keys().copied())
+                .map(|tx_hash| async move {
+                    Ok::<_, GrantClaimedError<M>>(
+                        self.claim_call(tx_hash)
+                            .await?
+                            .map(|claim_call| (tx_hash, claim_call)),
+                    )
+                })
+                .buffer_unordered(CLAIM_TRANSACTION_CONCURRENCY)
+                .filter_map(|result| async move { result.transpose() })
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<_, _>>()?;
+
+        let claims = transfers
+            .into_iter()
+            .map(|(block, tx_hash, log_index, transfer)| {
+                let grant_id = grants.get(&tx_hash).and_then(|grants| {
+                    grants
+                        .iter()
+                        .find(|grant| grant.receiver == transfer.to)
+                        .map(|grant| grant.grant_id)
+                });
+                let nullifier_hash = claim_calls
+                    .get(&tx_hash)
+                    .filter(|claim_call| {
+                        grant_id.is_some() && claim_call.receiver == transfer.to
+                    })
+                    .map(|claim_call| claim_call.nullifier_hash);
+
+                tracing::debug!(
+                    amount = ?transfer.value,
+                    receiver = ?transfer.to,
+                    ?grant_id,
+                    "Claimed WLD"
+                );
+
+                ClaimActiveModel {
+                    recipient: Set(format!("{:?}", transfer.to)),
+                    amount: Set(transfer.value.to_string()),
+                    block: Set(block.as_u64() as i64),
+                    tx_hash: Set(tx_hash.encode_hex()),
+                    log_index: Set(log_index.as_u64() as i64),
+                    grant_id: Set(grant_id.map(|id| id.to_string())),
+                    nullifier_hash: Set(
+                        nullifier_hash.map(|hash| hash.encode_hex())
+                    ),
+                    ..Default::default()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Block ranges may be re-scanned, so claims are upserted on the log they were decoded from
+        for chunk in claims.chunks(CLAIMS_INSERT_CHUNK_SIZE) {
+            Claims::insert_many(chunk.to_vec())
+                .on_conflict(
+                    OnConflict::columns([
+                        ClaimColumn::TxHash,
+                        ClaimColumn::LogIndex,
+                    ])
+                    .update_columns([
+                        ClaimColumn::Recipient,
+                        ClaimColumn::Amount,
+                        ClaimColumn::Block,
+                        ClaimColumn::GrantId,
+                        ClaimColumn::NullifierHash,
+                    ])
+                    .to_owned(),
+                )
+                .exec(db)
+                .await?;
         }
 
+        metrics::counter!("claims.claim_updater.claims", claims.len() as u64);
+
         Ok(())
     }
+
+    /// Decodes the `claim` call made by a transaction, or returns `None` if the transaction did not call `claim` directly, e.g. when claiming through another contract.
+    async fn claim_call(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Option<ClaimCall>, GrantClaimedError<M>> {
+        let transaction = self
+            .middleware
+            .get_transaction(tx_hash)
+            .await
+            .map_err(GrantClaimedError::MiddlewareError)?
+            .ok_or(GrantClaimedError::TransactionNotFound)?;
+
+        match ClaimCall::decode(transaction.input.as_ref()) {
+            Ok(claim_call) => Ok(Some(claim_call)),
+            Err(err) => {
+                tracing::debug!(
+                    ?err,
+                    ?tx_hash,
+                    "Grant was not claimed by a direct `claim` call"
+                );
+                Ok(None)
+            }
+        }
+    }
 }
 
 pub struct ClaimStorage<M: Middleware> {
@@ -96,28 +314,58 @@ pub struct ClaimStorage<M: Middleware> {
 }
 
 impl<M: Middleware> ClaimStorage<M> {
-    /// Spawns a task that continually syncs the `TreeData` to the state at the chain head.
-    #[instrument(skip(self))]
-    pub fn spawn(&self) -> JoinHandle<Result<(), GrantClaimedError<M>>> {
+    /// Initializes a new `ClaimStorage`. Claims are indexed independently of the tree, so a `WorldTree` is not required.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - `RecurringGrantDrop` to index claims from.
+    /// * `middleware` - Provider to interact with Ethereum.
+    pub fn new(config: &ClaimsConfig, middleware: Arc<M>) -> Self {
+        Self {
+            claim_updater: Arc::new(ClaimUpdater::new(
+                config.address,
+                config.creation_block,
+                config.window_size,
+                middleware,
+            )),
+        }
+    }
+
+    /// Spawns a task that continually syncs the claims to the state at the chain head.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - Database to persist the indexed claims to.
+    /// * `shutdown` - Token cancelled to stop syncing. The sync in progress is finished before the task exits, so that no window of claims is left partially persisted.
+    #[instrument(skip(self, db, shutdown))]
+    pub fn spawn(
+        &self,
+        db: DatabaseConnection,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<Result<(), GrantClaimedError<M>>> {
         let claim_updater = self.claim_updater.clone();
-        
+
         tokio::spawn(async move {
-            let database_url = std::env::var("DATABASE_URL").unwrap();
-            let db = Database::connect(database_url).await.unwrap();
             let start = tokio::time::Instant::now(); 
             claim_updater.sync_to_head(&db).await?;
             let sync_time = start.elapsed();
 
             tracing::info!(?sync_time, "ClaimUpdater synced to chain head");
 
-            loop {
+            while !shutdown.is_cancelled() {
                 claim_updater.sync_to_head(&db).await?;
 
-                tokio::time::sleep(Duration::from_secs(
-                    SYNC_TO_HEAD_SLEEP_SECONDS,
-                ))
-                    .await;
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(Duration::from_secs(
+                        SYNC_TO_HEAD_SLEEP_SECONDS,
+                    )) => {}
+                }
             }
+
+            tracing::info!("Stopped syncing claims");
+
+            Ok(())
         })
     }
 }
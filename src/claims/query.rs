@@ -0,0 +1,158 @@
+use std::str::FromStr;
+
+use ethers::types::{H160, H256, U256};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::entities::claims::{Column, Model};
+use crate::entities::prelude::Claims;
+
+/// A claim of the `RecurringGrantDrop`, decoded from the row persisted by the `ClaimUpdater`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimRecord {
+    pub recipient: H160,
+    pub amount: U256,
+    pub block: u64,
+    pub tx_hash: H256,
+    pub log_index: u64,
+    /// Grant claimed by the transfer, or `None` if the transfer was not made by a claim
+    pub grant_id: Option<U256>,
+    /// Nullifier hash of the claim, or `None` if it could not be decoded from the calldata of the transaction
+    pub nullifier_hash: Option<U256>,
+}
+
+impl TryFrom<Model> for ClaimRecord {
+    type Error = DbErr;
+
+    fn try_from(model: Model) -> Result<Self, Self::Error> {
+        Ok(Self {
+            recipient: H160::from_str(&model.recipient)
+                .map_err(|err| DbErr::Type(err.to_string()))?,
+            amount: U256::from_dec_str(&model.amount)
+                .map_err(|err| DbErr::Type(err.to_string()))?,
+            block: model.block as u64,
+            tx_hash: H256::from_str(&model.tx_hash)
+                .map_err(|err| DbErr::Type(err.to_string()))?,
+            log_index: model.log_index as u64,
+            grant_id: model
+                .grant_id
+                .map(|grant_id| U256::from_dec_str(&grant_id))
+                .transpose()
+                .map_err(|err| DbErr::Type(err.to_string()))?,
+            nullifier_hash: model
+                .nullifier_hash
+                .map(|nullifier_hash| H256::from_str(&nullifier_hash))
+                .transpose()
+                .map_err(|err| DbErr::Type(err.to_string()))?
+                .map(|nullifier_hash| {
+                    U256::from_big_endian(nullifier_hash.as_bytes())
+                }),
+        })
+    }
+}
+
+/// Returns the claims received by `address`, oldest first.
+///
+/// # Arguments
+///
+/// * `db` - Database the claims are persisted to.
+/// * `address` - Recipient of the claims.
+pub async fn claims_for_address(
+    db: &DatabaseConnection,
+    address: H160,
+) -> Result<Vec<ClaimRecord>, DbErr> {
+    Claims::find()
+        .filter(Column::Recipient.eq(format!("{address:?}")))
+        .order_by_asc(Column::Block)
+        .order_by_asc(Column::LogIndex)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(ClaimRecord::try_from)
+        .collect()
+}
+
+/// Returns the total amount claimed by `address`.
+///
+/// # Arguments
+///
+/// * `db` - Database the claims are persisted to.
+/// * `address` - Recipient of the claims.
+pub async fn total_claimed(
+    db: &DatabaseConnection,
+    address: H160,
+) -> Result<U256, DbErr> {
+    // Amounts are persisted as decimal text, so they are summed here rather than in SQL
+    Ok(claims_for_address(db, address)
+        .await?
+        .iter()
+        .fold(U256::zero(), |total, claim| total + claim.amount))
+}
+
+/// Returns a page of the most recent claims, most recent first.
+///
+/// # Arguments
+///
+/// * `db` - Database the claims are persisted to.
+/// * `limit` - Maximum number of claims to return.
+/// * `offset` - Number of most recent claims to skip.
+pub async fn recent_claims(
+    db: &DatabaseConnection,
+    limit: u64,
+    offset: u64,
+) -> Result<Vec<ClaimRecord>, DbErr> {
+    Claims::find()
+        .order_by_desc(Column::Block)
+        .order_by_desc(Column::LogIndex)
+        .limit(limit)
+        .offset(offset)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(ClaimRecord::try_from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::abi::AbiEncode;
+
+    use super::*;
+
+    #[test]
+    fn test_claim_record_from_model() {
+        let recipient = H160::repeat_byte(0xab);
+        let tx_hash = H256::repeat_byte(0xcd);
+        let amount = U256::exp10(18);
+        let nullifier_hash = U256::from(42);
+
+        // Columns are encoded the same way as by the `ClaimUpdater`
+        let model = Model {
+            id: 1,
+            recipient: format!("{recipient:?}"),
+            amount: amount.to_string(),
+            block: 100,
+            tx_hash: tx_hash.encode_hex(),
+            log_index: 2,
+            grant_id: Some("7".to_string()),
+            nullifier_hash: Some(nullifier_hash.encode_hex()),
+        };
+
+        assert_eq!(
+            ClaimRecord::try_from(model).unwrap(),
+            ClaimRecord {
+                recipient,
+                amount,
+                block: 100,
+                tx_hash,
+                log_index: 2,
+                grant_id: Some(U256::from(7)),
+                nullifier_hash: Some(nullifier_hash),
+            }
+        );
+    }
+}
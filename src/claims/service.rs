@@ -0,0 +1,105 @@
+use std::net::SocketAddr;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::{middleware, Json};
+use axum_middleware::logging;
+use ethers::types::{H160, U256};
+use sea_orm::{DatabaseConnection, DbErr};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::query::{self, ClaimRecord};
+
+/// Number of claims returned by `/claims` when no limit is requested.
+const DEFAULT_LIMIT: u64 = 100;
+/// Maximum number of claims returned by `/claims`.
+const MAX_LIMIT: u64 = 1000;
+
+/// Spawns an axum server exposing the claims persisted to `db`.
+///
+/// # Arguments
+///
+/// * `address` - Socket to bind the server to.
+/// * `db` - Database the claims are persisted to.
+/// * `shutdown` - Token cancelled to shut down the server.
+pub fn serve(
+    address: SocketAddr,
+    db: DatabaseConnection,
+    shutdown: CancellationToken,
+) -> JoinHandle<Result<(), hyper::Error>> {
+    tokio::spawn(async move {
+        tracing::info!(?address, "Spawning claims server");
+        axum::Server::bind(&address)
+            .serve(router(db).into_make_service())
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+    })
+}
+
+/// Builds the router exposing the claims persisted to `db`.
+pub fn router(db: DatabaseConnection) -> axum::Router {
+    axum::Router::new()
+        .route("/claims", axum::routing::get(recent_claims))
+        .route("/claims/:address", axum::routing::get(claims_for_address))
+        .layer(middleware::from_fn(logging::middleware))
+        .with_state(db)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressClaimsResponse {
+    pub claims: Vec<ClaimRecord>,
+    pub total_claimed: U256,
+}
+
+/// Serves the claims received by an address along with the total amount claimed.
+pub async fn claims_for_address(
+    State(db): State<DatabaseConnection>,
+    Path(address): Path<H160>,
+) -> Result<Json<AddressClaimsResponse>, StatusCode> {
+    let claims = query::claims_for_address(&db, address)
+        .await
+        .map_err(internal_error)?;
+    let total_claimed = claims
+        .iter()
+        .fold(U256::zero(), |total, claim| total + claim.amount);
+
+    Ok(Json(AddressClaimsResponse {
+        claims,
+        total_claimed,
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RecentClaimsParams {
+    pub limit: Option<u64>,
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentClaimsResponse {
+    pub claims: Vec<ClaimRecord>,
+}
+
+/// Serves a page of the most recent claims, most recent first.
+pub async fn recent_claims(
+    State(db): State<DatabaseConnection>,
+    Query(params): Query<RecentClaimsParams>,
+) -> Result<Json<RecentClaimsResponse>, StatusCode> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let claims = query::recent_claims(&db, limit, params.offset)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(RecentClaimsResponse { claims }))
+}
+
+fn internal_error(err: DbErr) -> StatusCode {
+    tracing::error!(?err, "Failed to query claims");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
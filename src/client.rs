@@ -0,0 +1,80 @@
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use url::Url;
+
+use crate::tree::service::{
+    InclusionProofRequest, InclusionProofResponse, TreeInfoResponse,
+};
+use crate::tree::tree_data::InclusionProof;
+use crate::tree::Hash;
+
+/// Client for the API served by the `TreeAvailabilityService`. Responses are decoded into the same types the service serializes, so the client cannot drift from the server.
+pub struct TreeAvailabilityClient {
+    /// HTTP client used to send the requests
+    client: reqwest::Client,
+    /// Base URL of the service, e.g. `http://localhost:8080`
+    url: Url,
+}
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error(transparent)]
+    HttpError(#[from] reqwest::Error),
+    #[error(transparent)]
+    UrlError(#[from] url::ParseError),
+}
+
+impl TreeAvailabilityClient {
+    /// Initializes a new `TreeAvailabilityClient`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Base URL of the service, without a path.
+    pub fn new(url: Url) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    /// Fetches the inclusion proof of an identity commitment against the latest root, as served by `/inclusionProof`. Returns `None` if the commitment is not in the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity_commitment` - Identity commitment to fetch the inclusion proof for.
+    pub async fn inclusion_proof(
+        &self,
+        identity_commitment: Hash,
+    ) -> Result<Option<InclusionProof>, ClientError> {
+        let response: Option<InclusionProofResponse<InclusionProof>> = self
+            .client
+            .post(self.url.join("inclusionProof")?)
+            .json(&InclusionProofRequest::new(identity_commitment, None))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response.map(|response| response.proof))
+    }
+
+    /// Fetches the root of the tree along with the number of identities it holds, as served by `/treeInfo`.
+    pub async fn tree_info(&self) -> Result<TreeInfoResponse, ClientError> {
+        self.get("treeInfo").await
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, ClientError> {
+        Ok(self
+            .client
+            .get(self.url.join(path)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
@@ -3,5 +3,6 @@
 pub mod prelude;
 
 pub mod batches;
+pub mod claims;
 pub mod deletions;
 pub mod insertions;
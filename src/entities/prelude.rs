@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
 pub use super::batches::Entity as Batches;
+pub use super::claims::Entity as Claims;
 pub use super::deletions::Entity as Deletions;
 pub use super::insertions::Entity as Insertions;
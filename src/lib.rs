@@ -1,4 +1,6 @@
 pub mod abi;
+pub mod audit;
+pub mod client;
 pub mod serde_utils;
 pub mod tree;
 pub mod entities;
@@ -18,3 +18,55 @@ where
 
     Url::parse(&s).map_err(serde::de::Error::custom)
 }
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S>(
+        url: &Option<Url>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match url {
+            Some(url) => serializer.serialize_some(url.as_str()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<Url>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: Option<Cow<'static, str>> =
+            Deserialize::deserialize(deserializer)?;
+
+        s.map(|s| Url::parse(&s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+pub mod vec {
+    use super::*;
+
+    pub fn serialize<S>(urls: &[Url], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(urls.iter().map(Url::as_str))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Url>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: Vec<Cow<'static, str>> = Deserialize::deserialize(deserializer)?;
+
+        s.iter()
+            .map(|s| Url::parse(s).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
@@ -0,0 +1,162 @@
+use std::convert::Infallible;
+
+use ethers::abi::{self, Token};
+use ethers::signers::{LocalWallet, Signer, WalletError};
+use ethers::types::transaction::eip712::{EIP712Domain, Eip712};
+use ethers::types::{Address, Bytes, U256};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+
+use super::config::SigningConfig;
+use super::Hash;
+
+/// Name of the EIP-712 signing domain.
+pub const DOMAIN_NAME: &str = "WorldTree";
+/// Version of the EIP-712 signing domain.
+pub const DOMAIN_VERSION: &str = "1";
+/// EIP-712 type of the signed root attestation.
+pub const ROOT_ATTESTATION_TYPE: &str =
+    "RootAttestation(uint256 root,uint256 blockNumber,uint256 timestamp)";
+
+/// Attestation that the service observed `root` as the latest root at `block_number`, signed at `timestamp`.
+///
+/// Signed as EIP-712 typed data under the domain `{ name: "WorldTree", version: "1", chainId, verifyingContract }`, where `verifyingContract` is the `WorldIDIdentityManager` the root belongs to, with the type `RootAttestation(uint256 root,uint256 blockNumber,uint256 timestamp)`.
+#[derive(Debug, Clone)]
+pub struct RootAttestation {
+    pub domain: EIP712Domain,
+    pub root: Hash,
+    pub block_number: u64,
+    pub timestamp: u64,
+}
+
+impl Eip712 for RootAttestation {
+    type Error = Infallible;
+
+    fn domain(&self) -> Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Self::Error> {
+        Ok(keccak256(ROOT_ATTESTATION_TYPE))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
+        Ok(keccak256(abi::encode(&[
+            Token::FixedBytes(Self::type_hash()?.to_vec()),
+            Token::Uint(U256(self.root.into_limbs())),
+            Token::Uint(self.block_number.into()),
+            Token::Uint(self.timestamp.into()),
+        ])))
+    }
+}
+
+/// A root attestation along with its signature and the address of the signer.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedRoot {
+    pub root: Hash,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub signer: Address,
+    pub signature: Bytes,
+}
+
+/// Signs root attestations with the service's configured key.
+pub struct RootSigner {
+    wallet: LocalWallet,
+    domain: EIP712Domain,
+}
+
+impl RootSigner {
+    /// Initializes a new `RootSigner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Signing key and chain id of the signing domain.
+    /// * `verifying_contract` - Address of the `WorldIDIdentityManager` the signed roots belong to.
+    pub fn new(
+        config: &SigningConfig,
+        verifying_contract: Address,
+    ) -> Result<Self, WalletError> {
+        let wallet = config
+            .private_key
+            .parse::<LocalWallet>()?
+            .with_chain_id(config.chain_id);
+
+        let domain = EIP712Domain {
+            name: Some(DOMAIN_NAME.to_string()),
+            version: Some(DOMAIN_VERSION.to_string()),
+            chain_id: Some(config.chain_id.into()),
+            verifying_contract: Some(verifying_contract),
+            salt: None,
+        };
+
+        Ok(Self { wallet, domain })
+    }
+
+    /// Signs an attestation of `root` being the latest root at `block_number`, timestamped with the current time.
+    pub async fn sign_root(
+        &self,
+        root: Hash,
+        block_number: u64,
+    ) -> Result<SignedRoot, WalletError> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+
+        let attestation = RootAttestation {
+            domain: self.domain.clone(),
+            root,
+            block_number,
+            timestamp,
+        };
+
+        let signature = self.wallet.sign_typed_data(&attestation).await?;
+
+        Ok(SignedRoot {
+            root,
+            block_number,
+            timestamp,
+            signer: self.wallet.address(),
+            signature: signature.to_vec().into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Signature, H256};
+
+    use super::*;
+
+    const PRIVATE_KEY: &str =
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    #[tokio::test]
+    async fn test_sign_root_recovers_signer() {
+        let config = SigningConfig {
+            private_key: PRIVATE_KEY.to_string(),
+            chain_id: 1,
+        };
+        let signer = RootSigner::new(&config, Address::random()).unwrap();
+
+        let signed_root =
+            signer.sign_root(Hash::from(42_u64), 100).await.unwrap();
+
+        let attestation = RootAttestation {
+            domain: signer.domain.clone(),
+            root: signed_root.root,
+            block_number: signed_root.block_number,
+            timestamp: signed_root.timestamp,
+        };
+        let digest = attestation.encode_eip712().unwrap();
+
+        let signature =
+            Signature::try_from(signed_root.signature.as_ref()).unwrap();
+        let recovered = signature.recover(H256::from(digest)).unwrap();
+
+        assert_eq!(recovered, signed_root.signer);
+        assert_eq!(recovered, signer.wallet.address());
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use ethers::types::{Transaction, H256};
+use ethers_throttle::Throttle;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+/// Client sending JSON-RPC batch requests, used to fetch many transactions in a single round trip while syncing.
+///
+/// Each batch counts as a single request against the shared throttle.
+pub struct RpcBatchClient {
+    /// HTTP client used to send the batch requests
+    client: reqwest::Client,
+    /// Ethereum RPC endpoint
+    url: Url,
+    /// Maximum number of calls to include in a single batch request
+    max_batch_size: usize,
+    /// Rate limiter shared with the provider stack
+    throttle: Option<Arc<Throttle>>,
+    /// Headers sent with every batch request
+    headers: HeaderMap,
+}
+
+#[derive(Error, Debug)]
+pub enum RpcBatchError {
+    #[error(transparent)]
+    HttpError(#[from] reqwest::Error),
+    #[error("JSON-RPC error in batch response: {0}")]
+    JsonRpcError(serde_json::Value),
+    #[error("Batch response is missing the response to request {0}")]
+    MissingResponse(u64),
+}
+
+#[derive(Serialize)]
+struct BatchRequest<'a> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'static str,
+    params: [&'a H256; 1],
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: u64,
+    #[serde(default)]
+    result: Option<Transaction>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+impl RpcBatchClient {
+    /// Initializes a new `RpcBatchClient`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Ethereum RPC endpoint supporting JSON-RPC batch requests.
+    /// * `max_batch_size` - Maximum number of calls to include in a single batch request.
+    /// * `throttle` - Optional rate limiter shared with the provider stack.
+    pub fn new(
+        url: Url,
+        max_batch_size: usize,
+        throttle: Option<Arc<Throttle>>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            max_batch_size: max_batch_size.max(1),
+            throttle,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Sets the headers sent with every batch request, e.g. to authenticate against the RPC endpoint.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Fetches the transactions for the given hashes, issuing one `eth_getTransactionByHash` batch request per `max_batch_size` hashes. The returned transactions are in the same order as `tx_hashes`.
+    pub async fn get_transactions(
+        &self,
+        tx_hashes: &[H256],
+    ) -> Result<Vec<Option<Transaction>>, RpcBatchError> {
+        let mut transactions = Vec::with_capacity(tx_hashes.len());
+
+        for chunk in tx_hashes.chunks(self.max_batch_size) {
+            if let Some(throttle) = &self.throttle {
+                throttle.until_ready().await;
+            }
+
+            let requests: Vec<_> = chunk
+                .iter()
+                .enumerate()
+                .map(|(id, tx_hash)| BatchRequest {
+                    jsonrpc: "2.0",
+                    id: id as u64,
+                    method: "eth_getTransactionByHash",
+                    params: [tx_hash],
+                })
+                .collect();
+
+            tracing::info!(batch_size = requests.len(), "Sending batch request");
+
+            let mut responses: Vec<BatchResponse> = self
+                .client
+                .post(self.url.clone())
+                .headers(self.headers.clone())
+                .json(&requests)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            // Responses to a batch request may be returned in any order
+            responses.sort_by_key(|response| response.id);
+
+            for id in 0..chunk.len() as u64 {
+                let response = responses
+                    .get(id as usize)
+                    .filter(|response| response.id == id)
+                    .ok_or(RpcBatchError::MissingResponse(id))?;
+
+                if let Some(error) = &response.error {
+                    return Err(RpcBatchError::JsonRpcError(error.clone()));
+                }
+
+                transactions.push(response.result.clone());
+            }
+        }
+
+        Ok(transactions)
+    }
+}
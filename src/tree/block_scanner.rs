@@ -1,7 +1,40 @@
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
 
-use ethers::providers::Middleware;
-use ethers::types::{BlockNumber, Filter, Log};
+use ethers::providers::{JsonRpcError, Middleware, MiddlewareError};
+use ethers::types::{BlockNumber, Filter, Log, H256};
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+use super::config::WindowTuning;
+use super::log_cache::{LogCache, LOG_CACHE_MIN_DEPTH};
+
+/// Number of synced block hashes retained to locate the fork point of a reorg.
+pub const BLOCK_HASH_HISTORY_SIZE: usize = 128;
+
+/// Lowercase fragments of the messages with which providers reject `eth_getLogs` requests spanning too many blocks or returning too many logs.
+const SIZE_LIMIT_ERRORS: &[&str] = &[
+    "block range",
+    "returned more than",
+    "response size",
+    "too large",
+    "too many",
+];
+
+/// Lowercase fragments of rate limit messages, which may also match `SIZE_LIMIT_ERRORS` but are retried rather than surfaced to the scanner.
+const RATE_LIMIT_ERRORS: &[&str] = &["rate limit", "too many requests"];
+
+/// A reorg of the blocks already scanned, detected by `BlockScanner::detect_reorg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reorg {
+    /// Latest synced block at the time the reorg was detected
+    pub previous_tip: u64,
+    /// Latest synced block still on the canonical chain. `None` if no retained block hash matches the canonical chain
+    pub fork_block: Option<u64>,
+    /// Number of synced blocks reorged out. A lower bound if `fork_block` is `None`
+    pub depth: u64,
+}
 
 /// The `BlockScanner` utility tool enables allows parsing arbitrary onchain events
 pub struct BlockScanner<M> {
@@ -9,10 +42,20 @@ pub struct BlockScanner<M> {
     middleware: M,
     /// The block from which to start parsing a given event
     pub last_synced_block: AtomicU64,
-    /// The maximum block range to parse
-    window_size: u64,
+    /// The block range currently parsed per request. Adjusted within the bounds of `window_tuning` when set
+    window_size: AtomicU64,
+    /// Consecutive successful fetches since the window last changed
+    successful_fetches: AtomicU32,
+    /// Adjusts `window_size` to the ranges the provider can serve. If `None`, the window size is fixed
+    window_tuning: Option<WindowTuning>,
+    /// Maximum number of windows fetched concurrently
+    window_concurrency: usize,
     /// Filter specifying the address and topics to match on when scanning
     filter: Filter,
+    /// Hashes of the most recently synced blocks, most recent first. If `None`, reorgs are not detected
+    block_hashes: Option<Mutex<VecDeque<(u64, H256)>>>,
+    /// On-disk cache of the logs fetched per window. If `None`, every window is fetched from the provider
+    log_cache: Option<LogCache>,
 }
 
 impl<M> BlockScanner<M>
@@ -29,40 +72,683 @@ where
         Self {
             middleware,
             last_synced_block: AtomicU64::new(current_block),
-            window_size,
+            window_size: AtomicU64::new(window_size),
+            successful_fetches: AtomicU32::new(0),
+            window_tuning: None,
+            window_concurrency: 1,
             filter,
+            block_hashes: None,
+            log_cache: None,
         }
     }
 
+    /// Shrinks the window whenever the provider rejects a range as too large and grows it back after consecutive successful fetches, within the bounds of `window_tuning`. The window is capped at the initial window size unless `max_window` is set.
+    pub fn with_window_tuning(
+        mut self,
+        mut window_tuning: WindowTuning,
+    ) -> Self {
+        let window_size = *self.window_size.get_mut();
+        window_tuning.max_window =
+            Some(window_tuning.max_window.unwrap_or(window_size));
+
+        self.window_tuning = Some(window_tuning);
+        self
+    }
+
+    /// Fetches up to `window_concurrency` windows concurrently when the range to scan spans several windows. The logs are still returned in block order.
+    pub fn with_window_concurrency(
+        mut self,
+        window_concurrency: usize,
+    ) -> Self {
+        self.window_concurrency = window_concurrency.max(1);
+        self
+    }
+
+    /// Records the hash of the last synced block after each scan, so that `detect_reorg` can tell when blocks already scanned are reorged out. This costs one additional request per scan.
+    pub fn with_reorg_detection(mut self) -> Self {
+        self.block_hashes = Some(Mutex::new(VecDeque::new()));
+        self
+    }
+
+    /// Reads the logs of each window from `log_cache` when cached, and caches the logs fetched for windows ending at least `LOG_CACHE_MIN_DEPTH` blocks below the end of the scanned range, which are unlikely to be reorged out.
+    pub fn with_log_cache(mut self, log_cache: LogCache) -> Self {
+        self.log_cache = Some(log_cache);
+        self
+    }
+
     /// Retrieves events matching the specified address and topics from the last synced block to the latest block, stepping by `window_size`.
     pub async fn next(&self) -> Result<Vec<Log>, M::Error> {
         let latest_block = self.middleware.get_block_number().await?.as_u64();
-        let mut last_synced_block =
-            self.last_synced_block.load(Ordering::SeqCst);
-        let mut logs = Vec::new();
 
-        while last_synced_block < latest_block {
-            let from_block = last_synced_block + 1;
-            let to_block = (from_block + self.window_size).min(latest_block);
+        self.next_until(latest_block).await
+    }
+
+    /// Retrieves events matching the specified address and topics from the last synced block to `to_block` (inclusive), stepping by `window_size`.
+    pub async fn next_until(
+        &self,
+        to_block: u64,
+    ) -> Result<Vec<Log>, M::Error> {
+        let last_synced_block = self.last_synced_block.load(Ordering::SeqCst);
+
+        if last_synced_block >= to_block {
+            return Ok(Vec::new());
+        }
 
-            tracing::info!(?from_block, ?to_block, "Scanning blocks");
+        // The hash is fetched before the logs, so that a reorg racing the scan is caught by the next `detect_reorg`
+        let to_block_hash = match &self.block_hashes {
+            Some(_) => self.block_hash(to_block).await?,
+            None => None,
+        };
 
-            let filter = self
-                .filter
-                .clone()
-                .from_block(BlockNumber::Number(from_block.into()))
-                .to_block(BlockNumber::Number(to_block.into()));
+        let cache_until = to_block.saturating_sub(LOG_CACHE_MIN_DEPTH);
+        let logs = if self.window_concurrency > 1 {
+            self.scan_concurrently(last_synced_block + 1, to_block, cache_until)
+                .await?
+        } else {
+            self.scan(last_synced_block + 1, to_block, cache_until)
+                .await?
+        };
 
-            logs.extend(self.middleware.get_logs(&filter).await?);
+        self.last_synced_block.store(to_block, Ordering::SeqCst);
 
-            last_synced_block = to_block;
+        if let (Some(block_hashes), Some(hash)) =
+            (&self.block_hashes, to_block_hash)
+        {
+            let mut block_hashes =
+                block_hashes.lock().expect("Block hash lock poisoned");
+
+            if block_hashes.len() == BLOCK_HASH_HISTORY_SIZE {
+                block_hashes.pop_back();
+            }
+            block_hashes.push_front((to_block, hash));
         }
 
-        self.last_synced_block
-            .store(last_synced_block, Ordering::SeqCst);
+        tracing::info!(
+            last_synced_block = ?to_block,
+            "Last synced block updated"
+        );
+
+        Ok(logs)
+    }
+
+    /// Retrieves events matching the specified address and topics within `from_block..=to_block` (inclusive), without advancing `last_synced_block`. Unlike `logs_in_range`, the range is fetched one window at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive).
+    pub async fn scan_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<Log>, M::Error> {
+        let cache_until = to_block.saturating_sub(LOG_CACHE_MIN_DEPTH);
+
+        self.scan(from_block, to_block, cache_until).await
+    }
 
-        tracing::info!(?last_synced_block, "Last synced block updated");
+    /// Current number of blocks scanned per window.
+    pub fn window_size(&self) -> u64 {
+        self.window_size.load(Ordering::SeqCst)
+    }
+
+    /// Splits `from_block..=to_block` into windows of `window_size` blocks, fetching up to `window_concurrency` of them concurrently. Each window is scanned as by `scan`, so a window rejected as too large is split further.
+    async fn scan_concurrently(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        cache_until: u64,
+    ) -> Result<Vec<Log>, M::Error> {
+        let window_size = self.window_size.load(Ordering::SeqCst);
+
+        let mut windows = Vec::new();
+        let mut window_start = from_block;
+        while window_start <= to_block {
+            let window_end = (window_start + window_size).min(to_block);
+            windows.push((window_start, window_end));
+            window_start = window_end + 1;
+        }
+
+        // Windows are buffered in order, so that the logs are returned in block order however the fetches complete
+        let window_logs: Vec<Vec<Log>> = stream::iter(windows)
+            .map(|(from_block, to_block)| {
+                self.scan(from_block, to_block, cache_until)
+            })
+            .buffered(self.window_concurrency)
+            .try_collect()
+            .await?;
+
+        Ok(window_logs.into_iter().flatten().collect())
+    }
+
+    /// Retrieves the events within `from_block..=to_block` one window at a time, without advancing `last_synced_block`. The window adapts to the provider when `window_tuning` is set, and a window rejected as too large that cannot shrink any further is split by `split_range`. Fetched windows ending at or before `cache_until` are stored in `log_cache`.
+    async fn scan(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        cache_until: u64,
+    ) -> Result<Vec<Log>, M::Error> {
+        let mut next_block = from_block;
+        let mut logs = Vec::new();
+
+        while next_block <= to_block {
+            let from_block = next_block;
+            let window_size = self.window_size.load(Ordering::SeqCst);
+            let window_end = (from_block + window_size).min(to_block);
+
+            if let Some(window_logs) = self
+                .log_cache
+                .as_ref()
+                .and_then(|log_cache| log_cache.get(from_block, window_end))
+            {
+                tracing::info!(
+                    ?from_block,
+                    to_block = ?window_end,
+                    "Loaded cached logs"
+                );
+                logs.extend(window_logs);
+                next_block = window_end + 1;
+                continue;
+            }
+
+            tracing::info!(?from_block, to_block = ?window_end, "Scanning blocks");
+
+            let window_logs = match self
+                .logs_in_range(&self.middleware, from_block, window_end)
+                .await
+            {
+                Ok(window_logs) => {
+                    self.grow_window(window_size);
+                    window_logs
+                }
+                Err(err) if !is_size_limit_error(&err) => return Err(err),
+                Err(err) if self.shrink_window(window_size) => {
+                    tracing::warn!(
+                        ?err,
+                        ?from_block,
+                        to_block = ?window_end,
+                        "Failed to fetch logs, retrying with a smaller window"
+                    );
+                    continue;
+                }
+                Err(err) if from_block < window_end => {
+                    tracing::warn!(
+                        ?err,
+                        ?from_block,
+                        to_block = ?window_end,
+                        "Failed to fetch logs, splitting the range"
+                    );
+                    self.split_range(from_block, window_end).await?
+                }
+                Err(err) => return Err(err),
+            };
+
+            if let Some(log_cache) = &self.log_cache {
+                if window_end <= cache_until {
+                    log_cache.insert(from_block, window_end, &window_logs);
+                }
+            }
+            logs.extend(window_logs);
+            next_block = window_end + 1;
+        }
 
         Ok(logs)
     }
+
+    /// Checks whether the last synced block is still on the canonical chain. If it is not, walks back the retained block hashes to find the latest synced block that still is.
+    ///
+    /// Returns `None` if no reorg is detected or reorg detection is disabled. `last_synced_block` is left untouched, see `rewind_to`.
+    pub async fn detect_reorg(&self) -> Result<Option<Reorg>, M::Error> {
+        let Some(block_hashes) = &self.block_hashes else {
+            return Ok(None);
+        };
+
+        let recorded: Vec<(u64, H256)> = block_hashes
+            .lock()
+            .expect("Block hash lock poisoned")
+            .iter()
+            .copied()
+            .collect();
+
+        let Some(&(previous_tip, tip_hash)) = recorded.first() else {
+            return Ok(None);
+        };
+
+        if self.block_hash(previous_tip).await? == Some(tip_hash) {
+            return Ok(None);
+        }
+
+        for &(block, hash) in &recorded[1..] {
+            if self.block_hash(block).await? == Some(hash) {
+                return Ok(Some(Reorg {
+                    previous_tip,
+                    fork_block: Some(block),
+                    depth: previous_tip - block,
+                }));
+            }
+        }
+
+        let (oldest_block, _) = recorded[recorded.len() - 1];
+
+        Ok(Some(Reorg {
+            previous_tip,
+            fork_block: None,
+            depth: previous_tip - oldest_block + 1,
+        }))
+    }
+
+    /// Moves `last_synced_block` to `block`, forgetting the hashes of any block synced after it. Used to resume scanning from a given block, or to re-scan from the fork point of a reorg.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - Last block whose logs are considered scanned.
+    pub fn rewind_to(&self, block: u64) {
+        self.last_synced_block.store(block, Ordering::SeqCst);
+
+        if let Some(block_hashes) = &self.block_hashes {
+            block_hashes
+                .lock()
+                .expect("Block hash lock poisoned")
+                .retain(|(number, _)| *number <= block);
+        }
+    }
+
+    /// Forgets the hashes of every synced block, so that no reorg is detected against them. Used once the tree is rebuilt after a reorg reaching past the recorded hashes.
+    pub fn forget_block_hashes(&self) {
+        if let Some(block_hashes) = &self.block_hashes {
+            block_hashes
+                .lock()
+                .expect("Block hash lock poisoned")
+                .clear();
+        }
+    }
+
+    /// Removes the cached logs of the windows overlapping `from_block..=to_block`, so that they are fetched from the provider on the next scan. Used when the logs of a range turn out to be incomplete.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive).
+    pub fn evict_cached_logs(&self, from_block: u64, to_block: u64) {
+        if let Some(log_cache) = &self.log_cache {
+            log_cache.remove_range(from_block, to_block);
+        }
+    }
+
+    async fn block_hash(&self, block: u64) -> Result<Option<H256>, M::Error> {
+        let block = self.middleware.get_block(block).await?;
+
+        Ok(block.and_then(|block| block.hash))
+    }
+
+    /// Grows the window after `growth_interval` consecutive successful fetches, up to `max_window`.
+    fn grow_window(&self, window_size: u64) {
+        let Some(window_tuning) = &self.window_tuning else {
+            return;
+        };
+
+        let successful_fetches =
+            self.successful_fetches.fetch_add(1, Ordering::SeqCst) + 1;
+        if successful_fetches < window_tuning.growth_interval {
+            return;
+        }
+
+        let grown =
+            (window_size as f64 * window_tuning.growth_factor).ceil() as u64;
+        let max_window = window_tuning.max_window.unwrap_or(window_size);
+
+        self.set_window(grown.max(window_size + 1).min(max_window));
+    }
+
+    /// Shrinks the window after a fetch rejected as too large, down to `min_window`. Returns `false` if the window cannot shrink any further, in which case the error should be surfaced.
+    fn shrink_window(&self, window_size: u64) -> bool {
+        let Some(window_tuning) = &self.window_tuning else {
+            return false;
+        };
+
+        let shrunk =
+            (window_size as f64 * window_tuning.shrink_factor).floor() as u64;
+        let shrunk = shrunk.max(window_tuning.min_window);
+
+        if shrunk >= window_size {
+            return false;
+        }
+
+        self.set_window(shrunk);
+        true
+    }
+
+    fn set_window(&self, window_size: u64) {
+        self.window_size.store(window_size, Ordering::SeqCst);
+        self.successful_fetches.store(0, Ordering::SeqCst);
+        metrics::gauge!(
+            "tree_availability.block_scanner.window_size",
+            window_size as f64
+        );
+    }
+
+    /// Retrieves the events within a range that the provider rejected as too large, by splitting it in half and fetching each half. A half that is rejected as well is split again, down to single blocks, whose rejection is surfaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive). Must be greater than `from_block`.
+    fn split_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> BoxFuture<'_, Result<Vec<Log>, M::Error>> {
+        Box::pin(async move {
+            let mid_block = from_block + (to_block - from_block) / 2;
+            let mut logs = Vec::new();
+
+            for (from_block, to_block) in
+                [(from_block, mid_block), (mid_block + 1, to_block)]
+            {
+                match self
+                    .logs_in_range(&self.middleware, from_block, to_block)
+                    .await
+                {
+                    Ok(half_logs) => logs.extend(half_logs),
+                    Err(err)
+                        if from_block < to_block
+                            && is_size_limit_error(&err) =>
+                    {
+                        logs.extend(
+                            self.split_range(from_block, to_block).await?,
+                        );
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+
+            Ok(logs)
+        })
+    }
+
+    /// Retrieves events matching the specified address and topics within a block range from the given provider, without advancing `last_synced_block`.
+    ///
+    /// # Arguments
+    ///
+    /// * `middleware` - Provider to fetch the logs from.
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive).
+    pub async fn logs_in_range(
+        &self,
+        middleware: &M,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<Log>, M::Error> {
+        let filter = self
+            .filter
+            .clone()
+            .from_block(BlockNumber::Number(from_block.into()))
+            .to_block(BlockNumber::Number(to_block.into()));
+
+        middleware.get_logs(&filter).await
+    }
+}
+
+/// Whether the provider rejected a request for spanning too many blocks or returning too many logs, in which case it may succeed over a smaller range.
+fn is_size_limit_error<E: MiddlewareError>(err: &E) -> bool {
+    err.as_error_response().is_some_and(is_size_limit_response)
+}
+
+/// Whether a JSON-RPC error response rejects a request for spanning too many blocks or returning too many logs. Retry and failover layers must not retry such errors, so that they reach the scanner and shrink the window.
+pub fn is_size_limit_response(err: &JsonRpcError) -> bool {
+    let message = err.message.to_lowercase();
+
+    SIZE_LIMIT_ERRORS
+        .iter()
+        .any(|fragment| message.contains(fragment))
+        && !RATE_LIMIT_ERRORS
+            .iter()
+            .any(|fragment| message.contains(fragment))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use ethers::providers::{
+        JsonRpcClient, MockProvider, MockResponse, Provider, ProviderError,
+    };
+    use ethers::types::Block;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::*;
+
+    fn rpc_error(code: i64, message: &str) -> JsonRpcError {
+        JsonRpcError {
+            code,
+            message: message.to_owned(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn test_is_size_limit_response() {
+        assert!(is_size_limit_response(&rpc_error(
+            -32005,
+            "query returned more than 10000 results"
+        )));
+        assert!(is_size_limit_response(&rpc_error(
+            -32602,
+            "Log response size exceeded."
+        )));
+        assert!(is_size_limit_response(&rpc_error(
+            -32000,
+            "exceed maximum block range: 5000"
+        )));
+
+        assert!(!is_size_limit_response(&rpc_error(
+            -32005,
+            "project ID request rate exceeded"
+        )));
+        assert!(!is_size_limit_response(&rpc_error(
+            -32005,
+            "Too many requests, rate limit exceeded"
+        )));
+        assert!(!is_size_limit_response(&rpc_error(
+            -32601,
+            "the method eth_getLogs does not exist"
+        )));
+    }
+
+    fn log_at(block: u64) -> Log {
+        Log {
+            block_number: Some(block.into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_range() {
+        let (provider, mock) = Provider::mocked();
+        let block_scanner = BlockScanner::new(provider, 7, 0, Filter::new());
+
+        let too_many = || {
+            MockResponse::Error(rpc_error(
+                -32005,
+                "query returned more than 10000 results",
+            ))
+        };
+
+        // Responses are popped in reverse order of being pushed. The window of blocks 1-8 is rejected, then its first half 1-4, which is split again
+        mock.push(vec![log_at(5)]).unwrap();
+        mock.push(vec![log_at(3)]).unwrap();
+        mock.push(vec![log_at(1)]).unwrap();
+        mock.push_response(too_many());
+        mock.push_response(too_many());
+
+        let logs = block_scanner.scan_range(1, 8).await.unwrap();
+        assert_eq!(logs, vec![log_at(1), log_at(3), log_at(5)]);
+
+        // A single block cannot be split, so its rejection is surfaced
+        mock.push_response(too_many());
+        assert!(block_scanner.scan_range(9, 9).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_range_from_genesis() {
+        let (provider, mock) = Provider::mocked();
+        let block_scanner = BlockScanner::new(provider, 10, 0, Filter::new());
+
+        // A range starting at block 0 is scanned from block 0 itself
+        mock.push(vec![log_at(0), log_at(5)]).unwrap();
+        let logs = block_scanner.scan_range(0, 5).await.unwrap();
+        assert_eq!(logs, vec![log_at(0), log_at(5)]);
+    }
+
+    fn block_with_hash(hash: H256) -> Block<H256> {
+        Block {
+            hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    /// Scanner with the hashes of blocks 10, 20 and 30 recorded, as if each had been the tip of a scan.
+    fn scanned_to_block_30(
+    ) -> (BlockScanner<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let block_scanner = BlockScanner::new(provider, 10, 30, Filter::new())
+            .with_reorg_detection();
+
+        block_scanner
+            .block_hashes
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .extend(
+                [30, 20, 10].map(|block| (block, H256::from_low_u64_be(block))),
+            );
+
+        (block_scanner, mock)
+    }
+
+    #[tokio::test]
+    async fn test_detect_reorg() {
+        let (block_scanner, mock) = scanned_to_block_30();
+
+        // The tip is still canonical
+        mock.push(block_with_hash(H256::from_low_u64_be(30)))
+            .unwrap();
+        assert_eq!(block_scanner.detect_reorg().await.unwrap(), None);
+
+        // The tip was reorged out, block 20 is still canonical. Responses are popped in reverse order of being pushed
+        mock.push(block_with_hash(H256::from_low_u64_be(20)))
+            .unwrap();
+        mock.push(block_with_hash(H256::repeat_byte(0xff))).unwrap();
+        assert_eq!(
+            block_scanner.detect_reorg().await.unwrap(),
+            Some(Reorg {
+                previous_tip: 30,
+                fork_block: Some(20),
+                depth: 10,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_detect_reorg_without_fork_block() {
+        let (block_scanner, mock) = scanned_to_block_30();
+
+        // None of the recorded blocks is on the canonical chain anymore
+        for _ in 0..3 {
+            mock.push(block_with_hash(H256::repeat_byte(0xff))).unwrap();
+        }
+        assert_eq!(
+            block_scanner.detect_reorg().await.unwrap(),
+            Some(Reorg {
+                previous_tip: 30,
+                fork_block: None,
+                depth: 21,
+            })
+        );
+    }
+
+    #[test]
+    fn test_window_tuning_bounds() {
+        let (provider, _mock) = Provider::mocked();
+        let block_scanner = BlockScanner::new(provider, 100, 0, Filter::new())
+            .with_window_tuning(WindowTuning {
+                growth_factor: 2.0,
+                growth_interval: 2,
+                shrink_factor: 0.5,
+                min_window: 10,
+                max_window: None,
+            });
+
+        // The window shrinks down to `min_window`, after which rejections are surfaced
+        for expected in [50, 25, 12, 10] {
+            assert!(block_scanner.shrink_window(block_scanner.window_size()));
+            assert_eq!(block_scanner.window_size(), expected);
+        }
+        assert!(!block_scanner.shrink_window(block_scanner.window_size()));
+        assert_eq!(block_scanner.window_size(), 10);
+
+        // The window grows every `growth_interval` successful fetches, capped at the initial window size
+        for expected in [10, 20, 20, 40, 40, 80, 80, 100, 100, 100] {
+            block_scanner.grow_window(block_scanner.window_size());
+            assert_eq!(block_scanner.window_size(), expected);
+        }
+    }
+
+    /// Client serving `eth_getLogs` with a single log at the first block of the requested range. Ranges starting earlier are answered later, so that concurrently fetched windows complete in reverse order.
+    #[derive(Debug, Default)]
+    struct ReversedLatencyClient {
+        /// First block of each range, in the order the responses completed
+        completed: Arc<Mutex<Vec<u64>>>,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for ReversedLatencyClient {
+        type Error = ProviderError;
+
+        async fn request<T, R>(
+            &self,
+            method: &str,
+            params: T,
+        ) -> Result<R, Self::Error>
+        where
+            T: std::fmt::Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            assert_eq!(method, "eth_getLogs");
+
+            let params = serde_json::to_value(params)?;
+            let from_block = params[0]["fromBlock"]
+                .as_str()
+                .and_then(|block| {
+                    u64::from_str_radix(block.trim_start_matches("0x"), 16).ok()
+                })
+                .expect("Missing fromBlock");
+
+            tokio::time::sleep(Duration::from_millis(100 - from_block)).await;
+            self.completed.lock().unwrap().push(from_block);
+
+            Ok(serde_json::from_value(serde_json::to_value(vec![
+                log_at(from_block),
+            ])?)?)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_concurrently_keeps_block_order() {
+        let client = ReversedLatencyClient::default();
+        let completed = client.completed.clone();
+        let provider = Arc::new(Provider::new(client));
+        let block_scanner = BlockScanner::new(provider, 9, 0, Filter::new())
+            .with_window_concurrency(3);
+
+        let logs = block_scanner.next_until(30).await.unwrap();
+
+        assert_eq!(*completed.lock().unwrap(), vec![21, 11, 1]);
+        assert_eq!(logs, vec![log_at(1), log_at(11), log_at(21)]);
+        assert_eq!(block_scanner.last_synced_block.load(Ordering::SeqCst), 30);
+    }
 }
@@ -1,17 +1,60 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 
-use ethers::types::Address;
+use ethers::contract::EthCall;
+use ethers::types::{Address, Selector, H256};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::abi::{
+    DeleteIdentitiesCall,
+    DeleteIdentitiesWithDeletionProofAndBatchSizeAndPackedDeletionIndicesAndPreRootCall,
+    RegisterIdentitiesCall,
+};
+use crate::audit::AuditLogConfig;
+
 pub const CONFIG_PREFIX: &str = "WLD";
 
+/// Memory taken by the dense prefix of the tree above which a warning is logged.
+const DENSE_PREFIX_MEMORY_WARNING_BYTES: u128 = 1 << 32;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServiceConfig {
     pub world_tree: WorldTreeConfig,
 
     pub provider: ProviderConfig,
+
+    #[serde(default)]
+    pub api: ApiConfig,
+
+    #[serde(default)]
+    pub database: DatabaseConfig,
+
+    /// Subsystems indexed by this process
+    #[serde(default)]
+    pub mode: IndexMode,
+
+    /// `RecurringGrantDrop` to index claims from. Required when indexing claims
+    #[serde(default)]
+    pub claims: Option<ClaimsConfig>,
+
+    /// Key used to sign root attestations. The signed root endpoint is disabled when unset
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+
+    /// Append-only audit log of observed roots and applied batches. Disabled when unset
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
+
+    /// Restarts of the tree and claims sync tasks after they fail
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    /// Per-client rate limit of the routes reading or signing the tree. Unlimited when unset
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
 }
 
 impl ServiceConfig {
@@ -32,6 +75,7 @@ impl ServiceConfig {
             .build()?;
 
         let config = settings.try_deserialize::<Self>()?;
+        config.world_tree.validate()?;
 
         Ok(config)
     }
@@ -44,9 +88,15 @@ pub struct WorldTreeConfig {
     /// Maximum window size when scanning blocks for TreeChanged events
     #[serde(default = "default::window_size")]
     pub window_size: u64,
+    /// Adjusts the scanning window to the ranges the provider can serve, starting from `window_size`. The window is fixed when unset
+    #[serde(default)]
+    pub window_tuning: Option<WindowTuning>,
     /// Creation block of the World Tree
     pub creation_block: u64,
-    /// Quantity of recent tree changes to cache. This allows inclusion proof requests to specify a historical root
+    /// Wait at startup for the chain head to reach `creation_block` instead of failing, for contracts that are not deployed yet
+    #[serde(default)]
+    pub wait_for_creation_block: bool,
+    /// Quantity of recent tree changes to cache. This allows inclusion proof requests to specify a historical root. If 0, only proofs against the latest root are served
     pub tree_history_size: usize,
     /// Depth of the World Tree
     pub tree_depth: usize,
@@ -55,15 +105,604 @@ pub struct WorldTreeConfig {
     /// Socket at which to serve the service
     #[serde(default = "default::socket_address")]
     pub socket_address: SocketAddr,
+    /// Sockets at which to serve the service, each optionally restricted to a subset of routes. When set, this replaces `socket_address`.
+    #[serde(default)]
+    pub binds: Vec<BindConfig>,
+    /// Alerting on anomalous numbers of insertions within a block window. Disabled when unset
+    #[serde(default)]
+    pub insertion_rate_limit: Option<InsertionRateLimit>,
+    /// Cross-check of the logs returned for each scanned window against the roots they commit to, guarding against providers returning incomplete log sets. Disabled when unset
+    #[serde(default)]
+    pub log_consistency_check: Option<LogConsistencyCheck>,
+    /// Version of the `WorldIDIdentityManager` implementation, selecting the calldata the tree is synced from
+    #[serde(default)]
+    pub contract_version: ContractVersion,
+    /// Time in milliseconds for which the onchain latest root is cached
+    #[serde(default = "default::onchain_root_cache_ttl")]
+    pub onchain_root_cache_ttl: u64,
+    /// Interval in seconds between checks of the root of the tree against the onchain latest root. Set to 0 to disable the check
+    #[serde(default = "default::root_check_interval_secs")]
+    pub root_check_interval_secs: u64,
+    /// Treats the tree as append-only, refusing to apply deletions. Disabled when unset
+    #[serde(default)]
+    pub append_only: Option<AppendOnly>,
+    /// Maximum number of concurrent transaction requests while catching up to the chain head
+    #[serde(default = "default::backfill_concurrency")]
+    pub backfill_concurrency: usize,
+    /// Maximum number of concurrent transaction requests once synced to the chain head
+    #[serde(default = "default::steadystate_concurrency")]
+    pub steadystate_concurrency: usize,
+    /// Maximum number of block windows whose logs are fetched concurrently while catching up to the chain head. Logs are still applied in block order
+    #[serde(default = "default::window_concurrency")]
+    pub window_concurrency: usize,
+    /// Snapshot of the tree loaded at startup if present, and saved periodically and on shutdown. Disabled when unset
+    #[serde(default)]
+    pub snapshot: Option<SnapshotConfig>,
+    /// Directory caching the logs fetched per block window, so that repeated syncs skip the windows already fetched. Disabled when unset
+    #[serde(default)]
+    pub log_cache_dir: Option<PathBuf>,
+}
+
+impl WorldTreeConfig {
+    /// Creates a config with the required fields set and all other fields set to their defaults.
+    pub fn new(
+        world_id_contract_address: Address,
+        creation_block: u64,
+        tree_depth: usize,
+        dense_prefix_depth: usize,
+        tree_history_size: usize,
+    ) -> Self {
+        Self {
+            world_id_contract_address,
+            window_size: default::window_size(),
+            window_tuning: None,
+            creation_block,
+            wait_for_creation_block: false,
+            tree_history_size,
+            tree_depth,
+            dense_prefix_depth,
+            socket_address: default::socket_address(),
+            binds: vec![],
+            insertion_rate_limit: None,
+            log_consistency_check: None,
+            contract_version: ContractVersion::default(),
+            onchain_root_cache_ttl: default::onchain_root_cache_ttl(),
+            root_check_interval_secs: default::root_check_interval_secs(),
+            append_only: None,
+            backfill_concurrency: default::backfill_concurrency(),
+            steadystate_concurrency: default::steadystate_concurrency(),
+            window_concurrency: default::window_concurrency(),
+            snapshot: None,
+            log_cache_dir: None,
+        }
+    }
+
+    /// Checks that the dense prefix fits within the tree, since the tree cannot be constructed otherwise, and that the log consistency check is not combined with a non-strict append-only tree. Warns if the dense prefix is large enough to take a significant amount of memory.
+    pub fn validate(&self) -> eyre::Result<()> {
+        eyre::ensure!(
+            self.dense_prefix_depth <= self.tree_depth,
+            "world_tree.dense_prefix_depth ({}) must not exceed world_tree.tree_depth ({})",
+            self.dense_prefix_depth,
+            self.tree_depth,
+        );
+
+        // Once a deletion is skipped, the local root never matches the roots of the logs again, so every window would fail the check
+        eyre::ensure!(
+            self.log_consistency_check.is_none()
+                || self
+                    .append_only
+                    .as_ref()
+                    .map_or(true, |append_only| append_only.strict),
+            "world_tree.log_consistency_check cannot be combined with a non-strict world_tree.append_only"
+        );
+
+        // The dense prefix allocates every node above `dense_prefix_depth` upfront, i.e. 2^(depth + 1) - 1 hashes of 32 bytes
+        let dense_prefix_bytes = 1u128
+            .checked_shl(self.dense_prefix_depth as u32 + 6)
+            .unwrap_or(u128::MAX);
+        if dense_prefix_bytes > DENSE_PREFIX_MEMORY_WARNING_BYTES {
+            tracing::warn!(
+                dense_prefix_depth = self.dense_prefix_depth,
+                dense_prefix_mib = (dense_prefix_bytes >> 20) as u64,
+                "Dense prefix takes a large amount of memory, consider lowering world_tree.dense_prefix_depth"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sockets to serve the service at, falling back to `socket_address` serving all routes when no `binds` are configured.
+    pub fn binds(&self) -> Vec<BindConfig> {
+        if self.binds.is_empty() {
+            vec![BindConfig::from(self.socket_address)]
+        } else {
+            self.binds.clone()
+        }
+    }
+}
+
+/// Version of the `WorldIDIdentityManager` implementation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractVersion {
+    /// `WorldIDIdentityManagerImplV1`, supporting insertions only
+    V1,
+    /// `WorldIDIdentityManagerImplV2`, supporting insertions and deletions
+    #[default]
+    V2,
+}
+
+impl ContractVersion {
+    /// Returns the selectors of the functions updating the tree in this version.
+    pub fn selectors(&self) -> Vec<Selector> {
+        match self {
+            ContractVersion::V1 => vec![RegisterIdentitiesCall::selector()],
+            ContractVersion::V2 => vec![
+                RegisterIdentitiesCall::selector(),
+                DeleteIdentitiesCall::selector(),
+                DeleteIdentitiesWithDeletionProofAndBatchSizeAndPackedDeletionIndicesAndPreRootCall::selector(),
+            ],
+        }
+    }
+
+    /// Returns whether the function with the given selector updates the tree in this version.
+    pub fn supports(&self, selector: Selector) -> bool {
+        self.selectors().contains(&selector)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogConsistencyCheck {
+    /// Number of times to re-fetch a window whose logs do not chain from the local root to the `post_root` of its last `TreeChanged` event before failing
+    #[serde(default = "default::max_refetches")]
+    pub max_refetches: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WindowTuning {
+    /// Factor the window is multiplied by after `growth_interval` consecutive successful fetches
+    #[serde(default = "default::growth_factor")]
+    pub growth_factor: f64,
+    /// Number of consecutive successful fetches after which the window grows
+    #[serde(default = "default::growth_interval")]
+    pub growth_interval: u32,
+    /// Factor the window is multiplied by after each fetch rejected for exceeding the provider's range or response size limit
+    #[serde(default = "default::shrink_factor")]
+    pub shrink_factor: f64,
+    /// Smallest window, below which rejected fetches are surfaced as errors
+    #[serde(default = "default::min_window")]
+    pub min_window: u64,
+    /// Largest window. Defaults to `window_size`
+    #[serde(default)]
+    pub max_window: Option<u64>,
+}
+
+impl Default for WindowTuning {
+    fn default() -> Self {
+        Self {
+            growth_factor: default::growth_factor(),
+            growth_interval: default::growth_interval(),
+            shrink_factor: default::shrink_factor(),
+            min_window: default::min_window(),
+            max_window: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SnapshotConfig {
+    /// File the snapshot is read from and written to
+    pub path: PathBuf,
+    /// Interval in seconds between snapshots
+    #[serde(default = "default::snapshot_interval_secs")]
+    pub interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AppendOnly {
+    /// Stop syncing when a deletion is encountered. Otherwise the deletion is logged and skipped, leaving the deleted leaves in the tree
+    #[serde(default)]
+    pub strict: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InsertionRateLimit {
+    /// Maximum number of identities that may be inserted within `window_blocks`
+    pub max_insertions: usize,
+    /// Number of blocks over which insertions are counted
+    pub window_blocks: u64,
+    /// Action to take when the limit is exceeded
+    #[serde(default)]
+    pub action: AnomalyAction,
+}
+
+/// Action taken when an anomaly is detected while syncing
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnomalyAction {
+    /// Log an error and continue syncing
+    #[default]
+    Log,
+    /// Log an error, post an alert to the webhook and continue syncing
+    Webhook {
+        #[serde(with = "crate::serde_utils::url")]
+        url: Url,
+    },
+    /// Log an error and stop syncing
+    Halt,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct BindConfig {
+    /// Socket at which to serve the service
+    pub address: SocketAddr,
+    /// Routes exposed on this socket
+    #[serde(default)]
+    pub routes: RouteScope,
+}
+
+impl From<SocketAddr> for BindConfig {
+    fn from(address: SocketAddr) -> Self {
+        Self {
+            address,
+            routes: RouteScope::All,
+        }
+    }
+}
+
+/// Subset of the service routes exposed on a given socket
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteScope {
+    /// Proof routes as well as internal routes
+    #[default]
+    All,
+    /// Proof routes only
+    Public,
+    /// Internal routes only (health, sync status and stats)
+    Internal,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct ProviderConfig {
     /// Ethereum RPC endpoint
     #[serde(with = "crate::serde_utils::url")]
     pub rpc_endpoint: Url,
     /// Request per minute limit
     pub throttle: Option<u32>,
+    /// Ethereum RPC endpoints failed over to, in order, when requests to `rpc_endpoint` persistently fail
+    #[serde(default, with = "crate::serde_utils::url::vec")]
+    pub fallback_rpc_endpoints: Vec<Url>,
+    /// Secondary Ethereum RPC endpoint used to re-fetch windows failing the log consistency check
+    #[serde(default, with = "crate::serde_utils::url::option")]
+    pub secondary_rpc_endpoint: Option<Url>,
+    /// Maximum time in milliseconds to spend on a single request, including all retries, before failing with a timeout error
+    #[serde(default = "default::retry_budget_ms")]
+    pub retry_budget_ms: u64,
+    /// Retries of requests failing with rate limits, timeouts and transient JSON-RPC errors
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Circuit breaker failing requests fast while the provider is persistently failing
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Maximum number of transactions to fetch in a single JSON-RPC batch request while syncing. Transactions are fetched individually when unset
+    #[serde(default)]
+    pub max_batch_size: Option<usize>,
+    /// Block hash expected at a known height, verified at startup to ensure the provider serves the intended chain
+    #[serde(default)]
+    pub expected_block: Option<ExpectedBlock>,
+    /// Headers sent with every request to the RPC endpoints, e.g. API keys or bearer tokens
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// File to record every request to the primary RPC endpoint and its response to, for offline replay. Requires the `record-replay` feature
+    #[serde(default)]
+    pub record_path: Option<PathBuf>,
+    /// Pass requests straight through to the RPC endpoint, without throttling, retries or the circuit breaker. Meant for local nodes such as Anvil, where failures should surface immediately
+    #[serde(default)]
+    pub direct: bool,
+}
+
+impl std::fmt::Debug for ProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let headers: HashMap<_, _> = self
+            .headers
+            .keys()
+            .map(|name| (name, "<redacted>"))
+            .collect();
+
+        f.debug_struct("ProviderConfig")
+            .field("rpc_endpoint", &self.rpc_endpoint)
+            .field("throttle", &self.throttle)
+            .field("fallback_rpc_endpoints", &self.fallback_rpc_endpoints)
+            .field("secondary_rpc_endpoint", &self.secondary_rpc_endpoint)
+            .field("retry_budget_ms", &self.retry_budget_ms)
+            .field("retry", &self.retry)
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("max_batch_size", &self.max_batch_size)
+            .field("expected_block", &self.expected_block)
+            .field("headers", &headers)
+            .field("record_path", &self.record_path)
+            .field("direct", &self.direct)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RetryConfig {
+    /// Maximum number of retries of a request failing with a rate limit or a retryable JSON-RPC error
+    #[serde(default = "default::rate_limit_retries")]
+    pub rate_limit_retries: u32,
+    /// Maximum number of retries of a request timing out
+    #[serde(default = "default::timeout_retries")]
+    pub timeout_retries: u32,
+    /// Backoff in milliseconds before the first retry, growing with every subsequent retry
+    #[serde(default = "default::initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// JSON-RPC error codes retried in addition to the built-in ones, for providers reporting transient failures with their own codes
+    #[serde(default)]
+    pub retryable_error_codes: Vec<i64>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit_retries: default::rate_limit_retries(),
+            timeout_retries: default::timeout_retries(),
+            initial_backoff_ms: default::initial_backoff_ms(),
+            retryable_error_codes: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failed requests after which the circuit opens
+    #[serde(default = "default::failure_threshold")]
+    pub failure_threshold: u32,
+    /// Time in milliseconds for which requests fail fast once the circuit opens, before a trial request tests recovery
+    #[serde(default = "default::cool_down_ms")]
+    pub cool_down_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default::failure_threshold(),
+            cool_down_ms: default::cool_down_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ExpectedBlock {
+    /// Height of the block
+    pub number: u64,
+    /// Expected hash of the block
+    pub hash: H256,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ApiConfig {
+    /// Maximum number of historical tree versions to traverse when serving a proof against a historical root. Unbounded when unset
+    #[serde(default)]
+    pub max_history_depth: Option<usize>,
+    /// Maximum time in milliseconds to spend generating a single inclusion proof
+    #[serde(default = "default::proof_timeout_ms")]
+    pub proof_timeout_ms: u64,
+    /// Keep serving proofs against the last synced tree while the provider circuit breaker is open, flagging their staleness with an `X-Tree-Stale` header. Proofs are rejected with 503 during outages when unset
+    #[serde(default)]
+    pub serve_stale_on_outage: bool,
+    /// Maximum number of commitments accepted by a single batch request
+    #[serde(default = "default::max_batch_size")]
+    pub max_batch_size: usize,
+    /// Naming convention of the fields of JSON responses
+    #[serde(default)]
+    pub field_case: FieldCase,
+    /// Time in milliseconds without a heartbeat after which `/live` reports the process unresponsive
+    #[serde(default = "default::liveness_timeout_ms")]
+    pub liveness_timeout_ms: u64,
+    /// Number of blocks a root must be buried by to be served for `confirmed=true` proof requests
+    #[serde(default = "default::confirmations")]
+    pub confirmations: u64,
+    /// Keep serving proofs from the live tree while a rebuilt tree is synced in the background. Proofs are rejected with 503 during resyncs when unset
+    #[serde(default = "default::serve_during_resync")]
+    pub serve_during_resync: bool,
+    /// Bearer token authenticating requests to `/resync` and the `/admin` routes, which are disabled when unset
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            max_history_depth: None,
+            proof_timeout_ms: default::proof_timeout_ms(),
+            serve_stale_on_outage: false,
+            max_batch_size: default::max_batch_size(),
+            field_case: FieldCase::default(),
+            liveness_timeout_ms: default::liveness_timeout_ms(),
+            confirmations: default::confirmations(),
+            serve_during_resync: default::serve_during_resync(),
+            admin_token: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiConfig")
+            .field("max_history_depth", &self.max_history_depth)
+            .field("proof_timeout_ms", &self.proof_timeout_ms)
+            .field("serve_stale_on_outage", &self.serve_stale_on_outage)
+            .field("max_batch_size", &self.max_batch_size)
+            .field("field_case", &self.field_case)
+            .field("liveness_timeout_ms", &self.liveness_timeout_ms)
+            .field("confirmations", &self.confirmations)
+            .field("serve_during_resync", &self.serve_during_resync)
+            .field(
+                "admin_token",
+                &self.admin_token.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// Naming convention of the fields of JSON responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldCase {
+    /// e.g. `latestSyncedBlock`
+    #[default]
+    Camel,
+    /// e.g. `latest_synced_block`
+    Snake,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SigningConfig {
+    /// Hex encoded private key of the signer
+    pub private_key: String,
+    /// Chain id of the EIP-712 signing domain
+    pub chain_id: u64,
+}
+
+impl std::fmt::Debug for SigningConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigningConfig")
+            .field("private_key", &"<redacted>")
+            .field("chain_id", &self.chain_id)
+            .finish()
+    }
+}
+
+/// Subsystems indexed by a process. The tree and claims can be indexed by separate processes pointed at the same database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexMode {
+    /// Sync the tree and serve the API, without indexing claims
+    #[default]
+    Tree,
+    /// Index claims only, without constructing the tree
+    Claims,
+    /// Sync the tree, serve the API and index claims
+    All,
+}
+
+impl IndexMode {
+    pub fn indexes_tree(&self) -> bool {
+        matches!(self, IndexMode::Tree | IndexMode::All)
+    }
+
+    pub fn indexes_claims(&self) -> bool {
+        matches!(self, IndexMode::Claims | IndexMode::All)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClaimsConfig {
+    /// Address of the `RecurringGrantDrop`
+    pub address: Address,
+    /// Creation block of the `RecurringGrantDrop`
+    pub creation_block: u64,
+    /// Maximum window size when scanning blocks for claim events
+    #[serde(default = "default::window_size")]
+    pub window_size: u64,
+    /// Socket at which to serve the claims API. The API is not served when unset
+    #[serde(default)]
+    pub bind: Option<SocketAddr>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DatabaseConfig {
+    /// Database connection string. Falls back to the `DATABASE_URL` environment variable when unset
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Maximum time in milliseconds to spend flushing pending writes on shutdown before exiting anyway
+    #[serde(default = "default::drain_timeout_ms")]
+    pub drain_timeout_ms: u64,
+    /// Retries of writes failing with transient errors, i.e. serialization failures and deadlocks
+    #[serde(default)]
+    pub write_retry: WriteRetry,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct WriteRetry {
+    /// Maximum number of times a write is re-run after a transient error
+    #[serde(default = "default::max_write_retries")]
+    pub max_retries: u32,
+    /// Backoff in milliseconds before the first retry, doubling with every subsequent retry
+    #[serde(default = "default::write_retry_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+impl Default for WriteRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: default::max_write_retries(),
+            backoff_ms: default::write_retry_backoff_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct RestartPolicy {
+    /// Maximum number of restarts within `window_secs`, beyond which the failure is surfaced
+    #[serde(default = "default::max_restarts")]
+    pub max_restarts: u32,
+    /// Window in seconds over which restarts are counted
+    #[serde(default = "default::restart_window_secs")]
+    pub window_secs: u64,
+    /// Backoff in milliseconds before a failed task is restarted
+    #[serde(default = "default::restart_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: default::max_restarts(),
+            window_secs: default::restart_window_secs(),
+            backoff_ms: default::restart_backoff_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// Maximum sustained number of requests per second accepted from a single client IP, counting each item of a batch
+    pub requests_per_second: NonZeroU32,
+    /// Maximum number of requests a client may send in a burst. Defaults to `requests_per_second`
+    #[serde(default)]
+    pub burst: Option<NonZeroU32>,
+}
+
+impl DatabaseConfig {
+    /// Returns the configured connection string, falling back to the `DATABASE_URL` environment variable. Returns `None` when no database is configured.
+    pub fn url(&self) -> Option<String> {
+        self.url
+            .clone()
+            .or_else(|| std::env::var("DATABASE_URL").ok())
+    }
+}
+
+impl std::fmt::Debug for DatabaseConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatabaseConfig")
+            .field("url", &self.url.as_ref().map(|_| "<redacted>"))
+            .field("drain_timeout_ms", &self.drain_timeout_ms)
+            .field("write_retry", &self.write_retry)
+            .finish()
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            drain_timeout_ms: default::drain_timeout_ms(),
+            write_retry: WriteRetry::default(),
+        }
+    }
 }
 
 mod default {
@@ -76,4 +715,116 @@ mod default {
     pub fn window_size() -> u64 {
         1000
     }
+
+    pub fn snapshot_interval_secs() -> u64 {
+        600
+    }
+
+    pub fn growth_factor() -> f64 {
+        1.25
+    }
+
+    pub fn growth_interval() -> u32 {
+        3
+    }
+
+    pub fn shrink_factor() -> f64 {
+        0.5
+    }
+
+    pub fn min_window() -> u64 {
+        1
+    }
+
+    pub fn serve_during_resync() -> bool {
+        true
+    }
+
+    pub fn confirmations() -> u64 {
+        12
+    }
+
+    pub fn proof_timeout_ms() -> u64 {
+        5000
+    }
+
+    pub fn liveness_timeout_ms() -> u64 {
+        30_000
+    }
+
+    pub fn max_batch_size() -> usize {
+        1000
+    }
+
+    pub fn onchain_root_cache_ttl() -> u64 {
+        2000
+    }
+
+    pub fn root_check_interval_secs() -> u64 {
+        300
+    }
+
+    pub fn backfill_concurrency() -> usize {
+        64
+    }
+
+    pub fn steadystate_concurrency() -> usize {
+        4
+    }
+
+    pub fn window_concurrency() -> usize {
+        4
+    }
+
+    pub fn max_refetches() -> usize {
+        3
+    }
+
+    pub fn failure_threshold() -> u32 {
+        10
+    }
+
+    pub fn cool_down_ms() -> u64 {
+        30_000
+    }
+
+    pub fn retry_budget_ms() -> u64 {
+        60_000
+    }
+
+    pub fn rate_limit_retries() -> u32 {
+        10
+    }
+
+    pub fn timeout_retries() -> u32 {
+        3
+    }
+
+    pub fn initial_backoff_ms() -> u64 {
+        500
+    }
+
+    pub fn drain_timeout_ms() -> u64 {
+        30_000
+    }
+
+    pub fn max_write_retries() -> u32 {
+        3
+    }
+
+    pub fn write_retry_backoff_ms() -> u64 {
+        100
+    }
+
+    pub fn max_restarts() -> u32 {
+        5
+    }
+
+    pub fn restart_window_secs() -> u64 {
+        600
+    }
+
+    pub fn restart_backoff_ms() -> u64 {
+        5000
+    }
 }
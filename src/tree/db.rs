@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+use super::Hash;
+use crate::entities::prelude::{Batches, Deletions, Insertions};
+use crate::entities::{batches, insertions};
+
+/// Loads the leaves persisted in the database, ordered by leaf index.
+///
+/// Identities are appended to the tree in block order, so the position of an insertion record ordered by block corresponds to its leaf index. Leaves of deleted identities are zeroed out.
+///
+/// # Arguments
+///
+/// * `db` - Database the tree updater persisted insertions and deletions to.
+pub async fn load_leaves(db: &DatabaseConnection) -> Result<Vec<Hash>, DbErr> {
+    let deleted: HashSet<String> = Deletions::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|deletion| deletion.pubkey)
+        .collect();
+
+    Insertions::find()
+        .order_by_asc(insertions::Column::InsertedInBlock)
+        .order_by_asc(insertions::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|insertion| {
+            if deleted.contains(&insertion.pubkey) {
+                return Ok(Hash::ZERO);
+            }
+
+            Hash::from_str(&insertion.pubkey)
+                .map_err(|err| DbErr::Custom(err.to_string()))
+        })
+        .collect()
+}
+
+/// Returns the block of the most recent batch persisted in the database, or `None` if no batch has been persisted.
+///
+/// # Arguments
+///
+/// * `db` - Database the tree updater persisted batches to.
+pub async fn latest_batch_block(
+    db: &DatabaseConnection,
+) -> Result<Option<u64>, DbErr> {
+    let batch = Batches::find()
+        .order_by_desc(batches::Column::Block)
+        .one(db)
+        .await?;
+
+    Ok(batch.map(|batch| batch.block as u64))
+}
+
+/// Returns the insertion record of the given identity, or `None` if the identity has not been persisted.
+///
+/// # Arguments
+///
+/// * `db` - Database the tree updater persisted insertions to.
+/// * `identity` - Identity commitment to look up.
+pub async fn find_insertion(
+    db: &DatabaseConnection,
+    identity: Hash,
+) -> Result<Option<insertions::Model>, DbErr> {
+    Insertions::find()
+        .filter(insertions::Column::Pubkey.eq(identity.to_string()))
+        .order_by_asc(insertions::Column::InsertedInBlock)
+        .one(db)
+        .await
+}
@@ -0,0 +1,308 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sea_orm::sqlx;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, RuntimeErr,
+};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+
+use super::config::WriteRetry;
+use crate::entities::batches::{
+    ActiveModel as BatchActiveModel, Column as BatchColumn,
+};
+use crate::entities::deletions::{
+    ActiveModel as DeletionActiveModel, Column as DeletionColumn,
+};
+use crate::entities::insertions::{
+    ActiveModel as InsertionActiveModel, Column as InsertionColumn,
+};
+use crate::entities::prelude::{Batches, Deletions, Insertions};
+
+/// Postgres error code of serialization failures.
+const SERIALIZATION_FAILURE: &str = "40001";
+/// Postgres error code of detected deadlocks.
+const DEADLOCK_DETECTED: &str = "40P01";
+
+/// Records indexed while syncing the tree, queued to be persisted by the `DbWriter`.
+pub enum DbWrite {
+    Insertions(Vec<InsertionActiveModel>),
+    Deletions(Vec<DeletionActiveModel>),
+    Batch(BatchActiveModel),
+    /// Removes the records indexed after the given block, which were reorged out
+    Rollback {
+        after_block: u64,
+    },
+}
+
+impl DbWrite {
+    /// Number of records contained in the write.
+    pub fn len(&self) -> usize {
+        match self {
+            DbWrite::Insertions(insertions) => insertions.len(),
+            DbWrite::Deletions(deletions) => deletions.len(),
+            DbWrite::Batch(_) | DbWrite::Rollback { .. } => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Executes the write against the given connection or transaction. The write is kept so that it can be re-run after a transient error.
+    async fn exec<C: ConnectionTrait>(&self, db: &C) -> Result<(), DbErr> {
+        match self {
+            DbWrite::Insertions(insertions) => {
+                Insertions::insert_many(insertions.clone()).exec(db).await?;
+            }
+            DbWrite::Deletions(deletions) => {
+                Deletions::insert_many(deletions.clone()).exec(db).await?;
+            }
+            DbWrite::Batch(batch) => {
+                Batches::insert(batch.clone()).exec(db).await?;
+            }
+            DbWrite::Rollback { after_block } => {
+                let after_block = *after_block as i64;
+
+                Insertions::delete_many()
+                    .filter(InsertionColumn::InsertedInBlock.gt(after_block))
+                    .exec(db)
+                    .await?;
+                Deletions::delete_many()
+                    .filter(DeletionColumn::DeletedAtBlock.gt(after_block))
+                    .exec(db)
+                    .await?;
+                Batches::delete_many()
+                    .filter(BatchColumn::Block.gt(after_block))
+                    .exec(db)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Persists indexed records on a dedicated task so that database latency does not stall tree syncing.
+pub struct DbWriter {
+    /// Queue of pending writes consumed by the writer task
+    sender: mpsc::UnboundedSender<DbWrite>,
+    /// Signals the writer task to stop accepting new writes and drain the queue
+    shutdown: Arc<Notify>,
+    /// Number of queued records that have not been written yet
+    pending: Arc<AtomicUsize>,
+    /// Handle of the writer task, taken on shutdown
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Returns whether the error is transient, i.e. a serialization failure or deadlock caused by concurrent writers, such that re-running the write may succeed. Other errors, e.g. constraint violations, are permanent.
+pub fn is_transient(err: &DbErr) -> bool {
+    let (DbErr::Exec(RuntimeErr::SqlxError(sqlx::Error::Database(err)))
+    | DbErr::Query(RuntimeErr::SqlxError(sqlx::Error::Database(err)))) = err
+    else {
+        return false;
+    };
+
+    matches!(
+        err.code().as_deref(),
+        Some(SERIALIZATION_FAILURE | DEADLOCK_DETECTED)
+    )
+}
+
+impl DbWriter {
+    /// Spawns the writer task persisting queued records to the given database.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - Database to persist the indexed records to.
+    /// * `write_retry` - Retries of writes failing with transient errors.
+    pub fn spawn(db: DatabaseConnection, write_retry: WriteRetry) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let shutdown = Arc::new(Notify::new());
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        let handle = tokio::spawn(Self::run(
+            db,
+            write_retry,
+            receiver,
+            shutdown.clone(),
+            pending.clone(),
+        ));
+
+        Self {
+            sender,
+            shutdown,
+            pending,
+            handle: Mutex::new(Some(handle)),
+        }
+    }
+
+    /// Queues records to be persisted. Writes submitted after shutdown has started are dropped and logged.
+    pub fn write(&self, write: DbWrite) {
+        if write.is_empty() {
+            return;
+        }
+
+        let records = write.len();
+        self.pending.fetch_add(records, Ordering::SeqCst);
+
+        if self.sender.send(write).is_err() {
+            self.pending.fetch_sub(records, Ordering::SeqCst);
+            tracing::error!(
+                ?records,
+                "DB writer is shut down, dropping records"
+            );
+        }
+    }
+
+    /// Number of queued records that have not been written yet.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Stops accepting new writes, flushes the remaining queue and waits for it to be written. Each queued write is flushed on its own, so that a write failing permanently only drops its own records.
+    ///
+    /// If the queue is not drained within `drain_timeout`, the number of unwritten records is logged and the function returns anyway so that shutdown cannot hang on an unresponsive database.
+    ///
+    /// # Arguments
+    ///
+    /// * `drain_timeout` - Maximum time to wait for the queue to be drained.
+    pub async fn shutdown(&self, drain_timeout: Duration) {
+        let Some(handle) = self
+            .handle
+            .lock()
+            .expect("DB writer handle lock poisoned")
+            .take()
+        else {
+            return;
+        };
+
+        tracing::info!(pending = self.pending(), "Draining DB writer queue");
+        self.shutdown.notify_one();
+
+        match tokio::time::timeout(drain_timeout, handle).await {
+            Ok(Ok(())) => {
+                tracing::info!("DB writer queue drained");
+            }
+            Ok(Err(err)) => {
+                tracing::error!(
+                    ?err,
+                    unwritten = self.pending(),
+                    "DB writer task failed while draining"
+                );
+            }
+            Err(_) => {
+                tracing::error!(
+                    ?drain_timeout,
+                    unwritten = self.pending(),
+                    "Timed out draining DB writer queue"
+                );
+            }
+        }
+    }
+
+    async fn run(
+        db: DatabaseConnection,
+        write_retry: WriteRetry,
+        mut receiver: mpsc::UnboundedReceiver<DbWrite>,
+        shutdown: Arc<Notify>,
+        pending: Arc<AtomicUsize>,
+    ) {
+        loop {
+            tokio::select! {
+                write = receiver.recv() => {
+                    let Some(write) = write else {
+                        return;
+                    };
+
+                    Self::flush(&db, write_retry, &pending, write).await;
+                }
+                _ = shutdown.notified() => break,
+            }
+        }
+
+        // Reject new writes while still allowing the buffered ones to be received
+        receiver.close();
+
+        while let Some(write) = receiver.recv().await {
+            Self::flush(&db, write_retry, &pending, write).await;
+        }
+    }
+
+    /// Writes a queued write, dropping its records if it fails permanently.
+    async fn flush(
+        db: &DatabaseConnection,
+        write_retry: WriteRetry,
+        pending: &AtomicUsize,
+        write: DbWrite,
+    ) {
+        let records = write.len();
+        let result =
+            Self::with_retry(write_retry, records, || write.exec(db)).await;
+
+        match result {
+            Ok(()) => {
+                pending.fetch_sub(records, Ordering::SeqCst);
+            }
+            Err(err) => {
+                tracing::error!(
+                    ?err,
+                    ?records,
+                    "Failed to write records to db"
+                );
+                Self::drop_records(pending, records);
+            }
+        }
+    }
+
+    /// Accounts for records whose write failed permanently, so that they no longer count as pending.
+    fn drop_records(pending: &AtomicUsize, records: usize) {
+        pending.fetch_sub(records, Ordering::SeqCst);
+        metrics::counter!(
+            "tree_availability.db_writer.dropped_records",
+            records as u64
+        );
+    }
+
+    /// Runs a write, re-running it with exponential backoff while it fails with a transient error, up to `write_retry.max_retries` times. Permanent errors are returned immediately.
+    async fn with_retry<F, Fut>(
+        write_retry: WriteRetry,
+        records: usize,
+        mut write: F,
+    ) -> Result<(), DbErr>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<(), DbErr>>,
+    {
+        let mut retries = 0;
+        let mut backoff = Duration::from_millis(write_retry.backoff_ms);
+
+        loop {
+            match write().await {
+                Err(err)
+                    if is_transient(&err)
+                        && retries < write_retry.max_retries =>
+                {
+                    retries += 1;
+                    metrics::increment_counter!(
+                        "tree_availability.db_writer.retry"
+                    );
+                    tracing::warn!(
+                        ?err,
+                        ?records,
+                        ?retries,
+                        ?backoff,
+                        "Transient error writing records, retrying"
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
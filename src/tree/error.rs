@@ -1,9 +1,13 @@
 use ethers::prelude::{AbiError, ContractError};
 use ethers::providers::{Middleware, ProviderError};
-use ethers::types::Log;
+use ethers::types::{Log, H160, H256};
+use sea_orm::DbErr;
 use thiserror::Error;
 use tokio::sync::mpsc::error::SendError;
 
+use super::config::ContractVersion;
+use super::Hash;
+
 #[derive(Error, Debug)]
 pub enum TreeAvailabilityError<M>
     where
@@ -17,10 +21,30 @@ pub enum TreeAvailabilityError<M>
     TransactionHashNotFound,
     #[error("Block number was not found")]
     BlockNumberNotFound,
+    #[error("Transaction index was not found")]
+    TransactionIndexNotFound,
+    #[error("Block {0} was not found")]
+    BlockNotFound(u64),
     #[error("Transaction was not found from hash")]
     TransactionNotFound,
     #[error("Unrecognized function selector")]
     UnrecognizedFunctionSelector,
+    #[error("Deletion in transaction {tx_hash:?} while the tree is append-only")]
+    UnexpectedDeletion { tx_hash: H256 },
+    #[error("Logs between blocks {from_block} and {to_block} do not chain from the local root")]
+    LogGap { from_block: u64, to_block: u64 },
+    #[error("Inserted {insertions} identities within the block window ending at block {block_number}, exceeding the insertion rate limit")]
+    InsertionRateExceeded { block_number: u64, insertions: usize },
+    #[error("Batch in transaction {tx_hash:?} applies on top of root {expected:?}, but the local root is {actual:?}")]
+    RootMismatch {
+        tx_hash: H256,
+        /// Pre-root of the batch
+        expected: Hash,
+        /// Root of the local tree
+        actual: Hash,
+    },
+    #[error("Reorg of at least {depth} blocks reaches past the tree history")]
+    ReorgDetected { depth: u64 },
     #[error("Middleware error")]
     MiddlewareError(<M as Middleware>::Error),
     #[error("Provider error")]
@@ -35,6 +59,8 @@ pub enum TreeAvailabilityError<M>
     HyperError(#[from] hyper::Error),
     #[error(transparent)]
     SendLogError(#[from] SendError<Log>),
+    #[error(transparent)]
+    PreflightError(#[from] PreflightError<M>),
 }
 
 #[derive(Error, Debug)]
@@ -54,6 +80,8 @@ pub enum GrantClaimedError<M>
     TransactionNotFound,
     #[error("Unrecognized function selector")]
     UnrecognizedFunctionSelector,
+    #[error("Log index was not found")]
+    LogIndexNotFound,
     #[error("Middleware error")]
     MiddlewareError(<M as Middleware>::Error),
     #[error("Provider error")]
@@ -68,10 +96,116 @@ pub enum GrantClaimedError<M>
     HyperError(#[from] hyper::Error),
     #[error(transparent)]
     SendLogError(#[from] SendError<Log>),
+    #[error(transparent)]
+    DbError(#[from] DbErr),
+}
+
+#[derive(Error, Debug)]
+pub enum PreflightError<M>
+where
+    M: Middleware + 'static,
+{
+    #[error("Middleware error")]
+    MiddlewareError(<M as Middleware>::Error),
+    #[error("Block {0} was not found")]
+    BlockNotFound(u64),
+    #[error("No contract code at {address:?}")]
+    NoContractCode { address: H160 },
+    #[error("creation_block {creation_block} is ahead of chain head {head}")]
+    CreationBlockAhead { creation_block: u64, head: u64 },
+    #[error("Block hash mismatch at block {number}: expected {expected:?}, found {actual:?}")]
+    BlockHashMismatch {
+        number: u64,
+        expected: H256,
+        actual: Option<H256>,
+    },
+    #[error("Contract error")]
+    ContractError(#[from] ContractError<M>),
+    #[error("Contract version mismatch: configured {configured:?}, deployed contract is {detected:?}")]
+    ContractVersionMismatch {
+        configured: ContractVersion,
+        detected: ContractVersion,
+    },
+    #[error("Root mismatch at block {block}: rebuilt {rebuilt:?}, onchain {onchain:?}")]
+    RootMismatch {
+        block: u64,
+        rebuilt: Hash,
+        onchain: Hash,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("Snapshot write task failed")]
+    WriteTaskFailed(#[from] tokio::task::JoinError),
+    #[error("Snapshot format version {snapshot} is not supported, expected {supported}")]
+    VersionMismatch { snapshot: u32, supported: u32 },
+    #[error("Snapshot was taken from a tree of depth {snapshot}, configured depth is {configured}")]
+    DepthMismatch { snapshot: usize, configured: usize },
+    #[error("Snapshot was taken from contract {snapshot:?}, configured contract is {configured:?}")]
+    AddressMismatch { snapshot: H160, configured: H160 },
+    #[error(
+        "Restored root {restored:?} does not match snapshot root {snapshot:?}"
+    )]
+    RootMismatch { snapshot: Hash, restored: Hash },
+    #[error("Tree root {stored:?} does not match root {recomputed:?} recomputed from its leaves")]
+    IntegrityMismatch { stored: Hash, recomputed: Hash },
+    #[error("Failed to fetch block {0}")]
+    BlockUnavailable(u64),
+    #[error("Snapshot block {block} has hash {snapshot:?}, but the canonical block has hash {canonical:?}")]
+    BlockHashMismatch {
+        block: u64,
+        snapshot: H256,
+        canonical: Option<H256>,
+    },
+    #[error("The tree is divergent from the onchain tree")]
+    TreeDivergent,
 }
 
 #[derive(Error, Debug)]
 pub enum TreeError {
     #[error("The world tree is not fully synced")]
     TreeNotSynced,
+    #[error("Root is {depth} versions back in the tree history, exceeding the limit of {max_depth}")]
+    HistoryLimitExceeded { depth: usize, max_depth: usize },
+    #[error("Timed out generating inclusion proof")]
+    ProofTimeout,
+    #[error("Inclusion proof task failed")]
+    ProofTaskFailed,
+    #[error("Root has been evicted from the tree history")]
+    RootEvicted { oldest_root: Hash, oldest_block: u64 },
+    #[error("Root signing is not configured")]
+    SigningDisabled,
+    #[error("Prometheus metrics are not enabled")]
+    MetricsDisabled,
+    #[error("Failed to sign root")]
+    SigningFailed,
+    #[error("Failed to fetch the onchain root")]
+    OnchainRootUnavailable,
+    #[error("The provider is unavailable and the tree cannot be synced")]
+    ProviderOutage,
+    #[error("Batch of {size} items exceeds the limit of {max_size}")]
+    BatchTooLarge { size: usize, max_size: usize },
+    #[error("No root in the tree history is buried by {confirmations} blocks")]
+    NoConfirmedRoot { confirmations: u64 },
+    #[error("The tree is being resynced")]
+    ResyncInProgress,
+    #[error("Leaf is in the tree")]
+    LeafPresent,
+    #[error("Leaf is not known to have been deleted from the tree")]
+    LeafIndexUnknown,
+    #[error("No version of the tree with a requested root holds the leaf")]
+    NoMatchingRoot,
+    #[error("Admin routes are not configured")]
+    AdminDisabled,
+    #[error("Missing or invalid admin token")]
+    Unauthorized,
+    #[error("The tree history does not reach back to block {0}")]
+    ResyncBlockUnavailable(u64),
+    #[error("Too many requests")]
+    RateLimited,
 }
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::body::{self, Body};
+use axum::extract::State;
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+
+use super::config::{ApiConfig, FieldCase};
+
+/// Rewrites the field names of JSON responses to the configured `api.field_case`. Responses are serialized in camelCase, so they are passed through untouched unless snake_case is configured.
+pub async fn middleware(
+    State(api): State<Arc<ApiConfig>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let response = next.run(request).await;
+
+    if api.field_case == FieldCase::Camel || !is_json(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!(?err, "Failed to read response body");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let body = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => serde_json::to_vec(&to_snake_case_keys(value))
+            .expect("Serializing a JSON value cannot fail"),
+        // Not valid JSON, pass the body through as is
+        Err(_) => body.to_vec(),
+    };
+
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Response::from_parts(parts, body::boxed(Body::from(body)))
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| {
+            content_type.starts_with("application/json")
+        })
+}
+
+/// Recursively converts the camelCase keys of JSON objects to snake_case.
+pub fn to_snake_case_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    (to_snake_case(&key), to_snake_case_keys(value))
+                })
+                .collect(),
+        ),
+        Value::Array(values) => {
+            Value::Array(values.into_iter().map(to_snake_case_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// Converts a camelCase name to snake_case. Only uppercase letters following a lowercase letter or a digit start a new word, so that names that are not camelCase (e.g. the `Left` and `Right` proof branches) are left untouched.
+fn to_snake_case(name: &str) -> String {
+    let mut snake_case = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+
+    for c in name.chars() {
+        let starts_word = c.is_ascii_uppercase()
+            && previous.is_some_and(|previous| {
+                previous.is_ascii_lowercase() || previous.is_ascii_digit()
+            });
+
+        if starts_word {
+            snake_case.push('_');
+            snake_case.push(c.to_ascii_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+
+        previous = Some(c);
+    }
+
+    snake_case
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_to_snake_case_keys() {
+        let value = json!({
+            "root": "0x1",
+            "latestSyncedBlock": 1,
+            "proof": [{ "Left": "0x2" }, { "Right": "0x3" }],
+            "insertion": { "insertedInBlock": 2 },
+            "zeroHashes": ["0x0"],
+        });
+
+        let expected = json!({
+            "root": "0x1",
+            "latest_synced_block": 1,
+            "proof": [{ "Left": "0x2" }, { "Right": "0x3" }],
+            "insertion": { "inserted_in_block": 2 },
+            "zero_hashes": ["0x0"],
+        });
+
+        assert_eq!(to_snake_case_keys(value), expected);
+    }
+}
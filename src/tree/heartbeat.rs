@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Interval at which the heartbeat task beats.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Heartbeat updated by a task on the async runtime, used to tell whether the process is still responsive regardless of the sync state of the tree.
+///
+/// If the runtime is blocked or deadlocked, the heartbeat task stops being polled and the time since the last beat grows past the liveness timeout.
+pub struct Heartbeat {
+    /// Reference point of `last_beat`
+    start: Instant,
+    /// Time of the last beat, in milliseconds since `start`
+    last_beat: AtomicU64,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_beat: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a beat at the current time.
+    pub fn beat(&self) {
+        let now = self.start.elapsed().as_millis() as u64;
+        self.last_beat.store(now, Ordering::SeqCst);
+    }
+
+    /// Time elapsed since the last beat.
+    pub fn elapsed(&self) -> Duration {
+        let now = self.start.elapsed().as_millis() as u64;
+        let last_beat = self.last_beat.load(Ordering::SeqCst);
+
+        Duration::from_millis(now.saturating_sub(last_beat))
+    }
+
+    /// Spawns the task beating every `HEARTBEAT_INTERVAL` until `shutdown` is cancelled.
+    pub fn spawn(
+        self: Arc<Self>,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = interval.tick() => self.beat(),
+                }
+            }
+        })
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_elapsed() {
+        let heartbeat = Heartbeat::new();
+        let delay = Duration::from_millis(100);
+
+        heartbeat.beat();
+        std::thread::sleep(delay);
+        assert!(heartbeat.elapsed() >= delay);
+
+        heartbeat.beat();
+        assert!(heartbeat.elapsed() < delay);
+    }
+}
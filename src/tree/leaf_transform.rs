@@ -0,0 +1,28 @@
+use super::Hash;
+
+/// Transform applied to identity commitments before they are inserted into the tree as leaves. This is an advanced extension point for deployments whose contract stores a hash or transform of the raw commitment rather than the commitment itself. Vanilla WorldID deployments should keep the default `IdentityTransform`.
+///
+/// The same transform is applied to the commitments queried through the proof endpoints, so clients keep querying raw commitments. Leaves persisted to the database are stored transformed, so the transform must not change across restarts of a service rebuilding from the database.
+pub trait LeafTransform: Send + Sync {
+    /// Maps a raw identity commitment to the leaf stored in the tree.
+    fn transform(&self, commitment: Hash) -> Hash;
+}
+
+/// Stores identity commitments in the tree as is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityTransform;
+
+impl LeafTransform for IdentityTransform {
+    fn transform(&self, commitment: Hash) -> Hash {
+        commitment
+    }
+}
+
+impl<F> LeafTransform for F
+where
+    F: Fn(Hash) -> Hash + Send + Sync,
+{
+    fn transform(&self, commitment: Hash) -> Hash {
+        self(commitment)
+    }
+}
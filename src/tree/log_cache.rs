@@ -0,0 +1,167 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use ethers::types::Log;
+
+/// Number of blocks below the end of a scanned range within which fetched logs are not cached, since they may still be reorged out.
+pub const LOG_CACHE_MIN_DEPTH: u64 = 64;
+
+/// On-disk cache of the logs fetched for block ranges, holding one JSON file per range. Lets a sync that was interrupted, or that is repeated during development, skip the ranges already fetched from the provider.
+///
+/// Entries are not scoped to a contract, so a cache directory must only be used with a single `WorldIDIdentityManager`.
+#[derive(Debug, Clone)]
+pub struct LogCache {
+    /// Directory holding the cached ranges, created on the first write
+    dir: PathBuf,
+}
+
+impl LogCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the logs cached for exactly `from_block..=to_block`, or `None` if the range is not cached. An unreadable entry is treated as missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive).
+    pub fn get(&self, from_block: u64, to_block: u64) -> Option<Vec<Log>> {
+        let path = self.path(from_block, to_block);
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                tracing::warn!(?err, ?path, "Failed to open cached logs");
+                return None;
+            }
+        };
+
+        match serde_json::from_reader(BufReader::new(file)) {
+            Ok(logs) => {
+                metrics::increment_counter!(
+                    "tree_availability.block_scanner.log_cache_hit"
+                );
+                Some(logs)
+            }
+            Err(err) => {
+                tracing::warn!(?err, ?path, "Failed to read cached logs");
+                None
+            }
+        }
+    }
+
+    /// Caches the logs fetched for `from_block..=to_block`. Failures are logged rather than returned, since the cache is only an optimization.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive).
+    /// * `logs` - All logs within the range.
+    pub fn insert(&self, from_block: u64, to_block: u64, logs: &[Log]) {
+        if let Err(err) = self.write(from_block, to_block, logs) {
+            tracing::warn!(
+                ?err,
+                ?from_block,
+                ?to_block,
+                "Failed to cache logs"
+            );
+        }
+    }
+
+    /// Removes the cached ranges overlapping `from_block..=to_block`, e.g. once their logs turn out to be incomplete.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block of the range.
+    /// * `to_block` - Last block of the range (inclusive).
+    pub fn remove_range(&self, from_block: u64, to_block: u64) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some((start, end)) = parse_range(&path) else {
+                continue;
+            };
+
+            if start <= to_block && end >= from_block {
+                if let Err(err) = fs::remove_file(&path) {
+                    tracing::warn!(?err, ?path, "Failed to remove cached logs");
+                }
+            }
+        }
+    }
+
+    /// Writes the entry to a temporary file first and renames it into place, so that an interrupted write never leaves a truncated entry behind.
+    fn write(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        logs: &[Log],
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let path = self.path(from_block, to_block);
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, logs)?;
+        writer.flush()?;
+
+        fs::rename(&tmp_path, path)
+    }
+
+    fn path(&self, from_block: u64, to_block: u64) -> PathBuf {
+        self.dir.join(format!("{from_block}-{to_block}.json"))
+    }
+}
+
+/// Parses the block range of a cache entry from its file name.
+fn parse_range(path: &Path) -> Option<(u64, u64)> {
+    if path.extension()? != "json" {
+        return None;
+    }
+
+    let (from_block, to_block) = path.file_stem()?.to_str()?.split_once('-')?;
+
+    Some((from_block.parse().ok()?, to_block.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H160;
+
+    use super::*;
+
+    #[test]
+    fn test_log_cache() {
+        let dir = std::env::temp_dir()
+            .join(format!("world-tree-log-cache-{}", std::process::id()));
+        let log_cache = LogCache::new(&dir);
+
+        let logs = vec![Log {
+            address: H160::repeat_byte(1),
+            block_number: Some(15.into()),
+            ..Default::default()
+        }];
+
+        assert_eq!(log_cache.get(10, 19), None);
+
+        log_cache.insert(10, 19, &logs);
+        log_cache.insert(20, 29, &[]);
+        assert_eq!(log_cache.get(10, 19), Some(logs));
+        assert_eq!(log_cache.get(20, 29), Some(vec![]));
+
+        // Entries are keyed by their exact range
+        assert_eq!(log_cache.get(10, 29), None);
+
+        log_cache.remove_range(25, 40);
+        assert!(log_cache.get(10, 19).is_some());
+        assert_eq!(log_cache.get(20, 29), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use metrics_exporter_prometheus::{
+    BuildError, Matcher, PrometheusBuilder, PrometheusHandle,
+};
+
+/// Latest block synced into the tree.
+pub const LATEST_SYNCED_BLOCK: &str =
+    "tree_availability.tree_updater.latest_synced_block";
+/// Chain head observed at the latest sync.
+pub const CHAIN_HEAD_BLOCK: &str =
+    "tree_availability.tree_updater.chain_head_block";
+/// Number of blocks between the chain head and the latest synced block.
+pub const BLOCKS_BEHIND: &str = "tree_availability.tree_updater.blocks_behind";
+/// Number of `TreeChanged` transactions applied to the tree.
+pub const TREE_UPDATES: &str = "tree_availability.tree_updater.tree_updates";
+/// Number of requests served by `/inclusionProof`.
+pub const INCLUSION_PROOF_REQUESTS: &str =
+    "tree_availability.inclusion_proof.requests";
+/// Time spent generating and serializing an inclusion proof, in seconds.
+pub const INCLUSION_PROOF_LATENCY: &str =
+    "tree_availability.inclusion_proof.latency";
+/// Buckets of `INCLUSION_PROOF_LATENCY` in seconds, from 100µs to 1s.
+pub const INCLUSION_PROOF_LATENCY_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+    0.25, 0.5, 1.0,
+];
+/// Number of proof requests against a root evicted from the tree history.
+pub const EVICTED_ROOT_REQUESTS: &str =
+    "tree_availability.tree_data.evicted_root_requests";
+/// Number of proof requests rejected by the per-client rate limit.
+pub const RATE_LIMITED_REQUESTS: &str =
+    "tree_availability.rate_limit.rejected_requests";
+
+/// Installs a Prometheus recorder as the global metrics recorder. The returned handle renders every metric recorded through the `metrics` facade in the Prometheus text exposition format, see the `/metrics` route.
+pub fn install_prometheus_recorder() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(INCLUSION_PROOF_LATENCY.to_string()),
+            INCLUSION_PROOF_LATENCY_BUCKETS,
+        )?
+        .install_recorder()
+}
+
+/// Records the latest synced block against the chain head, reporting how far the tree lags behind the chain.
+///
+/// # Arguments
+///
+/// * `latest_synced_block` - Latest block synced into the tree.
+/// * `chain_head` - Chain head the tree was synced to.
+pub fn record_sync_progress(latest_synced_block: u64, chain_head: u64) {
+    metrics::gauge!(LATEST_SYNCED_BLOCK, latest_synced_block as f64);
+    metrics::gauge!(CHAIN_HEAD_BLOCK, chain_head as f64);
+    metrics::gauge!(
+        BLOCKS_BEHIND,
+        chain_head.saturating_sub(latest_synced_block) as f64
+    );
+}
+
+/// Records `count` transactions applied to the tree.
+pub fn record_tree_updates(count: u64) {
+    metrics::counter!(TREE_UPDATES, count);
+}
+
+/// Records a request served by `/inclusionProof`.
+pub fn record_inclusion_proof_request() {
+    metrics::increment_counter!(INCLUSION_PROOF_REQUESTS);
+}
+
+/// Records the time spent generating and serializing an inclusion proof.
+pub fn record_inclusion_proof_latency(latency: Duration) {
+    metrics::histogram!(INCLUSION_PROOF_LATENCY, latency.as_secs_f64());
+}
+
+/// Records a proof request that missed because its root was evicted from the tree history.
+pub fn record_evicted_root_request() {
+    metrics::increment_counter!(EVICTED_ROOT_REQUESTS);
+}
+
+/// Records a proof request rejected by the per-client rate limit.
+pub fn record_rate_limited_request() {
+    metrics::increment_counter!(RATE_LIMITED_REQUESTS);
+}
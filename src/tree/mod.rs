@@ -1,7 +1,22 @@
+pub mod attestation;
+pub mod batch_client;
 pub mod block_scanner;
 pub mod config;
+pub mod db;
+pub mod db_writer;
 pub mod error;
+pub mod field_case;
+pub mod heartbeat;
+pub mod leaf_transform;
+pub mod log_cache;
+pub mod metrics;
+pub mod onchain_root;
+pub mod preflight;
+pub mod rate_limit;
+pub mod resync;
 pub mod service;
+pub mod snapshot;
+pub mod supervisor;
 pub mod tree_data;
 pub mod tree_updater;
 
@@ -9,24 +24,28 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use error::TreeAvailabilityError;
-use ethers::providers::Middleware;
-use ethers::types::H160;
-use sea_orm::Database;
+use error::{TreeAvailabilityError, TreeError};
+use ethers::providers::{Middleware, Provider, StreamExt, Ws};
 use semaphore::lazy_merkle_tree::{Canonical, LazyMerkleTree};
 use semaphore::merkle_tree::Hasher;
 use semaphore::poseidon_tree::PoseidonHash;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 
+use self::config::WorldTreeConfig;
+use self::db_writer::DbWriter;
+use self::resync::ResyncStatus;
 use self::tree_data::TreeData;
-use self::tree_updater::TreeUpdater;
+use self::tree_updater::{tree_changed_filter, TreeUpdater};
 
 pub type PoseidonTree<Version> = LazyMerkleTree<PoseidonHash, Version>;
 pub type Hash = <PoseidonHash as Hasher>::Hash;
 
 pub const SYNC_TO_HEAD_SLEEP_SECONDS: u64 = 5;
+/// Interval at which the tree is synced while subscribed to `TreeChanged` logs, in case a notification is missed.
+pub const SUBSCRIPTION_FALLBACK_SLEEP_SECONDS: u64 = 60;
 
 /// An abstraction over a tree with a history of changes
 ///
@@ -39,6 +58,16 @@ pub struct WorldTree<M: Middleware> {
     pub tree_updater: Arc<TreeUpdater<M>>,
     /// Boolean to indicate when the tree state is synced wth the chain head upon spawning the `WorldTree`.
     pub synced: Arc<AtomicBool>,
+    /// Writer persisting indexed records to the database. If `None`, the tree is synced without persisting anything.
+    pub db_writer: Option<Arc<DbWriter>>,
+    /// State of the background rebuild of the tree, if any.
+    pub resync: Arc<ResyncStatus>,
+    /// WebSocket provider notifying the live sync of new `TreeChanged` logs. If `None`, the chain head is polled every `SYNC_TO_HEAD_SLEEP_SECONDS`.
+    pub log_subscriber: Option<Arc<Provider<Ws>>>,
+    /// Held while syncing the live tree, so that a rebuilt tree can be caught up and swapped in without the live tree advancing in between.
+    sync_lock: Arc<Mutex<()>>,
+    /// Configuration the tree is rebuilt from on resync.
+    config: WorldTreeConfig,
 }
 
 impl<M: Middleware> WorldTree<M> {
@@ -47,60 +76,403 @@ impl<M: Middleware> WorldTree<M> {
     /// # Arguments
     ///
     /// * `tree` - The `PoseidonTree` used for the merkle tree representation.
-    /// * `tree_history_size` - The number of historical tree roots to keep in memory.
-    /// * `address` - The smart contract address of the `WorldIDIdentityManager`.
-    /// * `creation_block` - The block number at which the contract was deployed.
+    /// * `config` - Configuration of the World Tree, including the number of historical tree roots to keep in memory and the `WorldIDIdentityManager` to sync from.
     /// * `middleware` - Provider to interact with Ethereum.
     pub fn new(
         tree: PoseidonTree<Canonical>,
-        tree_history_size: usize,
-        address: H160,
-        creation_block: u64,
-        window_size: u64,
+        config: &WorldTreeConfig,
         middleware: Arc<M>,
     ) -> Self {
         Self {
             tree_data: Arc::new(RwLock::new(TreeData::new(
                 tree,
-                tree_history_size,
+                config.tree_history_size,
             ))),
-            tree_updater: Arc::new(TreeUpdater::new(
-                address,
-                creation_block,
-                window_size,
-                middleware,
-            )),
+            tree_updater: Arc::new(TreeUpdater::new(config, middleware)),
             synced: Arc::new(AtomicBool::new(false)),
+            db_writer: None,
+            resync: Arc::new(ResyncStatus::default()),
+            log_subscriber: None,
+            sync_lock: Arc::new(Mutex::new(())),
+            config: config.clone(),
         }
     }
 
+    /// Subscribes to the root of the tree, broadcast by the tree updater after each applied batch that changed it. Lets in-process consumers react to new roots without polling `tree_data`.
+    pub fn subscribe(&self) -> broadcast::Receiver<Hash> {
+        self.tree_updater.subscribe_roots()
+    }
+
+    /// Atomically replaces the live `TreeData` with a rebuilt one, returning the previous version.
+    ///
+    /// The swap happens within a single, constant-time write critical section, so in-flight reads complete against the previous version and subsequent reads observe the new version. Readers never observe a partially applied tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree_data` - The rebuilt tree to publish.
+    pub async fn swap_tree_data(&self, tree_data: TreeData) -> TreeData {
+        let new_root = tree_data.tree.root();
+
+        let previous =
+            std::mem::replace(&mut *self.tree_data.write().await, tree_data);
+
+        tracing::info!(
+            previous_root = ?previous.tree.root(),
+            ?new_root,
+            "Swapped in rebuilt tree"
+        );
+
+        previous
+    }
+
+    /// Spawns a task rebuilding the tree from the `WorldIDIdentityManager` creation block alongside the live tree, which keeps being synced and serving proofs meanwhile. Returns `None` if a resync is already in progress.
+    ///
+    /// Once the rebuilt tree has caught up with the live tree and its root matches the onchain root, it is swapped in atomically. If the resync fails, the live tree is left untouched.
+    pub fn spawn_resync(
+        self: &Arc<Self>,
+    ) -> Option<JoinHandle<Result<(), TreeAvailabilityError<M>>>> {
+        self.spawn_resync_with(None)
+    }
+
+    /// Spawns a task rebuilding the tree from `from_block` forward, like `spawn_resync`. The rebuilt tree starts from the live tree as of the block before `from_block`, so only the batches committed from `from_block` onwards are replayed. Returns `Ok(None)` if a resync is already in progress.
+    ///
+    /// Returns `TreeError::ResyncBlockUnavailable` if the tree history does not reach back to the block before `from_block`.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_block` - First block to replay the tree changes of. `0` rebuilds the tree from the `WorldIDIdentityManager` creation block.
+    pub async fn spawn_resync_from(
+        self: &Arc<Self>,
+        from_block: u64,
+    ) -> Result<
+        Option<JoinHandle<Result<(), TreeAvailabilityError<M>>>>,
+        TreeError,
+    > {
+        let Some(block) = from_block.checked_sub(1) else {
+            return Ok(self.spawn_resync());
+        };
+
+        let seed = self
+            .tree_data
+            .read()
+            .await
+            .leaves_as_of(block)
+            .ok_or(TreeError::ResyncBlockUnavailable(from_block))?;
+
+        Ok(self.spawn_resync_with(Some(seed)))
+    }
+
+    /// Spawns the resync task, seeding the rebuilt tree with `seed` if given.
+    fn spawn_resync_with(
+        self: &Arc<Self>,
+        seed: Option<(Vec<Hash>, u64)>,
+    ) -> Option<JoinHandle<Result<(), TreeAvailabilityError<M>>>> {
+        if !self.resync.try_start() {
+            return None;
+        }
+
+        let world_tree = self.clone();
+
+        Some(tokio::spawn(async move {
+            tracing::info!(
+                from_block = seed.as_ref().map(|(_, block)| block + 1),
+                "Resync started"
+            );
+
+            let result = world_tree.resync(seed).await;
+            world_tree.resync.finish();
+
+            match &result {
+                Ok(()) => tracing::info!("Resync complete"),
+                Err(err) => tracing::error!(?err, "Resync failed"),
+            }
+
+            result
+        }))
+    }
+
+    /// Rebuilds the tree and swaps it in once it is caught up with the live tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Leaves to start the rebuilt tree from, along with the block they were committed at. The rebuilt tree syncs from the following block. If `None`, the tree is rebuilt from the creation block.
+    async fn resync(
+        &self,
+        seed: Option<(Vec<Hash>, u64)>,
+    ) -> Result<(), TreeAvailabilityError<M>> {
+        // Replaying the history must not trip the insertion rate limit
+        let config = WorldTreeConfig {
+            insertion_rate_limit: None,
+            ..self.config.clone()
+        };
+
+        let mut tree_updater =
+            TreeUpdater::new(&config, self.tree_updater.middleware.clone());
+        tree_updater.leaf_transform = self.tree_updater.leaf_transform.clone();
+        tree_updater.secondary_middleware =
+            self.tree_updater.secondary_middleware.clone();
+
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            config.tree_depth,
+            config.dense_prefix_depth,
+            &Hash::ZERO,
+        );
+        let mut tree_data = TreeData::new(tree, config.tree_history_size);
+        if let Some((leaves, block)) = seed {
+            tree_data.restore_leaves(&leaves);
+            tree_data.latest_root_block = block;
+            tree_updater.resume_from(block);
+        }
+        let tree_data = RwLock::new(tree_data);
+
+        // Catch up with the live tree one window at a time while it keeps syncing, until it is within a window of the live tree
+        loop {
+            let target_block =
+                self.tree_updater.latest_synced_block.load(Ordering::SeqCst);
+            let synced_block =
+                tree_updater.latest_synced_block.load(Ordering::SeqCst);
+            self.resync.record(synced_block, target_block);
+
+            if synced_block + config.window_size >= target_block {
+                break;
+            }
+
+            tree_updater
+                .sync_to_block(
+                    &tree_data,
+                    None,
+                    synced_block + config.window_size,
+                )
+                .await?;
+        }
+
+        // The live tree is paused for the last window, so that the rebuilt tree is swapped in at the block the live tree is synced to
+        let _sync_guard = self.sync_lock.lock().await;
+
+        let target_block =
+            self.tree_updater.latest_synced_block.load(Ordering::SeqCst);
+        tree_updater
+            .sync_to_block(&tree_data, None, target_block)
+            .await?;
+        self.resync.record(target_block, target_block);
+
+        let tree_data = tree_data.into_inner();
+        preflight::verify_root(
+            self.tree_updater.middleware.clone(),
+            self.tree_updater.address,
+            tree_data.tree.root(),
+            target_block,
+        )
+        .await?;
+
+        self.swap_tree_data(tree_data).await;
+        self.tree_updater.finish_rebuild();
+
+        Ok(())
+    }
+
+    /// Syncs the live tree to the chain head. If a reorg reaches past the tree history, the tree is rebuilt by a resync, waiting for the one in progress if any, before returning.
+    async fn sync_to_head(
+        self: &Arc<Self>,
+    ) -> Result<(), TreeAvailabilityError<M>> {
+        let result = {
+            let _sync_guard = self.sync_lock.lock().await;
+            self.tree_updater
+                .sync_to_head(&self.tree_data, self.db_writer.as_deref())
+                .await
+        };
+
+        let Err(TreeAvailabilityError::ReorgDetected { depth }) = result else {
+            return result;
+        };
+
+        tracing::warn!(
+            ?depth,
+            "Rebuilding the tree after an unrecoverable reorg"
+        );
+
+        match self.spawn_resync() {
+            Some(handle) => handle.await.expect("Resync task panicked")?,
+            None => {
+                while self.resync.in_progress() {
+                    tokio::time::sleep(Duration::from_secs(
+                        SYNC_TO_HEAD_SLEEP_SECONDS,
+                    ))
+                    .await;
+                }
+            }
+        }
+
+        // The resync in progress may have failed, leaving the tree unservable
+        if self.tree_updater.reorg_in_progress.load(Ordering::SeqCst) {
+            return Err(TreeAvailabilityError::ReorgDetected { depth });
+        }
+
+        Ok(())
+    }
+
     /// Spawns a task that continually syncs the `TreeData` to the state at the chain head.
-    #[instrument(skip(self))]
-    pub fn spawn(&self) -> JoinHandle<Result<(), TreeAvailabilityError<M>>> {
-        let tree_data = self.tree_data.clone();
+    ///
+    /// Once `shutdown` is cancelled, the task finishes the sync in progress and exits, so that no batch is left partially applied or persisted.
+    #[instrument(skip(self, shutdown))]
+    pub fn spawn(
+        self: &Arc<Self>,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<Result<(), TreeAvailabilityError<M>>> {
+        let world_tree = self.clone();
         let tree_updater = self.tree_updater.clone();
 
         tracing::info!("Spawning thread to sync tree");
         let synced = self.synced.clone();
+        let log_subscriber = self.log_subscriber.clone();
 
         tokio::spawn(async move {
-            let database_url = std::env::var("DATABASE_URL").unwrap();
-            let db = Database::connect(database_url).await.unwrap();
+            // Subscribing before the initial sync ensures no log emitted after it is missed
+            let mut tree_changes = match &log_subscriber {
+                Some(log_subscriber) => Some(
+                    log_subscriber
+                        .subscribe_logs(&tree_changed_filter(
+                            tree_updater.address,
+                        ))
+                        .await?,
+                ),
+                None => None,
+            };
+
             let start = tokio::time::Instant::now();
-            tree_updater.sync_to_head(&tree_data, &db).await?;
+            world_tree.sync_to_head().await?;
             let sync_time = start.elapsed();
 
             tracing::info!(?sync_time, "WorldTree synced to chain head");
             synced.store(true, Ordering::Relaxed);
+            tree_updater.steady_state.store(true, Ordering::Relaxed);
+
+            while !shutdown.is_cancelled() {
+                world_tree.sync_to_head().await?;
+
+                let Some(logs) = tree_changes.as_mut() else {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = tokio::time::sleep(Duration::from_secs(
+                            SYNC_TO_HEAD_SLEEP_SECONDS,
+                        )) => continue,
+                    }
+                };
 
-            loop {
-                tree_updater.sync_to_head(&tree_data, &db).await?;
+                let notification = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    notification = tokio::time::timeout(
+                        Duration::from_secs(SUBSCRIPTION_FALLBACK_SLEEP_SECONDS),
+                        logs.next(),
+                    ) => notification,
+                };
 
-                tokio::time::sleep(Duration::from_secs(
-                    SYNC_TO_HEAD_SLEEP_SECONDS,
-                ))
-                .await;
+                if let Ok(None) = notification {
+                    tracing::warn!(
+                        "Log subscription closed, falling back to polling"
+                    );
+                    tree_changes = None;
+                }
             }
+
+            tracing::info!("Stopped syncing tree");
+
+            Ok(())
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::Provider;
+    use ethers::types::H160;
+
+    use super::*;
+
+    const TREE_DEPTH: usize = 10;
+    const NUM_READERS: usize = 8;
+    const NUM_SWAPS: usize = 50;
+
+    fn build_tree_data(identities: &[Hash]) -> TreeData {
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            TREE_DEPTH,
+            TREE_DEPTH,
+            &Hash::ZERO,
+        );
+
+        let mut tree_data = TreeData::new(tree, 0);
+        tree_data.insert_many_at(0, identities);
+        tree_data
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_swap_tree_data_read_consistency() {
+        let (provider, _mock) = Provider::mocked();
+        let config = WorldTreeConfig::new(H160::zero(), 0, TREE_DEPTH, 0, 0);
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            TREE_DEPTH,
+            TREE_DEPTH,
+            &Hash::ZERO,
+        );
+        let world_tree =
+            Arc::new(WorldTree::new(tree, &config, Arc::new(provider)));
+
+        let identities_a: Vec<Hash> = (1..=5).map(Hash::from).collect();
+        let identities_b: Vec<Hash> = (6..=10).map(Hash::from).collect();
+        let tree_data_a = build_tree_data(&identities_a);
+        let tree_data_b = build_tree_data(&identities_b);
+        let root_a = tree_data_a.tree.root();
+        let root_b = tree_data_b.tree.root();
+
+        world_tree.swap_tree_data(tree_data_a.clone()).await;
+
+        let readers: Vec<_> = (0..NUM_READERS)
+            .map(|_| {
+                let world_tree = world_tree.clone();
+                let identities_a = identities_a.clone();
+                let identities_b = identities_b.clone();
+
+                tokio::spawn(async move {
+                    for _ in 0..NUM_SWAPS {
+                        let tree_data = world_tree.tree_data.read().await;
+                        let root = tree_data.tree.root();
+
+                        // Every read must observe one complete version of the tree
+                        let identities = if root == root_a {
+                            &identities_a
+                        } else {
+                            assert_eq!(root, root_b);
+                            &identities_b
+                        };
+
+                        for identity in identities {
+                            let proof = tree_data
+                                .get_inclusion_proof(*identity, None)
+                                .unwrap()
+                                .expect("Identity should be in the tree");
+                            assert_eq!(proof.root, root);
+                        }
+
+                        drop(tree_data);
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..NUM_SWAPS {
+            let tree_data = if i % 2 == 0 {
+                tree_data_b.clone()
+            } else {
+                tree_data_a.clone()
+            };
+
+            world_tree.swap_tree_data(tree_data).await;
+            tokio::task::yield_now().await;
+        }
+
+        for reader in readers {
+            reader.await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ethers::contract::ContractError;
+use ethers::providers::Middleware;
+use ethers::types::{H160, U256};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use super::Hash;
+use crate::abi::IWorldIDIdentityManager;
+
+/// Read-through cache of the `latestRoot()` of the `WorldIDIdentityManager`, so that frequent callers within the TTL share a single provider call.
+pub struct OnchainRootCache<M: Middleware> {
+    /// `WorldIDIdentityManager` to read the latest root from
+    contract: IWorldIDIdentityManager<M>,
+    /// Duration for which a fetched root is served from the cache
+    ttl: Duration,
+    /// Latest root along with the instant it was fetched or observed
+    cached: Mutex<Option<(Hash, Instant)>>,
+}
+
+impl<M: Middleware> OnchainRootCache<M> {
+    /// Initializes a new `OnchainRootCache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the `WorldIDIdentityManager`.
+    /// * `ttl` - Duration for which a fetched root is served from the cache.
+    /// * `middleware` - Provider to interact with Ethereum.
+    pub fn new(address: H160, ttl: Duration, middleware: Arc<M>) -> Self {
+        Self {
+            contract: IWorldIDIdentityManager::new(address, middleware),
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Returns the latest onchain root, calling `latestRoot()` only if the cached root is older than the TTL.
+    ///
+    /// Concurrent callers wait on the same in-flight call rather than each issuing their own.
+    pub async fn latest_root(&self) -> Result<Hash, ContractError<M>> {
+        let mut cached = self.cached.lock().await;
+
+        if let Some((root, updated_at)) = *cached {
+            if updated_at.elapsed() < self.ttl {
+                return Ok(root);
+            }
+        }
+
+        let root =
+            Hash::from_limbs(self.contract.latest_root().call().await?.0);
+        *cached = Some((root, Instant::now()));
+
+        Ok(root)
+    }
+
+    /// Refreshes the cache with a root observed in a `TreeChanged` event, so the cached value does not lag behind a known update. Only roots observed up to the chain head are current, so roots of catch-up windows must not be observed.
+    ///
+    /// # Arguments
+    ///
+    /// * `post_root` - `post_root` of the most recent `TreeChanged` event up to the chain head.
+    pub async fn observe(&self, post_root: U256) {
+        *self.cached.lock().await =
+            Some((Hash::from_limbs(post_root.0), Instant::now()));
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ethers::contract::ContractError;
+use ethers::providers::Middleware;
+use ethers::types::H160;
+
+use super::config::{ContractVersion, ExpectedBlock};
+use super::error::PreflightError;
+use super::{Hash, SYNC_TO_HEAD_SLEEP_SECONDS};
+use crate::abi::IWorldIDIdentityManager;
+
+/// Verifies that the block at the expected height has the expected hash, ensuring the provider serves the intended chain rather than a fork or a different network sharing the same chain id.
+///
+/// # Arguments
+///
+/// * `middleware` - Provider to interact with Ethereum.
+/// * `expected` - Block height and the hash expected at that height.
+pub async fn verify_expected_block<M: Middleware>(
+    middleware: &M,
+    expected: &ExpectedBlock,
+) -> Result<(), PreflightError<M>> {
+    let block = middleware
+        .get_block(expected.number)
+        .await
+        .map_err(PreflightError::MiddlewareError)?
+        .ok_or(PreflightError::BlockNotFound(expected.number))?;
+
+    if block.hash != Some(expected.hash) {
+        return Err(PreflightError::BlockHashMismatch {
+            number: expected.number,
+            expected: expected.hash,
+            actual: block.hash,
+        });
+    }
+
+    tracing::info!(
+        number = expected.number,
+        hash = ?expected.hash,
+        "Verified expected block hash"
+    );
+
+    Ok(())
+}
+
+/// Verifies that a contract is deployed at the given address, catching a mistyped address or a provider serving another network before a long sync.
+///
+/// # Arguments
+///
+/// * `middleware` - Provider to interact with Ethereum.
+/// * `address` - Address of the contract.
+pub async fn verify_contract_code<M: Middleware>(
+    middleware: &M,
+    address: H160,
+) -> Result<(), PreflightError<M>> {
+    let code = middleware
+        .get_code(address, None)
+        .await
+        .map_err(PreflightError::MiddlewareError)?;
+
+    if code.is_empty() {
+        return Err(PreflightError::NoContractCode { address });
+    }
+
+    tracing::info!(?address, "Verified contract code");
+
+    Ok(())
+}
+
+/// Verifies that the chain head has reached the `WorldIDIdentityManager` creation block, which would otherwise leave the block scanner with nothing to scan indefinitely.
+///
+/// # Arguments
+///
+/// * `middleware` - Provider to interact with Ethereum.
+/// * `creation_block` - Creation block of the `WorldIDIdentityManager`.
+/// * `wait` - Wait for the chain head to reach `creation_block` instead of failing, for contracts that are not deployed yet.
+pub async fn verify_creation_block<M: Middleware>(
+    middleware: &M,
+    creation_block: u64,
+    wait: bool,
+) -> Result<(), PreflightError<M>> {
+    loop {
+        let head = middleware
+            .get_block_number()
+            .await
+            .map_err(PreflightError::MiddlewareError)?
+            .as_u64();
+
+        if head >= creation_block {
+            return Ok(());
+        }
+
+        if !wait {
+            return Err(PreflightError::CreationBlockAhead {
+                creation_block,
+                head,
+            });
+        }
+
+        tracing::info!(
+            ?creation_block,
+            ?head,
+            "Waiting for the chain head to reach the creation block"
+        );
+
+        tokio::time::sleep(Duration::from_secs(SYNC_TO_HEAD_SLEEP_SECONDS))
+            .await;
+    }
+}
+
+/// Verifies that a locally rebuilt root matches the `latestRoot()` of the `WorldIDIdentityManager` at the given block, ensuring the tree can be served without re-scanning the chain.
+///
+/// # Arguments
+///
+/// * `middleware` - Provider to interact with Ethereum.
+/// * `address` - Address of the `WorldIDIdentityManager`.
+/// * `root` - Root of the rebuilt tree.
+/// * `block` - Block at which the tree was rebuilt.
+pub async fn verify_root<M: Middleware>(
+    middleware: Arc<M>,
+    address: H160,
+    root: Hash,
+    block: u64,
+) -> Result<(), PreflightError<M>> {
+    let onchain = IWorldIDIdentityManager::new(address, middleware)
+        .latest_root()
+        .block(block)
+        .call()
+        .await?;
+    let onchain = Hash::from_limbs(onchain.0);
+
+    if onchain != root {
+        return Err(PreflightError::RootMismatch {
+            block,
+            rebuilt: root,
+            onchain,
+        });
+    }
+
+    tracing::info!(?block, ?root, "Verified rebuilt root");
+
+    Ok(())
+}
+
+/// Verifies that the deployed `WorldIDIdentityManager` matches the configured contract version, so the service never decodes calldata of one version with the selectors of another.
+///
+/// Only V2 exposes `getDeleteIdentitiesVerifierLookupTableAddress()`, so the call succeeding identifies a V2 implementation and the call reverting identifies a V1 implementation.
+///
+/// # Arguments
+///
+/// * `middleware` - Provider to interact with Ethereum.
+/// * `address` - Address of the `WorldIDIdentityManager`.
+/// * `configured` - Configured contract version.
+pub async fn verify_contract_version<M: Middleware>(
+    middleware: Arc<M>,
+    address: H160,
+    configured: ContractVersion,
+) -> Result<(), PreflightError<M>> {
+    let detected = match IWorldIDIdentityManager::new(address, middleware)
+        .get_delete_identities_verifier_lookup_table_address()
+        .call()
+        .await
+    {
+        Ok(_) => ContractVersion::V2,
+        Err(ContractError::Revert(_)) => ContractVersion::V1,
+        Err(err) => return Err(err.into()),
+    };
+
+    if detected != configured {
+        return Err(PreflightError::ContractVersionMismatch {
+            configured,
+            detected,
+        });
+    }
+
+    tracing::info!(contract_version = ?detected, "Verified contract version");
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use governor::clock::DefaultClock;
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::config::RateLimitConfig;
+use super::error::TreeError;
+use super::metrics::record_rate_limited_request;
+
+/// Interval at which clients whose rate limit has fully replenished are forgotten.
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rate limit of the costly public routes, tracked separately for each client IP.
+pub struct ClientRateLimiter {
+    limiter: RateLimiter<IpAddr, DefaultKeyedStateStore<IpAddr>, DefaultClock>,
+    /// Maximum units of quota a client can consume at once
+    burst: NonZeroU32,
+}
+
+impl ClientRateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let burst = config.burst.unwrap_or(config.requests_per_second);
+        let quota =
+            Quota::per_second(config.requests_per_second).allow_burst(burst);
+
+        Self {
+            limiter: RateLimiter::keyed(quota),
+            burst,
+        }
+    }
+
+    /// Returns whether a request from `ip` is within its rate limit, consuming one unit of its quota if so.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        self.limiter.check_key(&ip).is_ok()
+    }
+
+    /// Returns whether `n` units of quota are available to `ip`, consuming them if so. The charge is capped at the burst, so that a batch larger than the burst drains the quota of the client rather than being rejected forever.
+    pub fn check_n(&self, ip: IpAddr, n: u32) -> bool {
+        let Some(n) = NonZeroU32::new(n.min(self.burst.get())) else {
+            return true;
+        };
+
+        matches!(self.limiter.check_key_n(&ip, n), Ok(Ok(())))
+    }
+
+    /// Spawns the task forgetting idle clients every `PRUNE_INTERVAL` until `shutdown` is cancelled, so that the tracked clients do not grow without bound.
+    pub fn spawn(
+        self: Arc<Self>,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = interval.tick() => {
+                        self.limiter.retain_recent();
+                        self.limiter.shrink_to_fit();
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Rejects requests with 429 once their client IP has exceeded its rate limit. Requires the server to be served with `ConnectInfo<SocketAddr>`.
+pub async fn middleware(
+    State(limiter): State<Arc<ClientRateLimiter>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !limiter.check(address.ip()) {
+        record_rate_limited_request();
+        return TreeError::RateLimited.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Charges the items of a batch request beyond the first, which `middleware` already charged, rejecting the request with 429 once its client IP has exceeded its rate limit. Always succeeds if rate limiting is disabled.
+///
+/// # Arguments
+///
+/// * `limiter` - Rate limiter of the service, if enabled.
+/// * `address` - Address of the client.
+/// * `size` - Number of items in the batch.
+pub fn charge_batch(
+    limiter: Option<&ClientRateLimiter>,
+    address: SocketAddr,
+    size: usize,
+) -> Result<(), TreeError> {
+    let Some(limiter) = limiter else {
+        return Ok(());
+    };
+
+    let extra = u32::try_from(size.saturating_sub(1)).unwrap_or(u32::MAX);
+    if !limiter.check_n(address.ip(), extra) {
+        record_rate_limited_request();
+        return Err(TreeError::RateLimited);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let limiter = ClientRateLimiter::new(&RateLimitConfig {
+            requests_per_second: NonZeroU32::new(1).unwrap(),
+            burst: NonZeroU32::new(2),
+        });
+        let client: IpAddr = [10, 0, 0, 1].into();
+        let other_client: IpAddr = [10, 0, 0, 2].into();
+
+        assert!(limiter.check(client));
+        assert!(limiter.check(client));
+        assert!(!limiter.check(client));
+
+        // Clients are limited independently
+        assert!(limiter.check(other_client));
+    }
+
+    #[test]
+    fn test_check_n() {
+        let limiter = ClientRateLimiter::new(&RateLimitConfig {
+            requests_per_second: NonZeroU32::new(1).unwrap(),
+            burst: NonZeroU32::new(5),
+        });
+        let client: IpAddr = [10, 0, 0, 1].into();
+        let other_client: IpAddr = [10, 0, 0, 2].into();
+
+        assert!(limiter.check_n(client, 3));
+        assert!(!limiter.check_n(client, 3));
+        assert!(limiter.check_n(client, 2));
+        assert!(!limiter.check(client));
+
+        // A batch larger than the burst drains the whole quota
+        assert!(limiter.check_n(other_client, 10));
+        assert!(!limiter.check(other_client));
+    }
+}
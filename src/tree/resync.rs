@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// State of a background rebuild of the tree, see `WorldTree::spawn_resync`.
+#[derive(Debug, Default)]
+pub struct ResyncStatus {
+    /// Set while a rebuilt tree is being synced, until it is swapped in or the resync fails
+    in_progress: AtomicBool,
+    /// Block the rebuilt tree is synced to
+    synced_block: AtomicU64,
+    /// Block the rebuilt tree must reach before it is swapped in, i.e. the latest block synced by the live tree
+    target_block: AtomicU64,
+}
+
+/// Progress of a resync, as reported by `/stats`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResyncProgress {
+    pub synced_block: u64,
+    pub target_block: u64,
+}
+
+impl ResyncStatus {
+    /// Marks a resync as started. Returns `false` if a resync is already in progress.
+    pub fn try_start(&self) -> bool {
+        let started = self
+            .in_progress
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+
+        if started {
+            self.synced_block.store(0, Ordering::SeqCst);
+            self.target_block.store(0, Ordering::SeqCst);
+        }
+
+        started
+    }
+
+    /// Marks the resync as finished, whether the rebuilt tree was swapped in or not.
+    pub fn finish(&self) {
+        self.in_progress.store(false, Ordering::SeqCst);
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::SeqCst)
+    }
+
+    /// Records the blocks the rebuilt tree is synced to and must reach.
+    pub fn record(&self, synced_block: u64, target_block: u64) {
+        self.synced_block.store(synced_block, Ordering::SeqCst);
+        self.target_block.store(target_block, Ordering::SeqCst);
+    }
+
+    /// Returns the progress of the resync, or `None` if no resync is in progress.
+    pub fn progress(&self) -> Option<ResyncProgress> {
+        self.in_progress().then(|| ResyncProgress {
+            synced_block: self.synced_block.load(Ordering::SeqCst),
+            target_block: self.target_block.load(Ordering::SeqCst),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_resync_in_progress() {
+        let status = ResyncStatus::default();
+        assert!(status.progress().is_none());
+
+        assert!(status.try_start());
+        assert!(!status.try_start());
+
+        status.record(10, 20);
+        let progress = status.progress().unwrap();
+        assert_eq!(progress.synced_block, 10);
+        assert_eq!(progress.target_block, 20);
+
+        status.finish();
+        assert!(status.progress().is_none());
+        assert!(status.try_start());
+    }
+}
@@ -1,30 +1,78 @@
 use std::net::SocketAddr;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, FromRef, Path, Query, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::{middleware, Json};
-use ethers::abi::Address;
 use axum_middleware::logging;
-use ethers::providers::Middleware;
-use ethers::types::H160;
-use sea_orm::{Database, DatabaseConnection};
+use ethers::providers::{Middleware, Provider, Ws};
+use ethers_circuit_breaker::{CircuitBreaker, CircuitState};
+use ethers::types::Bytes;
+use metrics_exporter_prometheus::PrometheusHandle;
+use sea_orm::DatabaseConnection;
 use semaphore::lazy_merkle_tree::Canonical;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
-use crate::claims::{ClaimStorage, ClaimUpdater};
+use tokio_util::sync::CancellationToken;
 
+use super::attestation::{RootSigner, SignedRoot};
+use super::batch_client::RpcBatchClient;
+use super::config::{
+    ApiConfig, BindConfig, RateLimitConfig, RestartPolicy, RouteScope,
+    WorldTreeConfig,
+};
+use super::{db, field_case};
+use super::db_writer::DbWriter;
 use super::error::{TreeAvailabilityError, TreeError};
-use super::tree_data::InclusionProof;
+use super::heartbeat::Heartbeat;
+use super::leaf_transform::LeafTransform;
+use super::metrics::{
+    record_inclusion_proof_latency, record_inclusion_proof_request,
+};
+use super::rate_limit::{self, ClientRateLimiter};
+use super::resync::ResyncProgress;
+use super::supervisor::supervise;
+use super::tree_data::{self, InclusionProof};
+use super::tree_updater::TreeUpdater;
 use super::{Hash, PoseidonTree, WorldTree};
+use crate::audit::AuditLog;
+
+/// Number of leaves returned by `/leaves` when no limit is requested.
+const DEFAULT_LEAVES_LIMIT: usize = 1000;
+/// Maximum number of leaves returned by `/leaves`.
+const MAX_LEAVES_LIMIT: usize = 10_000;
+
+/// Header set on proofs served against a stale tree during a provider outage, holding the number of seconds since the tree was last synced.
+pub const TREE_STALE_HEADER: &str = "x-tree-stale";
 
 /// Service that keeps the World Tree synced with `WorldIDIdentityManager` and exposes an API endpoint to serve inclusion proofs for a given World ID.
 pub struct TreeAvailabilityService<M: Middleware + 'static> {
     /// In-memory representation of the merkle tree containing all verified World IDs.
     pub world_tree: Arc<WorldTree<M>>,
-    pub claim_storage: Arc<ClaimStorage<M>>,
+    /// Settings applied to the API endpoints.
+    pub api: Arc<ApiConfig>,
+    /// Signer of root attestations. If `None`, the signed root endpoint is disabled.
+    pub root_signer: Option<Arc<RootSigner>>,
+    /// Circuit breaker of the provider, reflected in the health endpoint.
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Database queried for the insertion metadata of identities. If `None`, proofs are served without it.
+    pub db: Option<DatabaseConnection>,
+    /// Hashes of empty subtrees for each level of the tree, computed once from the configured depth.
+    pub zero_hashes: Arc<ZeroHashesResponse>,
+    /// Heartbeat of the async runtime, reflected in the liveness endpoint.
+    pub heartbeat: Arc<Heartbeat>,
+    /// Handle of the Prometheus recorder rendering the metrics endpoint. If `None`, the metrics endpoint returns 404.
+    pub prometheus: Option<PrometheusHandle>,
+    /// Restarts of the sync task after it fails.
+    pub restart_policy: RestartPolicy,
+    /// Per-client rate limit of the proof routes. If `None`, proof requests are not rate limited.
+    pub rate_limiter: Option<Arc<ClientRateLimiter>>,
 }
 
 impl<M: Middleware> TreeAvailabilityService<M> {
@@ -32,95 +80,387 @@ impl<M: Middleware> TreeAvailabilityService<M> {
     ///
     /// # Arguments
     ///
-    /// * `tree_depth` - Depth of the merkle tree
-    /// * `dense_prefix_depth`: Depth of the tree that is densely populated. Nodes beyond the `dense_prefix_depth` will be stored through pointer based structures.
-    /// * `tree_history_size`: Number of historical roots to store in memory. This is used to serve proofs against historical roots.
-    /// * `world_tree_address`: Address of the `WorldIDIdentityManager` contract onchain
-    /// * `world_tree_creation_block`: Block number where `WorldIDIdentityManager` was deployed
+    /// * `config` - Configuration of the World Tree, including the tree depth, dense prefix depth, number of historical roots to store in memory and the `WorldIDIdentityManager` contract to sync from.
     /// * `middleware`: Provider to interact with Ethereum
     ///
     /// # Returns
     ///
     /// New instance of `TreeAvailabilityService`.
-    pub fn new(
-        tree_depth: usize,
-        dense_prefix_depth: usize,
-        tree_history_size: usize,
-        world_tree_address: H160,
-        world_tree_creation_block: u64,
-        window_size: u64,
-        middleware: Arc<M>,
-    ) -> Self {
+    pub fn new(config: &WorldTreeConfig, middleware: Arc<M>) -> Self {
         let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
-            tree_depth,
-            dense_prefix_depth,
+            config.tree_depth,
+            config.dense_prefix_depth,
             &Hash::ZERO,
         );
 
-        let world_tree = Arc::new(WorldTree::new(
-            tree,
-            tree_history_size,
-            world_tree_address,
-            world_tree_creation_block,
-            window_size,
-            middleware.clone(),
-        ));
-
-        let addy: Address = "0x7f26A7572E8B877654eeDcBc4E573657619FA3CE".parse().unwrap();
-
-        let claim_updater = Arc::new(ClaimUpdater::new(addy,
-                                                       118372573, 2, middleware));
+        let world_tree = Arc::new(WorldTree::new(tree, config, middleware));
 
         Self {
             world_tree,
-            claim_storage: Arc::new(ClaimStorage {
-                claim_updater
+            api: Arc::new(ApiConfig::default()),
+            root_signer: None,
+            circuit_breaker: None,
+            db: None,
+            zero_hashes: Arc::new(ZeroHashesResponse {
+                zero_hashes: tree_data::zero_hashes(config.tree_depth),
             }),
+            heartbeat: Arc::new(Heartbeat::new()),
+            prometheus: None,
+            restart_policy: RestartPolicy::default(),
+            rate_limiter: None,
         }
     }
 
-    /// Spawns an axum server and exposes an API endpoint to serve inclusion proofs for a given World ID. This function also spawns a new task to keep the world tree synced to the chain head.
+    /// Sets the settings applied to the API endpoints.
+    pub fn with_api_config(mut self, api: ApiConfig) -> Self {
+        self.api = Arc::new(api);
+        self
+    }
+
+    /// Sets the circuit breaker of the provider, so that the service reports itself unhealthy while the circuit is open.
+    pub fn with_circuit_breaker(
+        mut self,
+        circuit_breaker: Arc<CircuitBreaker>,
+    ) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Sets the database queried for the insertion metadata served alongside inclusion proofs.
+    pub fn with_database(mut self, db: DatabaseConnection) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Sets the handle of the installed Prometheus recorder, exposing the recorded metrics on `/metrics`.
+    pub fn with_prometheus(mut self, prometheus: PrometheusHandle) -> Self {
+        self.prometheus = Some(prometheus);
+        self
+    }
+
+    /// Sets the budget of restarts of the sync task after it fails.
+    pub fn with_restart_policy(
+        mut self,
+        restart_policy: RestartPolicy,
+    ) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Limits the rate of proof requests accepted from each client IP, rejecting requests beyond it with 429.
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(Arc::new(ClientRateLimiter::new(&rate_limit)));
+        self
+    }
+
+    /// Sets the signer used to serve root attestations.
+    pub fn with_root_signer(mut self, root_signer: RootSigner) -> Self {
+        self.root_signer = Some(Arc::new(root_signer));
+        self
+    }
+
+    /// Sets the provider used to re-fetch windows failing the log consistency check.
+    pub fn with_secondary_middleware(mut self, middleware: Arc<M>) -> Self {
+        self.tree_updater_mut().secondary_middleware = Some(middleware);
+        self
+    }
+
+    /// Records observed roots, applied batches and reorgs to `audit_log` while syncing.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.tree_updater_mut().audit_log = Some(audit_log);
+        self
+    }
+
+    /// Sets the client used to fetch transactions through JSON-RPC batch requests while syncing.
+    pub fn with_rpc_batch_client(
+        mut self,
+        batch_client: RpcBatchClient,
+    ) -> Self {
+        self.tree_updater_mut().batch_client = Some(batch_client);
+        self
+    }
+
+    /// Syncs the tree whenever the WebSocket provider notifies a new `TreeChanged` log, instead of polling the chain head.
+    pub fn with_log_subscriber(mut self, log_subscriber: Provider<Ws>) -> Self {
+        self.world_tree_mut().log_subscriber = Some(Arc::new(log_subscriber));
+        self
+    }
+
+    /// Sets the writer persisting indexed records to the database while syncing.
+    pub fn with_db_writer(mut self, db_writer: Arc<DbWriter>) -> Self {
+        self.world_tree_mut().db_writer = Some(db_writer);
+        self
+    }
+
+    /// Applies `leaf_transform` to identity commitments before they are inserted into the tree, and to the commitments queried through the proof endpoints. See `LeafTransform`.
+    pub fn with_leaf_transform(
+        mut self,
+        leaf_transform: Arc<dyn LeafTransform>,
+    ) -> Self {
+        self.tree_updater_mut().leaf_transform = leaf_transform;
+        self
+    }
+
+    /// Restores the tree from leaves persisted in the database and resumes syncing after `block`, instead of re-scanning the chain from the `WorldIDIdentityManager` creation block.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - Leaves of the tree, ordered by leaf index.
+    /// * `block` - Block of the most recent persisted batch.
+    pub fn with_restored_leaves(
+        mut self,
+        leaves: &[Hash],
+        block: u64,
+    ) -> Self {
+        let world_tree = self.world_tree_mut();
+
+        let tree_data = Arc::get_mut(&mut world_tree.tree_data)
+            .expect("TreeData must not be shared before serving")
+            .get_mut();
+        tree_data.restore_leaves(leaves);
+        tree_data.latest_root_block = block;
+
+        world_tree.tree_updater.resume_from(block);
+
+        self
+    }
+
+    /// Returns a mutable reference to the world tree. Only valid before the service is served, while the `WorldTree` is not shared.
+    fn world_tree_mut(&mut self) -> &mut WorldTree<M> {
+        Arc::get_mut(&mut self.world_tree)
+            .expect("WorldTree must not be shared before serving")
+    }
+
+    /// Returns a mutable reference to the tree updater. Only valid before the service is served, while the `WorldTree` is not shared.
+    fn tree_updater_mut(&mut self) -> &mut TreeUpdater<M> {
+        Arc::get_mut(&mut self.world_tree_mut().tree_updater)
+            .expect("TreeUpdater must not be shared before serving")
+    }
+
+    /// Spawns an axum server per bind address and exposes API endpoints to serve inclusion proofs for a given World ID. This function also spawns a new task to keep the world tree synced to the chain head.
     ///
     /// # Arguments
     ///
-    /// * `port` - Port to bind the server to.
+    /// * `binds` - Sockets to bind the servers to, along with the routes exposed on each socket.
+    /// * `shutdown` - Token cancelled to shut down the servers and stop syncing the tree.
     ///
     /// # Returns
     ///
     /// Vector of `JoinHandle`s for the spawned tasks.
     pub fn serve(
         self,
-        addr: SocketAddr,
+        binds: Vec<BindConfig>,
+        shutdown: CancellationToken,
     ) -> Vec<JoinHandle<Result<(), TreeAvailabilityError<M>>>> {
         let mut handles = vec![];
 
-        // Initialize a new router and spawn the server
-        tracing::info!(?addr, "Initializing axum server");
+        for bind in binds {
+            let BindConfig { address, routes } = bind;
+
+            // Initialize a new router and spawn the server
+            tracing::info!(?address, ?routes, "Initializing axum server");
+
+            // Client addresses are exposed to the per-client rate limit
+            let make_service = self
+                .router(routes)
+                .into_make_service_with_connect_info::<SocketAddr>();
+            let shutdown = shutdown.clone();
+
+            let server_handle = tokio::spawn(async move {
+                tracing::info!(?address, "Spawning server");
+                axum::Server::bind(&address)
+                    .serve(make_service)
+                    .with_graceful_shutdown(shutdown.cancelled_owned())
+                    .await
+                    .map_err(TreeAvailabilityError::HyperError)?;
+                tracing::info!(?address, "Server spawned");
+
+                Ok(())
+            });
+
+            handles.push(server_handle);
+        }
+
+        // Spawn a new task to keep the world tree synced to the chain head, restarted whenever it fails
+        let world_tree = self.world_tree.clone();
+        let sync_shutdown = shutdown.clone();
+        handles.push(supervise(
+            "world_tree",
+            self.restart_policy,
+            shutdown.clone(),
+            move || world_tree.spawn(sync_shutdown.clone()),
+        ));
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let prune_handle = rate_limiter.clone().spawn(shutdown.clone());
+            handles.push(tokio::spawn(async move {
+                prune_handle.await.expect("Rate limiter task panicked");
+                Ok(())
+            }));
+        }
+
+        let heartbeat_handle = self.heartbeat.clone().spawn(shutdown);
+        handles.push(tokio::spawn(async move {
+            heartbeat_handle.await.expect("Heartbeat task panicked");
+            Ok(())
+        }));
+
+        handles
+    }
 
-        let router = axum::Router::new()
+    /// Builds the router exposing the routes within the specified scope.
+    fn router(&self, scope: RouteScope) -> axum::Router {
+        // Routes reading or signing the tree, which are rate limited when enabled
+        let mut limited = axum::Router::<ServiceState<M>>::new()
             .route("/inclusionProof", axum::routing::post(inclusion_proof))
+            .route(
+                "/inclusionProofBatch",
+                axum::routing::post(inclusion_proof_batch),
+            )
+            .route(
+                "/inclusionProofAnyRoot",
+                axum::routing::post(inclusion_proof_any_root),
+            )
+            .route("/containsBatch", axum::routing::post(contains_batch))
+            .route("/signedRoot", axum::routing::get(signed_root))
+            .route(
+                "/identityIndex/:commitment",
+                axum::routing::get(identity_index),
+            )
+            .route("/leaves", axum::routing::get(leaves));
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            limited = limited.route_layer(middleware::from_fn_with_state(
+                rate_limiter.clone(),
+                rate_limit::middleware,
+            ));
+        }
+
+        let public = axum::Router::<ServiceState<M>>::new()
+            .merge(limited)
+            .route("/zeroHashes", axum::routing::get(zero_hashes))
+            .route("/treeInfo", axum::routing::get(tree_info))
+            .route("/ws", axum::routing::get(root_subscription));
+
+        let internal = axum::Router::<ServiceState<M>>::new()
             .route("/synced", axum::routing::post(synced))
             .route("/health", axum::routing::get(health))
+            .route("/live", axum::routing::get(live))
+            .route("/ready", axum::routing::get(ready))
+            .route("/stats", axum::routing::get(stats))
+            .route("/resync", axum::routing::post(resync))
+            .route("/admin/resync", axum::routing::post(admin_resync))
+            .route("/onchainRoot", axum::routing::get(onchain_root))
+            .route("/metrics", axum::routing::get(prometheus_metrics));
+
+        let router = match scope {
+            RouteScope::All => public.merge(internal),
+            RouteScope::Public => public,
+            RouteScope::Internal => internal,
+        };
+
+        router
+            .layer(middleware::from_fn_with_state(
+                self.api.clone(),
+                field_case::middleware,
+            ))
             .layer(middleware::from_fn(logging::middleware))
-            .with_state(self.world_tree.clone());
+            .with_state(ServiceState {
+                world_tree: self.world_tree.clone(),
+                api: self.api.clone(),
+                root_signer: self.root_signer.clone(),
+                circuit_breaker: self.circuit_breaker.clone(),
+                db: self.db.clone(),
+                zero_hashes: self.zero_hashes.clone(),
+                heartbeat: self.heartbeat.clone(),
+                prometheus: self.prometheus.clone(),
+                rate_limiter: self.rate_limiter.clone(),
+            })
+    }
+}
 
-        let server_handle = tokio::spawn(async move {
-            tracing::info!("Spawning server");
-            axum::Server::bind(&addr)
-                .serve(router.into_make_service())
-                .await
-                .map_err(TreeAvailabilityError::HyperError)?;
-            tracing::info!("Server spawned");
+/// State shared by the API handlers.
+pub struct ServiceState<M: Middleware> {
+    pub world_tree: Arc<WorldTree<M>>,
+    pub api: Arc<ApiConfig>,
+    pub root_signer: Option<Arc<RootSigner>>,
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    pub db: Option<DatabaseConnection>,
+    pub zero_hashes: Arc<ZeroHashesResponse>,
+    pub heartbeat: Arc<Heartbeat>,
+    pub prometheus: Option<PrometheusHandle>,
+    pub rate_limiter: Option<Arc<ClientRateLimiter>>,
+}
 
-            Ok(())
-        });
+impl<M: Middleware> Clone for ServiceState<M> {
+    fn clone(&self) -> Self {
+        Self {
+            world_tree: self.world_tree.clone(),
+            api: self.api.clone(),
+            root_signer: self.root_signer.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            db: self.db.clone(),
+            zero_hashes: self.zero_hashes.clone(),
+            heartbeat: self.heartbeat.clone(),
+            prometheus: self.prometheus.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+        }
+    }
+}
 
-        handles.push(server_handle);
+impl<M: Middleware> FromRef<ServiceState<M>> for Arc<WorldTree<M>> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.world_tree.clone()
+    }
+}
 
-        // Spawn a new task to keep the world tree synced to the chain head
-        handles.push(self.world_tree.spawn());
-        
-        handles
+impl<M: Middleware> FromRef<ServiceState<M>> for Arc<ApiConfig> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.api.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>> for Option<Arc<RootSigner>> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.root_signer.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>> for Option<Arc<CircuitBreaker>> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.circuit_breaker.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>> for Option<DatabaseConnection> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.db.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>> for Arc<ZeroHashesResponse> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.zero_hashes.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>> for Arc<Heartbeat> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.heartbeat.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>> for Option<PrometheusHandle> {
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.prometheus.clone()
+    }
+}
+
+impl<M: Middleware> FromRef<ServiceState<M>>
+    for Option<Arc<ClientRateLimiter>>
+{
+    fn from_ref(state: &ServiceState<M>) -> Self {
+        state.rate_limiter.clone()
     }
 }
 
@@ -143,24 +483,556 @@ impl InclusionProofRequest {
     }
 }
 
-#[tracing::instrument(level = "debug", skip(world_tree))]
+/// Encoding of the inclusion proof in the response body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofEncoding {
+    /// The proof as a JSON object of branches
+    #[default]
+    Json,
+    /// The proof ABI-encoded as hex, see `InclusionProof::abi_encode`
+    Abi,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InclusionProofParams {
+    #[serde(default)]
+    pub encoding: ProofEncoding,
+    /// Include the block and timestamp at which the identity was inserted
+    #[serde(default)]
+    pub include_insertion: bool,
+    /// Serve the proof against the latest root buried by `api.confirmations` blocks instead of the latest root. Takes precedence over the requested root
+    #[serde(default)]
+    pub confirmed: bool,
+    /// Historical root to serve the proof against. Takes precedence over the root in the request body
+    #[serde(default)]
+    pub root: Option<Hash>,
+}
+
+/// Block and timestamp at which an identity was inserted into the tree.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertionMetadata {
+    pub inserted_in_block: u64,
+    /// Unix timestamp of the block
+    pub inserted_at: i64,
+}
+
+/// Inclusion proof along with the insertion metadata of the identity, which is omitted unless requested and available.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProofResponse<T> {
+    #[serde(flatten)]
+    pub proof: T,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insertion: Option<InsertionMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbiEncodedInclusionProof {
+    pub root: Hash,
+    /// `abi.encode(uint256 root, uint256 leafIndex, uint256[] siblings)`
+    pub proof: Bytes,
+}
+
+impl From<InclusionProof> for AbiEncodedInclusionProof {
+    fn from(inclusion_proof: InclusionProof) -> Self {
+        Self {
+            root: inclusion_proof.root,
+            proof: inclusion_proof.abi_encode(),
+        }
+    }
+}
+
+/// Serves an inclusion proof for the requested identity. Passing `?root=` serves the proof against a historical root. Passing `?encoding=abi` returns the proof ABI-encoded, ready to be passed to a verifier contract. Passing `?include_insertion=true` adds the block and timestamp at which the identity was inserted, when a database is configured.
+#[tracing::instrument(
+    level = "debug",
+    skip(world_tree, api, db, circuit_breaker)
+)]
 pub async fn inclusion_proof<M: Middleware>(
     State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(db): State<Option<DatabaseConnection>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    Query(params): Query<InclusionProofParams>,
     Json(req): Json<InclusionProofRequest>,
-) -> Result<(StatusCode, Json<Option<InclusionProof>>), TreeError> {
-    if world_tree.synced.load(Ordering::Relaxed) {
-        let inclusion_proof = world_tree
-            .tree_data
-            .read()
-            .await
-            .get_inclusion_proof(req.identity_commitment, req.root);
+) -> Result<Response, TreeError> {
+    record_inclusion_proof_request();
 
-        Ok((StatusCode::OK, inclusion_proof.into()))
-    } else {
-        Err(TreeError::TreeNotSynced)
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+    let provider_outage = is_outage(circuit_breaker.as_deref());
+
+    let identity_commitment = world_tree
+        .tree_updater
+        .leaf_transform
+        .transform(req.identity_commitment);
+
+    // Proofs against old roots can be expensive to generate, so they are computed off the async runtime and bounded by a timeout
+    let tree_data = world_tree.tree_data.clone();
+    let max_history_depth = api.max_history_depth;
+    let confirmed = params.confirmed;
+    let requested_root = params.root.or(req.root);
+    let confirmations = api.confirmations;
+    let head_block = world_tree
+        .tree_updater
+        .latest_synced_block
+        .load(Ordering::SeqCst);
+    let timeout = Duration::from_millis(api.proof_timeout_ms);
+    let deadline = Instant::now() + timeout;
+    let proof_task = tokio::task::spawn_blocking(move || {
+        let tree_data = tree_data.blocking_read();
+
+        let root = if confirmed {
+            let (root, _) = tree_data
+                .confirmed_root(head_block, confirmations)
+                .ok_or(TreeError::NoConfirmedRoot { confirmations })?;
+            Some(root)
+        } else {
+            requested_root
+        };
+
+        let start = Instant::now();
+        let inclusion_proof = tree_data.get_inclusion_proof_bounded(
+            identity_commitment,
+            root,
+            max_history_depth,
+            Some(deadline),
+        )?;
+
+        Ok((inclusion_proof, start.elapsed()))
+    });
+
+    let (inclusion_proof, proof_latency) =
+        await_proof_task(proof_task, timeout).await?;
+
+    let insertion = match &db {
+        Some(db) if params.include_insertion && inclusion_proof.is_some() => {
+            insertion_metadata(db, identity_commitment).await
+        }
+        _ => None,
+    };
+
+    let serialization_start = Instant::now();
+    let mut response = match params.encoding {
+        ProofEncoding::Json => proof_response(inclusion_proof, insertion),
+        ProofEncoding::Abi => proof_response(
+            inclusion_proof.map(AbiEncodedInclusionProof::from),
+            insertion,
+        ),
+    };
+    record_inclusion_proof_latency(
+        proof_latency + serialization_start.elapsed(),
+    );
+
+    if provider_outage {
+        let staleness = world_tree.tree_updater.staleness();
+        response
+            .headers_mut()
+            .insert(TREE_STALE_HEADER, HeaderValue::from(staleness));
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ContainsBatchRequest {
+    pub identity_commitments: Vec<Hash>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainsBatchResponse {
+    /// Root of the tree the commitments were checked against
+    pub root: Hash,
+    /// Whether each requested commitment is in the tree, in request order
+    pub contains: Vec<bool>,
+}
+
+/// Checks the membership of many identity commitments against the latest root, without generating proofs. All commitments are checked under a single read of the tree, so the results are consistent with the returned root.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn contains_batch<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    State(rate_limiter): State<Option<Arc<ClientRateLimiter>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Json(req): Json<ContainsBatchRequest>,
+) -> Result<(StatusCode, Json<ContainsBatchResponse>), TreeError> {
+    let size = req.identity_commitments.len();
+    if size > api.max_batch_size {
+        return Err(TreeError::BatchTooLarge {
+            size,
+            max_size: api.max_batch_size,
+        });
+    }
+
+    rate_limit::charge_batch(rate_limiter.as_deref(), address, size)?;
+
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+
+    let leaf_transform = &world_tree.tree_updater.leaf_transform;
+    let tree_data = world_tree.tree_data.read().await;
+    let response = ContainsBatchResponse {
+        root: tree_data.tree.root(),
+        contains: req
+            .identity_commitments
+            .into_iter()
+            .map(|commitment| {
+                let leaf = leaf_transform.transform(commitment);
+                tree_data.leaves.contains_key(&leaf)
+            })
+            .collect(),
+    };
+
+    Ok((StatusCode::OK, response.into()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProofBatchResponse {
+    /// Root of the tree all proofs were generated against
+    pub root: Hash,
+    /// Inclusion proof of each requested commitment, in request order. `None` if the commitment is not in the tree
+    pub proofs: Vec<Option<InclusionProof>>,
+}
+
+/// Serves inclusion proofs for many identity commitments, sent as a JSON array. All proofs are generated under a single read of the tree, so they share the returned root.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn inclusion_proof_batch<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    State(rate_limiter): State<Option<Arc<ClientRateLimiter>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Json(identity_commitments): Json<Vec<Hash>>,
+) -> Result<(StatusCode, Json<InclusionProofBatchResponse>), TreeError> {
+    let size = identity_commitments.len();
+    if size > api.max_batch_size {
+        return Err(TreeError::BatchTooLarge {
+            size,
+            max_size: api.max_batch_size,
+        });
+    }
+
+    rate_limit::charge_batch(rate_limiter.as_deref(), address, size)?;
+
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+
+    let leaf_transform = world_tree.tree_updater.leaf_transform.clone();
+    let tree_data = world_tree.tree_data.clone();
+    let timeout = Duration::from_millis(api.proof_timeout_ms);
+    let deadline = Instant::now() + timeout;
+    let proof_task = tokio::task::spawn_blocking(move || {
+        let tree_data = tree_data.blocking_read();
+
+        let proofs = identity_commitments
+            .into_iter()
+            .map(|commitment| {
+                let leaf = leaf_transform.transform(commitment);
+                tree_data.get_inclusion_proof_bounded(
+                    leaf,
+                    None,
+                    None,
+                    Some(deadline),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok::<_, TreeError>(InclusionProofBatchResponse {
+            root: tree_data.tree.root(),
+            proofs,
+        })
+    });
+
+    let response = await_proof_task(proof_task, timeout).await?;
+
+    Ok((StatusCode::OK, response.into()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct InclusionProofAnyRootRequest {
+    pub identity_commitment: Hash,
+    /// Roots the inclusion proof may be served against
+    pub roots: Vec<Hash>,
+}
+
+/// Serves an inclusion proof against the most recent of several acceptable roots, with the root used returned alongside the proof. Responds with 404 if the identity is not in any version of the tree with one of the roots.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn inclusion_proof_any_root<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    State(rate_limiter): State<Option<Arc<ClientRateLimiter>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Json(req): Json<InclusionProofAnyRootRequest>,
+) -> Result<(StatusCode, Json<InclusionProof>), TreeError> {
+    let size = req.roots.len();
+    if size > api.max_batch_size {
+        return Err(TreeError::BatchTooLarge {
+            size,
+            max_size: api.max_batch_size,
+        });
+    }
+
+    rate_limit::charge_batch(rate_limiter.as_deref(), address, size)?;
+
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+
+    let leaf = world_tree
+        .tree_updater
+        .leaf_transform
+        .transform(req.identity_commitment);
+    let tree_data = world_tree.tree_data.clone();
+    let max_history_depth = api.max_history_depth;
+    let timeout = Duration::from_millis(api.proof_timeout_ms);
+    let deadline = Instant::now() + timeout;
+    let proof_task = tokio::task::spawn_blocking(move || {
+        tree_data.blocking_read().get_inclusion_proof_any_root(
+            leaf,
+            &req.roots,
+            max_history_depth,
+            Some(deadline),
+        )
+    });
+
+    let inclusion_proof = await_proof_task(proof_task, timeout)
+        .await?
+        .ok_or(TreeError::NoMatchingRoot)?;
+
+    Ok((StatusCode::OK, inclusion_proof.into()))
+}
+
+/// Waits for a blocking proof task. The task enforces the proof deadline itself; the timeout here also covers waiting for the tree lock. A panicked task is reported as a server error rather than propagated into the handler.
+async fn await_proof_task<T>(
+    proof_task: JoinHandle<Result<T, TreeError>>,
+    timeout: Duration,
+) -> Result<T, TreeError> {
+    match tokio::time::timeout(timeout, proof_task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(err)) => {
+            tracing::error!(?err, "Inclusion proof task failed");
+            Err(TreeError::ProofTaskFailed)
+        }
+        Err(_) => Err(TreeError::ProofTimeout),
+    }
+}
+
+/// Checks that the tree can be served: it is synced and not being re-applied after a reorg, which may transiently roll it back, and neither a resync nor a provider outage is in progress unless serving through them is enabled.
+fn ensure_servable<M: Middleware>(
+    world_tree: &WorldTree<M>,
+    api: &ApiConfig,
+    circuit_breaker: Option<&CircuitBreaker>,
+) -> Result<(), TreeError> {
+    // While the provider is down the tree cannot advance, so it is only served stale when explicitly enabled
+    if is_outage(circuit_breaker) && !api.serve_stale_on_outage {
+        return Err(TreeError::ProviderOutage);
+    }
+
+    if world_tree.resync.in_progress() && !api.serve_during_resync {
+        return Err(TreeError::ResyncInProgress);
+    }
+
+    let reorg_in_progress = world_tree
+        .tree_updater
+        .reorg_in_progress
+        .load(Ordering::SeqCst);
+    if !world_tree.synced.load(Ordering::Relaxed) || reorg_in_progress {
+        return Err(TreeError::TreeNotSynced);
+    }
+
+    Ok(())
+}
+
+/// Returns whether the provider is considered down, i.e. its circuit breaker is open.
+fn is_outage(circuit_breaker: Option<&CircuitBreaker>) -> bool {
+    matches!(
+        circuit_breaker.map(|breaker| breaker.state()),
+        Some(CircuitState::Open)
+    )
+}
+
+/// Looks up the insertion metadata of an identity. Lookup failures are logged and the metadata omitted, so that the proof is still served.
+async fn insertion_metadata(
+    db: &DatabaseConnection,
+    identity: Hash,
+) -> Option<InsertionMetadata> {
+    match db::find_insertion(db, identity).await {
+        Ok(insertion) => insertion.map(|insertion| InsertionMetadata {
+            inserted_in_block: insertion.inserted_in_block as u64,
+            inserted_at: insertion.created_at.timestamp(),
+        }),
+        Err(err) => {
+            tracing::warn!(?err, "Failed to look up insertion metadata");
+            None
+        }
     }
 }
 
+fn proof_response<T: Serialize>(
+    proof: Option<T>,
+    insertion: Option<InsertionMetadata>,
+) -> Response {
+    let proof =
+        proof.map(|proof| InclusionProofResponse { proof, insertion });
+
+    (StatusCode::OK, Json(proof)).into_response()
+}
+
+/// Serves the latest root signed as an EIP-712 `RootAttestation`, see `attestation::RootAttestation` for the signing domain and type.
+#[tracing::instrument(
+    level = "debug",
+    skip(world_tree, api, circuit_breaker, root_signer)
+)]
+pub async fn signed_root<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    State(root_signer): State<Option<Arc<RootSigner>>>,
+) -> Result<(StatusCode, Json<SignedRoot>), TreeError> {
+    let root_signer = root_signer.ok_or(TreeError::SigningDisabled)?;
+
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+
+    let (root, block_number) = {
+        let tree_data = world_tree.tree_data.read().await;
+        (tree_data.tree.root(), tree_data.latest_root_block)
+    };
+
+    let signed_root = root_signer
+        .sign_root(root, block_number)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Failed to sign root");
+            TreeError::SigningFailed
+        })?;
+
+    Ok((StatusCode::OK, signed_root.into()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZeroHashesResponse {
+    /// Hash of an empty subtree at each level, from the empty leaf at index 0 up to the root of the empty tree at index `depth`
+    pub zero_hashes: Vec<Hash>,
+}
+
+/// Serves the hashes of empty subtrees for each level of the tree.
+#[tracing::instrument(level = "debug", skip(zero_hashes))]
+pub async fn zero_hashes(
+    State(zero_hashes): State<Arc<ZeroHashesResponse>>,
+) -> (StatusCode, Json<ZeroHashesResponse>) {
+    (
+        StatusCode::OK,
+        Json(ZeroHashesResponse {
+            zero_hashes: zero_hashes.zero_hashes.clone(),
+        }),
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeInfoResponse {
+    pub root: Hash,
+    /// Number of identities in the tree, excluding deleted leaves
+    pub num_leaves: usize,
+    pub latest_synced_block: u64,
+    pub depth: usize,
+}
+
+/// Serves the root of the tree along with the number of identities it holds.
+#[tracing::instrument(level = "debug", skip(world_tree))]
+pub async fn tree_info<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+) -> (StatusCode, Json<TreeInfoResponse>) {
+    let latest_synced_block = world_tree
+        .tree_updater
+        .latest_synced_block
+        .load(Ordering::SeqCst);
+
+    let tree_data = world_tree.tree_data.read().await;
+    let tree_info = TreeInfoResponse {
+        root: tree_data.tree.root(),
+        num_leaves: tree_data.leaves.len(),
+        latest_synced_block,
+        depth: tree_data.depth,
+    };
+
+    (StatusCode::OK, Json(tree_info))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityIndexResponse {
+    /// Root of the tree the index was looked up in
+    pub root: Hash,
+    pub leaf_index: usize,
+}
+
+/// Serves the leaf index of an identity commitment in the latest tree, or `null` if the commitment is not in the tree.
+#[tracing::instrument(level = "debug", skip(world_tree, api, circuit_breaker))]
+pub async fn identity_index<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    Path(commitment): Path<Hash>,
+) -> Result<(StatusCode, Json<Option<IdentityIndexResponse>>), TreeError> {
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+
+    let leaf = world_tree.tree_updater.leaf_transform.transform(commitment);
+    let tree_data = world_tree.tree_data.read().await;
+    let response =
+        tree_data
+            .index_of(leaf)
+            .map(|leaf_index| IdentityIndexResponse {
+                root: tree_data.tree.root(),
+                leaf_index,
+            });
+
+    Ok((StatusCode::OK, Json(response)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LeavesParams {
+    pub start: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeavesResponse {
+    /// Index and value of each non-zero leaf in the page, ordered by index
+    pub leaves: Vec<(usize, Hash)>,
+    /// Index to pass as `start` to fetch the next page. `None` once all leaves have been returned
+    pub next: Option<usize>,
+}
+
+/// Serves a page of the non-zero leaves of the latest tree, ordered by leaf index. Pass the returned `next` cursor as `?start=` to fetch the following page.
+#[tracing::instrument(level = "debug", skip(world_tree, api, circuit_breaker))]
+pub async fn leaves<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+    Query(params): Query<LeavesParams>,
+) -> Result<(StatusCode, Json<LeavesResponse>), TreeError> {
+    ensure_servable(&world_tree, &api, circuit_breaker.as_deref())?;
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LEAVES_LIMIT)
+        .min(MAX_LEAVES_LIMIT);
+    let (leaves, next) = world_tree
+        .tree_data
+        .read()
+        .await
+        .leaves_page(params.start, limit);
+
+    Ok((StatusCode::OK, Json(LeavesResponse { leaves, next })))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncResponse {
@@ -197,23 +1069,526 @@ pub async fn synced<M: Middleware>(
     }
 }
 
-#[tracing::instrument(level = "debug")]
-pub async fn health() -> StatusCode {
-    StatusCode::OK
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsResponse {
+    pub synced: bool,
+    pub reorg_in_progress: bool,
+    pub latest_synced_block: u64,
+    pub root: Hash,
+    /// Whether the provider circuit breaker is open, preventing the tree from advancing
+    pub provider_outage: bool,
+    /// Seconds elapsed since the tree was last synced
+    pub staleness: u64,
+    /// Latest root buried by `api.confirmations` blocks, served for `confirmed=true` proof requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmed_root: Option<Hash>,
+    /// Block at which `confirmed_root` was committed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmed_block: Option<u64>,
+    /// Whether a rebuilt tree is being synced in the background
+    pub resync_in_progress: bool,
+    /// Progress of the rebuilt tree towards the live tree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resync_progress: Option<ResyncProgress>,
+}
+
+#[tracing::instrument(level = "debug", skip(world_tree, api, circuit_breaker))]
+pub async fn stats<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+) -> (StatusCode, Json<StatsResponse>) {
+    let tree_updater = &world_tree.tree_updater;
+    let latest_synced_block =
+        tree_updater.latest_synced_block.load(Ordering::SeqCst);
+
+    let (root, confirmed) = {
+        let tree_data = world_tree.tree_data.read().await;
+        (
+            tree_data.tree.root(),
+            tree_data.confirmed_root(latest_synced_block, api.confirmations),
+        )
+    };
+
+    let stats = StatsResponse {
+        synced: world_tree.synced.load(Ordering::Relaxed),
+        reorg_in_progress: tree_updater
+            .reorg_in_progress
+            .load(Ordering::SeqCst),
+        latest_synced_block,
+        root,
+        provider_outage: is_outage(circuit_breaker.as_deref()),
+        staleness: tree_updater.staleness(),
+        confirmed_root: confirmed.map(|(root, _)| root),
+        confirmed_block: confirmed.map(|(_, block)| block),
+        resync_in_progress: world_tree.resync.in_progress(),
+        resync_progress: world_tree.resync.progress(),
+    };
+
+    (StatusCode::OK, stats.into())
+}
+
+/// Starts rebuilding the tree in the background, see `WorldTree::spawn_resync`. Requests must carry the configured `api.admin_token` as a bearer token, see `authorize_admin`.
+///
+/// Responds with 409 if a resync is already in progress.
+#[tracing::instrument(level = "debug", skip(world_tree, api, headers))]
+pub async fn resync<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, TreeError> {
+    authorize_admin(&api, &headers)?;
+
+    Ok(match world_tree.spawn_resync() {
+        Some(_) => StatusCode::ACCEPTED,
+        None => StatusCode::CONFLICT,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminResyncParams {
+    /// First block to replay. 0 rebuilds the tree from the creation block
+    pub from: u64,
+}
+
+/// Starts rebuilding the tree in the background from block `?from=`, see `WorldTree::spawn_resync_from`. Requests must carry the configured `api.admin_token` as a bearer token, see `authorize_admin`.
+///
+/// Responds with 400 if `from` is missing, 422 if the tree history does not reach back to `from`, and 409 if a resync is already in progress.
+#[tracing::instrument(level = "debug", skip(world_tree, api, headers))]
+pub async fn admin_resync<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(api): State<Arc<ApiConfig>>,
+    headers: HeaderMap,
+    Query(params): Query<AdminResyncParams>,
+) -> Result<StatusCode, TreeError> {
+    authorize_admin(&api, &headers)?;
+
+    let handle = world_tree
+        .spawn_resync_from(params.from)
+        .await?;
+
+    Ok(match handle {
+        Some(_) => StatusCode::ACCEPTED,
+        None => StatusCode::CONFLICT,
+    })
+}
+
+/// Checks that a request carries the configured `api.admin_token` as a bearer token. Fails with `TreeError::AdminDisabled` (404) if no admin token is configured, and with `TreeError::Unauthorized` (401) if the token is missing or invalid.
+fn authorize_admin(
+    api: &ApiConfig,
+    headers: &HeaderMap,
+) -> Result<(), TreeError> {
+    let admin_token =
+        api.admin_token.as_deref().ok_or(TreeError::AdminDisabled)?;
+
+    let authorized = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| {
+            constant_time_eq(token.as_bytes(), admin_token.as_bytes())
+        });
+
+    if authorized {
+        Ok(())
+    } else {
+        Err(TreeError::Unauthorized)
+    }
+}
+
+/// Compares two byte strings in time independent of where they first differ, so that the admin token cannot be guessed byte by byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OnchainRootResponse {
+    pub root: Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootUpdate {
+    pub root: Hash,
+}
+
+/// Upgrades the connection to a WebSocket pushing the root of the tree each time a synced window changes it.
+pub async fn root_subscription<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let roots = world_tree.subscribe();
+
+    ws.on_upgrade(move |socket| stream_roots(socket, roots))
+}
+
+/// Pushes root updates to the socket until the client disconnects. A client lagging behind skips the roots it missed and receives the latest root instead of being disconnected.
+async fn stream_roots(
+    mut socket: WebSocket,
+    mut roots: broadcast::Receiver<Hash>,
+) {
+    loop {
+        let root = tokio::select! {
+            root = roots.recv() => root,
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let root = match root {
+            Ok(root) => root,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!(?skipped, "Root subscriber lagged");
+
+                // Only the latest of the buffered roots is relevant to the client
+                let mut latest = None;
+                while let Ok(root) = roots.try_recv() {
+                    latest = Some(root);
+                }
+
+                match latest {
+                    Some(root) => root,
+                    None => continue,
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let update = serde_json::to_string(&RootUpdate { root })
+            .expect("Serializing a root update cannot fail");
+
+        if socket.send(Message::Text(update)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Serves the `latestRoot()` of the `WorldIDIdentityManager`, cached for `onchain_root_cache_ttl`.
+#[tracing::instrument(level = "debug", skip(world_tree))]
+pub async fn onchain_root<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+) -> Result<(StatusCode, Json<OnchainRootResponse>), TreeError> {
+    let root = world_tree
+        .tree_updater
+        .onchain_root
+        .latest_root()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "Failed to fetch onchain root");
+            TreeError::OnchainRootUnavailable
+        })?;
+
+    Ok((StatusCode::OK, OnchainRootResponse { root }.into()))
+}
+
+/// Renders the recorded metrics in the Prometheus text exposition format.
+#[tracing::instrument(level = "debug", skip(prometheus))]
+pub async fn prometheus_metrics(
+    State(prometheus): State<Option<PrometheusHandle>>,
+) -> Result<Response, TreeError> {
+    let prometheus = prometheus.ok_or(TreeError::MetricsDisabled)?;
+
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        prometheus.render(),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthResponse {
+    /// Whether the initial sync of the tree to the chain head has completed
+    pub synced: bool,
+    /// Latest block synced into the tree
+    pub latest_block: u64,
+    /// Whether the root of the tree differed from the onchain latest root at the latest check
+    pub divergent: bool,
+}
+
+/// Reports the sync status of the tree. The service is reported unhealthy until the initial sync has completed, while the provider circuit breaker is open, and while the root of the tree differs from the onchain latest root.
+#[tracing::instrument(level = "debug", skip(world_tree, circuit_breaker))]
+pub async fn health<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let response = HealthResponse {
+        synced: world_tree.synced.load(Ordering::Relaxed),
+        latest_block: world_tree
+            .tree_updater
+            .latest_synced_block
+            .load(Ordering::SeqCst),
+        divergent: world_tree.tree_updater.divergent.load(Ordering::SeqCst),
+    };
+
+    let status = if response.synced
+        && !response.divergent
+        && !is_outage(circuit_breaker.as_deref())
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, response.into())
+}
+
+/// Liveness probe. Reports the process unresponsive when the runtime heartbeat is older than `liveness_timeout_ms`, independently of the sync state of the tree.
+#[tracing::instrument(level = "debug", skip(api, heartbeat))]
+pub async fn live(
+    State(api): State<Arc<ApiConfig>>,
+    State(heartbeat): State<Arc<Heartbeat>>,
+) -> StatusCode {
+    let elapsed = heartbeat.elapsed();
+
+    if elapsed > Duration::from_millis(api.liveness_timeout_ms) {
+        tracing::error!(?elapsed, "Heartbeat missed");
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    }
+}
+
+/// Readiness probe. Reports the service ready to serve proofs once the tree is synced, no reorg is being recovered from and the provider is available.
+#[tracing::instrument(level = "debug", skip(world_tree, circuit_breaker))]
+pub async fn ready<M: Middleware>(
+    State(world_tree): State<Arc<WorldTree<M>>>,
+    State(circuit_breaker): State<Option<Arc<CircuitBreaker>>>,
+) -> StatusCode {
+    let synced = world_tree.synced.load(Ordering::Relaxed);
+    let reorg_in_progress = world_tree
+        .tree_updater
+        .reorg_in_progress
+        .load(Ordering::SeqCst);
+
+    if synced && !reorg_in_progress && !is_outage(circuit_breaker.as_deref())
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
 }
 
 impl TreeError {
     fn to_status_code(&self) -> StatusCode {
         match self {
             TreeError::TreeNotSynced => StatusCode::SERVICE_UNAVAILABLE,
+            TreeError::HistoryLimitExceeded { .. } => StatusCode::GONE,
+            TreeError::ProofTimeout => StatusCode::SERVICE_UNAVAILABLE,
+            TreeError::ProofTaskFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            TreeError::RootEvicted { .. } => StatusCode::GONE,
+            TreeError::SigningDisabled => StatusCode::NOT_FOUND,
+            TreeError::MetricsDisabled => StatusCode::NOT_FOUND,
+            TreeError::SigningFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            TreeError::OnchainRootUnavailable => StatusCode::BAD_GATEWAY,
+            TreeError::ProviderOutage => StatusCode::SERVICE_UNAVAILABLE,
+            TreeError::BatchTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            TreeError::NoConfirmedRoot { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            TreeError::ResyncInProgress => StatusCode::SERVICE_UNAVAILABLE,
+            TreeError::LeafPresent => StatusCode::CONFLICT,
+            TreeError::LeafIndexUnknown => StatusCode::NOT_FOUND,
+            TreeError::NoMatchingRoot => StatusCode::NOT_FOUND,
+            TreeError::AdminDisabled => StatusCode::NOT_FOUND,
+            TreeError::Unauthorized => StatusCode::UNAUTHORIZED,
+            TreeError::ResyncBlockUnavailable(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            TreeError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 }
 
+/// Body returned when a proof is requested against a root that has been evicted from the tree history, pointing the client to the oldest root it can request a proof against instead.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootEvictedResponse {
+    pub error: String,
+    pub oldest_root: Hash,
+    pub oldest_block: u64,
+}
+
 impl IntoResponse for TreeError {
     fn into_response(self) -> axum::response::Response {
         let status_code = self.to_status_code();
+
+        if let TreeError::RootEvicted {
+            oldest_root,
+            oldest_block,
+        } = self
+        {
+            let response_body = RootEvictedResponse {
+                error: self.to_string(),
+                oldest_root,
+                oldest_block,
+            };
+            return (status_code, Json(response_body)).into_response();
+        }
+
         let response_body = self.to_string();
         (status_code, response_body).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::MockProvider;
+    use ethers::types::{Block, Log, H160, H256};
+
+    use super::*;
+
+    const TREE_DEPTH: usize = 10;
+
+    fn synced_world_tree(
+    ) -> (Arc<WorldTree<Provider<MockProvider>>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let config = WorldTreeConfig::new(H160::zero(), 0, TREE_DEPTH, 0, 0);
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            TREE_DEPTH,
+            TREE_DEPTH,
+            &Hash::ZERO,
+        );
+
+        let world_tree = WorldTree::new(tree, &config, Arc::new(provider));
+        world_tree.synced.store(true, Ordering::Relaxed);
+
+        (Arc::new(world_tree), mock)
+    }
+
+    #[tokio::test]
+    async fn test_reads_rejected_during_reorg() {
+        let (world_tree, _mock) = synced_world_tree();
+        let api = Arc::new(ApiConfig::default());
+        let identity = Hash::from(1);
+        world_tree
+            .tree_data
+            .write()
+            .await
+            .insert_many_at(0, &[identity]);
+
+        world_tree
+            .tree_updater
+            .reorg_in_progress
+            .store(true, Ordering::SeqCst);
+
+        let err = inclusion_proof(
+            State(world_tree.clone()),
+            State(api.clone()),
+            State(None),
+            State(None),
+            Query(InclusionProofParams::default()),
+            Json(InclusionProofRequest::new(identity, None)),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let err = contains_batch(
+            State(world_tree.clone()),
+            State(api.clone()),
+            State(None),
+            State(None),
+            ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))),
+            Json(ContainsBatchRequest {
+                identity_commitments: vec![identity],
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let err = leaves(
+            State(world_tree.clone()),
+            State(api.clone()),
+            State(None),
+            Query(LeavesParams::default()),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Proofs are served again once the tree is re-synced past the reorg
+        world_tree
+            .tree_updater
+            .reorg_in_progress
+            .store(false, Ordering::SeqCst);
+
+        let response = inclusion_proof(
+            State(world_tree),
+            State(api),
+            State(None),
+            State(None),
+            Query(InclusionProofParams::default()),
+            Json(InclusionProofRequest::new(identity, None)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_proofs_refused_after_unrecoverable_reorg() {
+        let (world_tree, mock) = synced_world_tree();
+        let api = Arc::new(ApiConfig::default());
+        let identity = Hash::from(1);
+        world_tree
+            .tree_data
+            .write()
+            .await
+            .insert_many_at(0, &[identity]);
+
+        // Blocks 1-10 are synced, recording the hash of block 10. Responses are popped in reverse order of being pushed
+        mock.push::<Vec<Log>, _>(Vec::new()).unwrap();
+        mock.push(Block::<H256> {
+            hash: Some(H256::repeat_byte(1)),
+            ..Default::default()
+        })
+        .unwrap();
+        world_tree
+            .tree_updater
+            .sync_to_block(&world_tree.tree_data, None, 10)
+            .await
+            .unwrap();
+
+        // Block 10 is reorged out, with no earlier recorded block to roll the tree back to
+        mock.push(Block::<H256> {
+            hash: Some(H256::repeat_byte(2)),
+            ..Default::default()
+        })
+        .unwrap();
+        let err = world_tree
+            .tree_updater
+            .sync_to_block(&world_tree.tree_data, None, 20)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TreeAvailabilityError::ReorgDetected { .. }));
+        assert!(world_tree.tree_updater.divergent.load(Ordering::SeqCst));
+
+        // The tree stays unservable until it is rebuilt
+        let err = inclusion_proof(
+            State(world_tree.clone()),
+            State(api),
+            State(None),
+            State(None),
+            Query(InclusionProofParams::default()),
+            Json(InclusionProofRequest::new(identity, None)),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        world_tree.tree_updater.finish_rebuild();
+        assert!(!world_tree
+            .tree_updater
+            .reorg_in_progress
+            .load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,328 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use ethers::providers::Middleware;
+use ethers::types::{H160, H256};
+use semaphore::lazy_merkle_tree::Canonical;
+use serde::{Deserialize, Serialize};
+
+use super::error::SnapshotError;
+use super::{Hash, PoseidonTree, WorldTree};
+
+/// Version of the snapshot format, bumped on incompatible changes.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Leaves of the tree as of `latest_synced_block`, persisted so that a restart resumes syncing from that block instead of `creation_block`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub version: u32,
+    /// Depth of the tree the snapshot was taken from
+    pub tree_depth: usize,
+    /// Address of the `WorldIDIdentityManager` the tree was synced from
+    pub address: H160,
+    /// Last block whose changes are applied to `leaves`
+    pub latest_synced_block: u64,
+    /// Hash of `latest_synced_block`, checked against the canonical chain on load
+    pub block_hash: H256,
+    /// Root of the tree, verified against the restored tree on load
+    pub root: Hash,
+    /// Leaves of the tree ordered by leaf index. Deleted leaves are zero
+    pub leaves: Vec<Hash>,
+}
+
+impl<M: Middleware> WorldTree<M> {
+    /// Persists the leaves of the tree along with the latest synced block to `path`.
+    ///
+    /// The live sync is paused while the leaves are read, so that the snapshot never holds a partially applied block range. A tree divergent from the onchain tree is not saved. The snapshot is written to a temporary file first and renamed into place once synced to disk, so that an interrupted save never leaves a truncated snapshot behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File to write the snapshot to.
+    pub async fn save_snapshot(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+
+        let mut snapshot = {
+            let _sync_guard = self.sync_lock.lock().await;
+            if self.tree_updater.divergent.load(Ordering::SeqCst) {
+                return Err(SnapshotError::TreeDivergent);
+            }
+
+            let tree_data = self.tree_data.read().await;
+
+            Snapshot {
+                version: SNAPSHOT_VERSION,
+                tree_depth: tree_data.depth,
+                address: self.tree_updater.address,
+                latest_synced_block: self
+                    .tree_updater
+                    .latest_synced_block
+                    .load(Ordering::SeqCst),
+                block_hash: H256::zero(),
+                root: tree_data.tree.root(),
+                leaves: tree_data.dense_leaves(),
+            }
+        };
+
+        let block = snapshot.latest_synced_block;
+        snapshot.block_hash = self
+            .block_hash(block)
+            .await?
+            .ok_or(SnapshotError::BlockUnavailable(block))?;
+        let leaves = snapshot.leaves.len();
+
+        // Serializing a large tree takes a while, so it is done off the async runtime
+        let write_path = path.to_owned();
+        tokio::task::spawn_blocking(move || {
+            write_snapshot(&write_path, &snapshot)
+        })
+        .await??;
+
+        tracing::info!(?path, block, leaves, "Saved snapshot");
+
+        Ok(())
+    }
+
+    /// Restores the tree from the snapshot at `path` and resumes syncing after the block it was taken at. Must be called before the tree is synced.
+    ///
+    /// Fails if the snapshot was taken from a tree of another depth or another `WorldIDIdentityManager`, if the block it was taken at is no longer on the canonical chain, or if the restored root does not match the root recorded in the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File to read the snapshot from.
+    pub async fn load_snapshot(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let snapshot: Snapshot =
+            serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::VersionMismatch {
+                snapshot: snapshot.version,
+                supported: SNAPSHOT_VERSION,
+            });
+        }
+
+        // A snapshot taken before a reorg may hold batches that are no longer canonical
+        let canonical = self.block_hash(snapshot.latest_synced_block).await?;
+        if canonical != Some(snapshot.block_hash) {
+            return Err(SnapshotError::BlockHashMismatch {
+                block: snapshot.latest_synced_block,
+                snapshot: snapshot.block_hash,
+                canonical,
+            });
+        }
+
+        let mut tree_data = self.tree_data.write().await;
+
+        if snapshot.tree_depth != tree_data.depth {
+            return Err(SnapshotError::DepthMismatch {
+                snapshot: snapshot.tree_depth,
+                configured: tree_data.depth,
+            });
+        }
+
+        if snapshot.address != self.tree_updater.address {
+            return Err(SnapshotError::AddressMismatch {
+                snapshot: snapshot.address,
+                configured: self.tree_updater.address,
+            });
+        }
+
+        tree_data.restore_leaves(&snapshot.leaves);
+        tree_data.latest_root_block = snapshot.latest_synced_block;
+
+        let restored = tree_data.tree.root();
+        if restored != snapshot.root {
+            return Err(SnapshotError::RootMismatch {
+                snapshot: snapshot.root,
+                restored,
+            });
+        }
+
+        self.tree_updater.resume_from(snapshot.latest_synced_block);
+
+        tracing::info!(
+            ?path,
+            block = snapshot.latest_synced_block,
+            leaves = snapshot.leaves.len(),
+            "Loaded snapshot"
+        );
+
+        Ok(())
+    }
+
+    /// Fetches the hash of `block` on the canonical chain, or `None` if the block is not found.
+    async fn block_hash(
+        &self,
+        block: u64,
+    ) -> Result<Option<H256>, SnapshotError> {
+        let block = self
+            .tree_updater
+            .middleware
+            .get_block(block)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, block, "Failed to fetch block");
+                SnapshotError::BlockUnavailable(block)
+            })?;
+
+        Ok(block.and_then(|block| block.hash))
+    }
+
+    /// Recomputes the root from the leaves of the tree in a fresh tree and compares it to the root of the tree, detecting a tree whose nodes no longer match its leaves, e.g. after restoring a corrupted snapshot. Returns the verified root.
+    pub async fn verify_integrity(&self) -> Result<Hash, SnapshotError> {
+        let tree_data = self.tree_data.read().await;
+
+        let mut tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            tree_data.depth,
+            self.config.dense_prefix_depth,
+            &Hash::ZERO,
+        );
+        for (index, leaf) in tree_data.dense_leaves().iter().enumerate() {
+            if *leaf != Hash::ZERO {
+                tree = tree.update_with_mutation(index, leaf);
+            }
+        }
+
+        let stored = tree_data.tree.root();
+        let recomputed = tree.root();
+        if stored != recomputed {
+            return Err(SnapshotError::IntegrityMismatch {
+                stored,
+                recomputed,
+            });
+        }
+
+        tracing::info!(root = ?stored, "Verified tree integrity");
+
+        Ok(stored)
+    }
+}
+
+/// Writes `snapshot` to a temporary file next to `path` and renames it into place once it is synced to disk.
+fn write_snapshot(
+    path: &Path,
+    snapshot: &Snapshot,
+) -> Result<(), SnapshotError> {
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    serde_json::to_writer(&mut writer, snapshot)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::providers::{MockProvider, Provider};
+    use ethers::types::Block;
+
+    use super::*;
+    use crate::tree::config::WorldTreeConfig;
+
+    const TREE_DEPTH: usize = 10;
+
+    fn world_tree(
+        tree_depth: usize,
+    ) -> (WorldTree<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let config = WorldTreeConfig::new(H160::zero(), 0, tree_depth, 0, 0);
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            tree_depth,
+            tree_depth,
+            &Hash::ZERO,
+        );
+
+        (WorldTree::new(tree, &config, Arc::new(provider)), mock)
+    }
+
+    fn block_with_hash(hash: H256) -> Block<H256> {
+        Block {
+            hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("world-tree-snapshot-{}.json", std::process::id()));
+
+        let (source, source_mock) = world_tree(TREE_DEPTH);
+        let identities: Vec<Hash> = (1..=5).map(Hash::from).collect();
+        source
+            .tree_data
+            .write()
+            .await
+            .insert_many_at(0, &identities);
+        source.tree_updater.resume_from(100);
+        source_mock
+            .push(block_with_hash(H256::repeat_byte(1)))
+            .unwrap();
+        source.save_snapshot(&path).await.unwrap();
+
+        let (restored, mock) = world_tree(TREE_DEPTH);
+        mock.push(block_with_hash(H256::repeat_byte(1))).unwrap();
+        restored.load_snapshot(&path).await.unwrap();
+        assert_eq!(
+            restored.verify_integrity().await.unwrap(),
+            source.tree_data.read().await.tree.root()
+        );
+
+        assert_eq!(
+            restored.tree_data.read().await.tree.root(),
+            source.tree_data.read().await.tree.root()
+        );
+        assert_eq!(
+            restored
+                .tree_updater
+                .latest_synced_block
+                .load(Ordering::SeqCst),
+            100
+        );
+
+        // A snapshot of a tree of another depth is refused
+        let (other, mock) = world_tree(TREE_DEPTH + 1);
+        mock.push(block_with_hash(H256::repeat_byte(1))).unwrap();
+        let err = other.load_snapshot(&path).await.unwrap_err();
+        assert!(matches!(err, SnapshotError::DepthMismatch { .. }));
+
+        // A snapshot taken at a block reorged out since is refused
+        let (reorged, mock) = world_tree(TREE_DEPTH);
+        mock.push(block_with_hash(H256::repeat_byte(2))).unwrap();
+        let err = reorged.load_snapshot(&path).await.unwrap_err();
+        assert!(matches!(err, SnapshotError::BlockHashMismatch { .. }));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_divergent_tree_is_not_saved() {
+        let path = std::env::temp_dir().join(format!(
+            "world-tree-divergent-snapshot-{}.json",
+            std::process::id()
+        ));
+
+        let (world_tree, _mock) = world_tree(TREE_DEPTH);
+        world_tree
+            .tree_updater
+            .divergent
+            .store(true, Ordering::SeqCst);
+
+        let err = world_tree.save_snapshot(&path).await.unwrap_err();
+        assert!(matches!(err, SnapshotError::TreeDivergent));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use super::config::RestartPolicy;
+
+/// Spawns a task supervising the task returned by `spawn`, respawning it after `policy.backoff_ms` whenever it returns an error.
+///
+/// The supervisor gives up and returns the error once the task has been restarted `policy.max_restarts` times within `policy.window_secs`, so that a persistent failure does not turn into a hot crash loop. It returns `Ok` once the task returns `Ok`, and the error of the last failure if `shutdown` is cancelled while waiting to restart. Panics are propagated rather than restarted.
+///
+/// # Arguments
+///
+/// * `name` - Name of the supervised task, used in logs and metrics.
+/// * `policy` - Budget of restarts and backoff between them.
+/// * `shutdown` - Token cancelled to stop restarting the task.
+/// * `spawn` - Spawns a new instance of the supervised task.
+pub fn supervise<E, F>(
+    name: &'static str,
+    policy: RestartPolicy,
+    shutdown: CancellationToken,
+    mut spawn: F,
+) -> JoinHandle<Result<(), E>>
+where
+    E: Debug + Send + 'static,
+    F: FnMut() -> JoinHandle<Result<(), E>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let window = Duration::from_secs(policy.window_secs);
+        let backoff = Duration::from_millis(policy.backoff_ms);
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+
+        loop {
+            let err = match spawn().await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(err)) => err,
+                Err(err) if err.is_panic() => {
+                    std::panic::resume_unwind(err.into_panic())
+                }
+                Err(_) => return Ok(()),
+            };
+
+            if shutdown.is_cancelled() {
+                return Err(err);
+            }
+
+            // Only the restarts within the window count towards the budget
+            while restarts
+                .front()
+                .is_some_and(|restart| restart.elapsed() > window)
+            {
+                restarts.pop_front();
+            }
+
+            if restarts.len() >= policy.max_restarts as usize {
+                tracing::error!(
+                    task = name,
+                    ?err,
+                    max_restarts = policy.max_restarts,
+                    "Task exceeded its restart budget"
+                );
+                return Err(err);
+            }
+
+            restarts.push_back(Instant::now());
+            metrics::increment_counter!("supervisor.restart", "task" => name);
+            tracing::error!(
+                task = name,
+                ?err,
+                restarts = restarts.len(),
+                ?backoff,
+                "Task failed, restarting"
+            );
+
+            tokio::select! {
+                _ = shutdown.cancelled() => return Err(err),
+                _ = tokio::time::sleep(backoff) => {}
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn policy(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts,
+            window_secs: 60,
+            backoff_ms: 0,
+        }
+    }
+
+    /// Spawns a task failing on its first `failures` runs, counting the runs in `runs`.
+    fn flaky_task(
+        runs: Arc<AtomicU32>,
+        failures: u32,
+    ) -> impl FnMut() -> JoinHandle<Result<(), u32>> + Send + 'static {
+        move || {
+            let runs = runs.clone();
+            tokio::spawn(async move {
+                let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+                if run <= failures {
+                    Err(run)
+                } else {
+                    Ok(())
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_supervise_restarts_failed_task() {
+        let runs = Arc::new(AtomicU32::new(0));
+
+        let result = supervise(
+            "test",
+            policy(3),
+            CancellationToken::new(),
+            flaky_task(runs.clone(), 2),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_supervise_gives_up_after_restart_budget() {
+        let runs = Arc::new(AtomicU32::new(0));
+
+        let result = supervise(
+            "test",
+            policy(2),
+            CancellationToken::new(),
+            flaky_task(runs.clone(), u32::MAX),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, Err(3));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,14 +1,26 @@
 use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::time::Instant;
 
+use ethers::abi::{self, Token};
+use ethers::types::{Bytes, U256};
 use semaphore::lazy_merkle_tree::{Canonical, Derived, VersionMarker};
-use semaphore::poseidon_tree::{ Proof};
+use semaphore::poseidon_tree::{PoseidonHash, Proof};
 use semaphore::Field;
 use semaphore::merkle_tree::{Branch, Hasher};
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
+use super::error::TreeError;
+use super::metrics::record_evicted_root_request;
 use super::{Hash, PoseidonTree};
 
+/// Number of replaced roots remembered when `tree_history_size` is 0, so that requests against them are reported as evicted rather than unknown.
+pub const LATEST_ONLY_EVICTED_ROOTS: usize = 64;
+
+/// Number of leaves scanned for an identity between checks of the proof deadline.
+const DEADLINE_CHECK_INTERVAL: usize = 1 << 12;
+
 macro_rules! current_unix_timestamp {
     () => {{
         std::time::SystemTime::now()
@@ -29,15 +41,25 @@ pub struct TreeData {
     pub tree_history_size: usize,
     /// Timestamp representing when the most recent root was received.
     pub latest_root_timestamp: u64,
+    /// Block at which the most recent root was committed onchain.
+    pub latest_root_block: u64,
     /// Cache of historical tree state, used to serve proofs against older roots. If the cache becomes larger than `tree_history_size`, the oldest roots are removed on a FIFO basis.
     pub tree_history: VecDeque<HistoricalTree>,
     /// Maintains the valid leaves in tree as well as the timestamp that they were inserted.
     pub leaves: HashMap<Hash, u64>,
+    /// Index of each valid leaf in the tree. Kept consistent with `leaves`, so deleted leaves have no index.
+    pub leaf_indices: HashMap<Hash, usize>,
+    /// Index each deleted leaf was held at before being zeroed, used to prove its absence from the tree.
+    pub deleted_indices: HashMap<Hash, usize>,
+    /// Roots most recently evicted from `tree_history`, retained to distinguish evicted roots from unknown roots. Bounded by `tree_history_size`, or by `LATEST_ONLY_EVICTED_ROOTS` when only the latest root is kept.
+    pub evicted_roots: VecDeque<Hash>,
+    /// Index following the highest leaf ever inserted. Deleted leaves are zeroed in place, so all leaves of the tree lie below this index.
+    pub next_leaf_index: usize,
 }
 
 impl TreeData {
     /// * `tree` - PoseidonTree representing the World Tree onchain, which will be used to generate inclusion proofs.
-    /// * `tree_history_size` - Number of previous tree states to retain for serving proofs with historical roots.
+    /// * `tree_history_size` - Number of previous tree states to retain for serving proofs with historical roots. If 0, only proofs against the latest root are served.
     pub fn new(
         tree: PoseidonTree<Canonical>,
         tree_history_size: usize,
@@ -48,7 +70,12 @@ impl TreeData {
             tree: tree.derived(),
             tree_history: VecDeque::new(),
             leaves: HashMap::new(),
+            leaf_indices: HashMap::new(),
+            deleted_indices: HashMap::new(),
             latest_root_timestamp: 0,
+            latest_root_block: 0,
+            evicted_roots: VecDeque::new(),
+            next_leaf_index: 0,
         }
     }
 
@@ -66,10 +93,35 @@ impl TreeData {
             let idx = start_index + i;
             self.tree = self.tree.update(idx, identity);
             self.leaves.insert(*identity, timestamp);
+            self.leaf_indices.insert(*identity, idx);
+            self.deleted_indices.remove(identity);
 
             tracing::info!(?identity, ?idx, "Inserted identity");
         }
 
+        self.next_leaf_index =
+            self.next_leaf_index.max(start_index + identities.len());
+        self.latest_root_timestamp = timestamp;
+    }
+
+    /// Restores leaves persisted elsewhere into the tree, starting at index 0, without caching any tree history. Zero leaves hold deleted identities and are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - Leaves of the tree, ordered by leaf index.
+    pub fn restore_leaves(&mut self, leaves: &[Hash]) {
+        let timestamp = current_unix_timestamp!();
+        for (idx, leaf) in leaves.iter().enumerate() {
+            if *leaf == Hash::ZERO {
+                continue;
+            }
+
+            self.tree = self.tree.update(idx, leaf);
+            self.leaves.insert(*leaf, timestamp);
+            self.leaf_indices.insert(*leaf, idx);
+        }
+
+        self.next_leaf_index = self.next_leaf_index.max(leaves.len());
         self.latest_root_timestamp = timestamp;
     }
 
@@ -84,6 +136,10 @@ impl TreeData {
         for idx in delete_indices.iter() {
             let identity = self.tree.get_leaf(*idx);
             self.leaves.remove(&identity);
+            self.leaf_indices.remove(&identity);
+            if identity != Hash::ZERO {
+                self.deleted_indices.insert(identity, *idx);
+            }
 
             self.tree = self.tree.update(*idx, &Hash::ZERO);
             tracing::info!(?idx, "Deleted identity");
@@ -92,29 +148,176 @@ impl TreeData {
         self.latest_root_timestamp = current_unix_timestamp!();
     }
 
-    /// Caches the current tree state to `tree_history` if `tree_history_size` is greater than 0.
+    /// Caches the current tree state to `tree_history` if `tree_history_size` is greater than 0. Otherwise only the latest root is kept, and the current root is evicted right away.
     pub fn cache_tree_history(&mut self) {
-        if self.tree_history_size != 0 {
-            if self.tree_history.len() == self.tree_history_size {
-                let historical_tree = self
-                    .tree_history
-                    .pop_back()
-                    .expect("Tree history length should be > 0");
-
-                let historical_root = historical_tree.tree.root();
-                tracing::info!(?historical_root, "Popping tree from history",);
+        if self.tree_history_size == 0 {
+            self.evict_root(self.tree.root());
+            return;
+        }
+
+        if self.tree_history.len() == self.tree_history_size {
+            let historical_tree = self
+                .tree_history
+                .pop_back()
+                .expect("Tree history length should be > 0");
+
+            let historical_root = historical_tree.tree.root();
+            tracing::info!(?historical_root, "Popping tree from history",);
+
+            self.evict_root(historical_root);
+        }
+
+        let new_root = self.tree.root();
+        tracing::info!(?new_root, "Pushing tree to history",);
+
+        self.tree_history.push_front(HistoricalTree::new(
+            self.tree.clone(),
+            self.latest_root_timestamp,
+            self.latest_root_block,
+        ));
+    }
+
+    /// Records a root evicted from the tree history, forgetting the oldest evicted root once `evicted_roots` is full.
+    fn evict_root(&mut self, root: Hash) {
+        let capacity = if self.tree_history_size == 0 {
+            LATEST_ONLY_EVICTED_ROOTS
+        } else {
+            self.tree_history_size
+        };
+
+        if self.evicted_roots.len() == capacity {
+            self.evicted_roots.pop_back();
+        }
+        self.evicted_roots.push_front(root);
+    }
+
+    /// Rolls the tree back to its state as of `block`, discarding the tree history committed after it. Used to re-apply the changes following the fork point of a reorg.
+    ///
+    /// Returns `false`, leaving the tree untouched, if the tree history does not reach back to `block`.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - Latest block whose changes remain applied.
+    pub fn rollback_to(&mut self, block: u64) -> bool {
+        if self.latest_root_block <= block {
+            return true;
+        }
+
+        let Some(position) = self
+            .tree_history
+            .iter()
+            .position(|historical_tree| historical_tree.root_block <= block)
+        else {
+            return false;
+        };
+
+        let restored = self.tree_history[position].clone();
+        self.tree_history.drain(..=position);
+
+        let timestamp = current_unix_timestamp!();
+        for idx in 0..self.next_leaf_index {
+            let current = self.tree.get_leaf(idx);
+            let leaf = restored.tree.get_leaf(idx);
+
+            if current == leaf {
+                continue;
             }
 
-            let new_root = self.tree.root();
-            tracing::info!(?new_root, "Pushing tree to history",);
+            if current != Hash::ZERO {
+                self.leaves.remove(&current);
+                self.leaf_indices.remove(&current);
+            }
+            if leaf != Hash::ZERO {
+                self.leaves.entry(leaf).or_insert(timestamp);
+                self.leaf_indices.insert(leaf, idx);
+                self.deleted_indices.remove(&leaf);
+            }
+        }
+
+        tracing::warn!(
+            from_block = self.latest_root_block,
+            to_block = restored.root_block,
+            "Rolled back tree"
+        );
 
-            self.tree_history.push_front(HistoricalTree::new(
-                self.tree.clone(),
-                self.latest_root_timestamp,
-            ));
+        self.tree = restored.tree;
+        self.latest_root_timestamp = restored.root_timestamp;
+        self.latest_root_block = restored.root_block;
+
+        true
+    }
+
+    /// Returns the leaves of the tree as of `block`, along with the block at which that version of the tree was committed. Leaves are ordered by leaf index, up to the last non-empty leaf.
+    ///
+    /// Returns `None` if the tree history does not reach back to `block`.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - Latest block whose changes the leaves include.
+    pub fn leaves_as_of(&self, block: u64) -> Option<(Vec<Hash>, u64)> {
+        let indices = 0..self.next_leaf_index;
+        let (mut leaves, root_block): (Vec<Hash>, u64) =
+            if self.latest_root_block <= block {
+                (
+                    indices.map(|index| self.tree.get_leaf(index)).collect(),
+                    self.latest_root_block,
+                )
+            } else {
+                let historical_tree =
+                    self.tree_history.iter().find(|historical_tree| {
+                        historical_tree.root_block <= block
+                    })?;
+                (
+                    indices
+                        .map(|index| historical_tree.tree.get_leaf(index))
+                        .collect(),
+                    historical_tree.root_block,
+                )
+            };
+
+        while leaves.last() == Some(&Hash::ZERO) {
+            leaves.pop();
+        }
+
+        Some((leaves, root_block))
+    }
+
+    /// Returns the oldest root still available in the tree history, along with the block at which it was committed.
+    pub fn oldest_root(&self) -> (Hash, u64) {
+        match self.tree_history.back() {
+            Some(historical_tree) => {
+                (historical_tree.tree.root(), historical_tree.root_block)
+            }
+            None => (self.tree.root(), self.latest_root_block),
         }
     }
 
+    /// Returns the most recent root committed at least `confirmations` blocks before `head_block`, along with the block at which it was committed. Returns `None` if no root in the tree history is buried deep enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `head_block` - Block the confirmations are counted from.
+    /// * `confirmations` - Number of blocks the root must be buried by.
+    pub fn confirmed_root(
+        &self,
+        head_block: u64,
+        confirmations: u64,
+    ) -> Option<(Hash, u64)> {
+        let is_confirmed =
+            |root_block: u64| root_block + confirmations <= head_block;
+
+        if is_confirmed(self.latest_root_block) {
+            return Some((self.tree.root(), self.latest_root_block));
+        }
+
+        self.tree_history
+            .iter()
+            .find(|historical_tree| is_confirmed(historical_tree.root_block))
+            .map(|historical_tree| {
+                (historical_tree.tree.root(), historical_tree.root_block)
+            })
+    }
+
     /// Fetches the inclusion proof for a given identity against a specified root. If no root is specified, the latest root is used. Returns `None` if root or identity is not found.
     ///
     /// # Arguments
@@ -125,74 +328,279 @@ impl TreeData {
         &self,
         identity: Hash,
         root: Option<Hash>,
-    ) -> Option<InclusionProof> {
+    ) -> Result<Option<InclusionProof>, TreeError> {
+        self.get_inclusion_proof_bounded(identity, root, None, None)
+    }
+
+    /// Fetches the inclusion proof for a given identity against a specified root, traversing at most `max_history_depth` historical tree versions. If no root is specified, the latest root is used. Returns `None` if root or identity is not found, and `TreeError::RootEvicted` if the root has recently been evicted from the tree history.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity commitment for which to fetch the inclusion proof.
+    /// * `root` - Optional root hash to serve the inclusion proof against. If `None`, uses the latest root.
+    /// * `max_history_depth` - Maximum number of historical tree versions to traverse. If the root is further back, `TreeError::HistoryLimitExceeded` is returned. If `None`, the whole tree history is searched.
+    /// * `deadline` - Instant after which the proof generation is abandoned with `TreeError::ProofTimeout`. If `None`, the proof is generated however long it takes.
+    pub fn get_inclusion_proof_bounded(
+        &self,
+        identity: Hash,
+        root: Option<Hash>,
+        max_history_depth: Option<usize>,
+        deadline: Option<Instant>,
+    ) -> Result<Option<InclusionProof>, TreeError> {
         // Get the timestamp that the leaf was inserted into the tree. If the leaf does not exist, None will be returned.
-        let leaf_timestamp = self.leaves.get(&identity)?;
+        let Some(leaf_timestamp) = self.leaves.get(&identity) else {
+            return Ok(None);
+        };
+
+        // If the root is not specified, return a proof at the latest root
+        let Some(root) = root else {
+            let latest_root = self.tree.root();
+            tracing::info!(?identity, ?latest_root, "Getting inclusion proof");
 
-        if let Some(root) = root {
-            // If the root is the latest root, use the current version of the tree
-            if root == self.tree.root() {
-                tracing::info!(?identity, ?root, "Getting inclusion proof");
+            return Ok(Self::proof(&self.tree, identity, deadline)?
+                .map(|proof| InclusionProof::new(latest_root, proof)));
+        };
 
-                return Some(InclusionProof::new(
-                    root,
-                    Self::proof(&self.tree, identity)?,
-                ));
-            } else {
-                // Otherwise, search the tree history for the root and use the corresponding tree
-                for prev_tree in self.tree_history.iter() {
-                    if prev_tree.tree.root() == root {
-                        // If the tree root was committed after the leaf, the leaf does not exist in this tree
-                        if prev_tree.root_timestamp < *leaf_timestamp {
-                            //TODO: return some error here if the leaf is not in the tree
-                            return None;
-                        }
-
-                        tracing::info!(
-                            ?identity,
-                            ?root,
-                            "Getting inclusion proof"
-                        );
-
-                        return Some(InclusionProof::new(
-                            root,
-                            Self::proof(&prev_tree.tree, identity)?,
-                        ));
-                    }
-                }
+        // If the root is the latest root, use the current version of the tree
+        if root == self.tree.root() {
+            tracing::info!(?identity, ?root, "Getting inclusion proof");
+            metrics::histogram!(
+                "tree_availability.tree_data.history_depth",
+                0.0
+            );
+
+            return Ok(Self::proof(&self.tree, identity, deadline)?
+                .map(|proof| InclusionProof::new(root, proof)));
+        }
+
+        // Otherwise, search the tree history for the root and use the corresponding tree
+        let Some((idx, prev_tree)) = self
+            .tree_history
+            .iter()
+            .enumerate()
+            .find(|(_, prev_tree)| prev_tree.tree.root() == root)
+        else {
+            if self.evicted_roots.contains(&root) {
+                record_evicted_root_request();
+
+                let (oldest_root, oldest_block) = self.oldest_root();
+                tracing::warn!(
+                    ?identity,
+                    ?root,
+                    ?oldest_root,
+                    "Could not get inclusion proof. Root evicted from tree history."
+                );
+                return Err(TreeError::RootEvicted {
+                    oldest_root,
+                    oldest_block,
+                });
             }
 
-            //TODO: should return an error if the tree root is specified but not in history
             tracing::warn!(
                 ?identity,
                 ?root,
                 "Could not get inclusion proof. Root not in tree history."
             );
-            None
-        } else {
-            let latest_root = self.tree.root();
-            tracing::info!(?identity, ?latest_root, "Getting inclusion proof");
-            // If the root is not specified, return a proof at the latest root
-            Some(InclusionProof::new(
-                latest_root,
-                Self::proof(&self.tree, identity)?,
-            ))
+            return Ok(None);
+        };
+
+        let depth = idx + 1;
+        metrics::histogram!(
+            "tree_availability.tree_data.history_depth",
+            depth as f64
+        );
+
+        if let Some(max_depth) = max_history_depth {
+            if depth > max_depth {
+                tracing::warn!(
+                    ?identity,
+                    ?root,
+                    ?depth,
+                    ?max_depth,
+                    "Could not get inclusion proof. Root exceeds history limit."
+                );
+                return Err(TreeError::HistoryLimitExceeded {
+                    depth,
+                    max_depth,
+                });
+            }
+        }
+
+        // If the tree root was committed after the leaf, the leaf does not exist in this tree
+        if prev_tree.root_timestamp < *leaf_timestamp {
+            //TODO: return some error here if the leaf is not in the tree
+            return Ok(None);
+        }
+
+        tracing::info!(?identity, ?root, "Getting inclusion proof");
+
+        Ok(Self::proof(&prev_tree.tree, identity, deadline)?
+            .map(|proof| InclusionProof::new(root, proof)))
+    }
+
+    /// Fetches the inclusion proof for a given identity against the most recent of several acceptable roots, for clients that accept any of them. The latest root is tried first, then the tree history from the newest version to the oldest. Returns `None` if the identity is not in any version of the tree with an acceptable root.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity commitment for which to fetch the inclusion proof.
+    /// * `roots` - Roots the inclusion proof may be served against.
+    /// * `max_history_depth` - Maximum number of historical tree versions to traverse. Roots further back are not considered. If `None`, the whole tree history is searched.
+    /// * `deadline` - Instant after which the proof generation is abandoned with `TreeError::ProofTimeout`. If `None`, the proof is generated however long it takes.
+    pub fn get_inclusion_proof_any_root(
+        &self,
+        identity: Hash,
+        roots: &[Hash],
+        max_history_depth: Option<usize>,
+        deadline: Option<Instant>,
+    ) -> Result<Option<InclusionProof>, TreeError> {
+        let versions = std::iter::once(self.tree.root())
+            .chain(self.tree_history.iter().map(|prev| prev.tree.root()))
+            .take(max_history_depth.map_or(usize::MAX, |depth| depth + 1));
+
+        for root in versions.filter(|root| roots.contains(root)) {
+            if let Some(inclusion_proof) = self.get_inclusion_proof_bounded(
+                identity,
+                Some(root),
+                max_history_depth,
+                deadline,
+            )? {
+                return Ok(Some(inclusion_proof));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetches a proof that a deleted identity commitment is no longer in the tree, against the latest root. The proof is the inclusion proof of the empty leaf at the index the identity was inserted at, as recorded in `deleted_indices` when it was deleted.
+    ///
+    /// Returns `TreeError::LeafPresent` if the identity is in the tree, and `TreeError::LeafIndexUnknown` if it is not known to have been deleted, either because it was never inserted or because it was deleted before the tree was restored from persisted leaves.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The deleted identity commitment to prove the absence of.
+    pub fn get_exclusion_proof(
+        &self,
+        identity: Hash,
+    ) -> Result<ExclusionProof, TreeError> {
+        if self.leaves.contains_key(&identity) {
+            return Err(TreeError::LeafPresent);
+        }
+
+        // Deleted leaves are zeroed in place, so the empty slot is at the index the identity was inserted at
+        let leaf_index = *self
+            .deleted_indices
+            .get(&identity)
+            .ok_or(TreeError::LeafIndexUnknown)?;
+
+        let root = self.tree.root();
+        tracing::info!(
+            ?identity,
+            ?root,
+            ?leaf_index,
+            "Getting exclusion proof"
+        );
+
+        Ok(ExclusionProof {
+            root,
+            leaf_index,
+            leaf: self.tree.get_leaf(leaf_index),
+            proof: self.tree.proof(leaf_index),
+        })
+    }
+
+    /// Returns the index of a leaf in the latest tree, or `None` if the leaf is not in the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The leaf to look up.
+    pub fn index_of(&self, leaf: Hash) -> Option<usize> {
+        self.leaf_indices.get(&leaf).copied()
+    }
+
+    /// Returns up to `limit` non-zero leaves of the latest tree at or after index `start`, ordered by leaf index, along with the index to resume from to fetch the next page. The cursor is `None` once there are no further leaves.
+    ///
+    /// Leaves are never moved once inserted, so leaf indices serve as a stable cursor across pages.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - First leaf index to return.
+    /// * `limit` - Maximum number of leaves to return.
+    pub fn leaves_page(
+        &self,
+        start: usize,
+        limit: usize,
+    ) -> (Vec<(usize, Hash)>, Option<usize>) {
+        let mut leaves = Vec::new();
+
+        for index in start..self.next_leaf_index {
+            if leaves.len() == limit {
+                return (leaves, Some(index));
+            }
+
+            let leaf = self.tree.get_leaf(index);
+            if leaf != Hash::ZERO {
+                leaves.push((index, leaf));
+            }
         }
+
+        (leaves, None)
+    }
+
+    /// Returns the leaves of the tree ordered by leaf index, up to `next_leaf_index`. Deleted leaves are zero.
+    pub fn dense_leaves(&self) -> Vec<Hash> {
+        (0..self.next_leaf_index)
+            .map(|index| self.tree.get_leaf(index))
+            .collect()
+    }
+
+    /// Compares the leaves of the tree against a set of locally stored leaves, returning the first index at which they differ.
+    ///
+    /// # Arguments
+    ///
+    /// * `local` - Leaves to compare against the tree, ordered by leaf index. Indices beyond the end of `local` are treated as empty leaves.
+    /// * `indices` - Range of leaf indices to compare.
+    pub fn first_divergence(
+        &self,
+        local: &[Hash],
+        indices: Range<usize>,
+    ) -> Option<LeafDivergence> {
+        indices.find_map(|index| {
+            let local = local.get(index).copied().unwrap_or(Hash::ZERO);
+            let expected = self.tree.get_leaf(index);
+
+            (local != expected).then_some(LeafDivergence {
+                index,
+                local,
+                expected,
+            })
+        })
     }
 
-    /// Generates an inclusion proof for a specific identity commitment from a given `PoseidonTree`.
+    /// Generates an inclusion proof for a specific identity commitment from a given `PoseidonTree`. Returns `TreeError::ProofTimeout` if `deadline` passes while the leaves are scanned for the identity.
     ///
     /// # Arguments
     ///
     /// * `tree` - The Poseidon tree to fetch the inclusion proof against.
-    /// * `idx` - Leaf index of the target identity to generate the inclusion proof for.
+    /// * `identity` - The identity commitment to generate the inclusion proof for.
+    /// * `deadline` - Instant after which the scan is abandoned. If `None`, the whole tree may be scanned.
     fn proof<V: VersionMarker>(
         tree: &PoseidonTree<V>,
         identity: Hash,
-    ) -> Option<Proof> {
-        let idx = tree.leaves().position(|leaf| leaf == identity)?;
+        deadline: Option<Instant>,
+    ) -> Result<Option<Proof>, TreeError> {
+        for (idx, leaf) in tree.leaves().enumerate() {
+            if leaf == identity {
+                return Ok(Some(tree.proof(idx)));
+            }
 
-        Some(tree.proof(idx))
+            if idx % DEADLINE_CHECK_INTERVAL == 0
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(TreeError::ProofTimeout);
+            }
+        }
+
+        Ok(None)
     }
 }
 
@@ -207,19 +615,84 @@ impl InclusionProof {
     pub fn new(root: Field, proof: Proof) -> InclusionProof {
         Self { root, proof }
     }
+
+    /// Encodes the proof in the layout expected by verifier contracts, equivalent to `abi.encode(uint256 root, uint256 leafIndex, uint256[] siblings)`.
+    ///
+    /// `siblings` holds one sibling hash per tree level, ordered from the leaf up to the root. `leafIndex` is the position of the identity in the tree, where bit `i` set means the node at level `i` is a right child.
+    pub fn abi_encode(&self) -> Bytes {
+        let siblings = self
+            .proof
+            .0
+            .iter()
+            .map(|branch| match branch {
+                Branch::Left(sibling) | Branch::Right(sibling) => {
+                    Token::Uint(U256(sibling.into_limbs()))
+                }
+            })
+            .collect();
+
+        abi::encode(&[
+            Token::Uint(U256(self.root.into_limbs())),
+            Token::Uint(U256::from(self.proof.leaf_index())),
+            Token::Array(siblings),
+        ])
+        .into()
+    }
+}
+
+/// Proof that an identity commitment is no longer in the tree, see `TreeData::get_exclusion_proof`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExclusionProof {
+    pub root: Field,
+    /// Index the identity was inserted at
+    pub leaf_index: usize,
+    /// Current value of the leaf at `leaf_index`, zero once the identity is deleted
+    pub leaf: Hash,
+    /// Inclusion proof of `leaf` at `leaf_index`. Its branches hold the hashes of the occupied neighbors needed to recompute `root`
+    pub proof: Proof,
+}
+
+/// A leaf whose locally stored value differs from the value derived from onchain state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeafDivergence {
+    pub index: usize,
+    pub local: Hash,
+    pub expected: Hash,
+}
+
+/// Computes the hashes of empty subtrees for each level of a Poseidon tree of the given depth, from the empty leaf at level 0 up to the root of the empty tree at level `depth`.
+pub fn zero_hashes(depth: usize) -> Vec<Hash> {
+    let mut zero_hashes = Vec::with_capacity(depth + 1);
+    let mut zero_hash = Hash::ZERO;
+
+    zero_hashes.push(zero_hash);
+    for _ in 0..depth {
+        zero_hash = PoseidonHash::hash_node(&zero_hash, &zero_hash);
+        zero_hashes.push(zero_hash);
+    }
+
+    zero_hashes
 }
 
 #[derive(Clone)]
 pub struct HistoricalTree {
     pub tree: PoseidonTree<Derived>,
     pub root_timestamp: u64,
+    pub root_block: u64,
 }
 
 impl HistoricalTree {
-    pub fn new(tree: PoseidonTree<Derived>, root_timestamp: u64) -> Self {
+    pub fn new(
+        tree: PoseidonTree<Derived>,
+        root_timestamp: u64,
+        root_block: u64,
+    ) -> Self {
         HistoricalTree {
             tree,
             root_timestamp,
+            root_block,
         }
     }
 }
@@ -278,12 +751,70 @@ mod tests {
         {
             let proof_from_world_tree = tree_data
                 .get_inclusion_proof(*identity, Some(root))
+                .unwrap()
                 .unwrap();
 
             assert_eq!(ref_tree.proof(i), proof_from_world_tree.proof);
         }
     }
 
+    #[test]
+    fn test_get_inclusion_proof_any_root() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        tree_data.insert_many_at(0, &identities[0..5]);
+        let intermediate_root = tree_data.tree.root();
+        tree_data.insert_many_at(5, &identities[5..]);
+        let latest_root = tree_data.tree.root();
+        let unknown_root = Hash::from(12345);
+
+        // The most recent acceptable root is used
+        let inclusion_proof = tree_data
+            .get_inclusion_proof_any_root(
+                identities[2],
+                &[intermediate_root, latest_root],
+                None,
+                None,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(inclusion_proof.root, latest_root);
+
+        let inclusion_proof = tree_data
+            .get_inclusion_proof_any_root(
+                identities[2],
+                &[unknown_root, intermediate_root],
+                None,
+                None,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(inclusion_proof.root, intermediate_root);
+
+        // An identity inserted after the only acceptable root cannot be proven
+        assert!(tree_data
+            .get_inclusion_proof_any_root(
+                identities[7],
+                &[intermediate_root],
+                None,
+                None
+            )
+            .unwrap()
+            .is_none());
+
+        // Roots beyond the history limit are not considered
+        assert!(tree_data
+            .get_inclusion_proof_any_root(
+                identities[2],
+                &[intermediate_root],
+                Some(0),
+                None
+            )
+            .unwrap()
+            .is_none());
+    }
+
     #[tokio::test]
     async fn test_get_inclusion_proof_for_intermediate_root() {
         let (mut tree_data, mut ref_tree, identities) =
@@ -305,6 +836,7 @@ mod tests {
         for (i, _identity) in identities.iter().enumerate().take(5) {
             let proof_from_world_tree = tree_data
                 .get_inclusion_proof(identities[i], Some(root))
+                .unwrap()
                 .unwrap();
 
             assert_eq!(ref_tree.proof(i), proof_from_world_tree.proof);
@@ -355,6 +887,7 @@ mod tests {
         for i in non_deleted_identity_idxs {
             let proof_from_world_tree = tree_data
                 .get_inclusion_proof(identities[i], Some(root))
+                .unwrap()
                 .unwrap();
 
             assert_eq!(ref_tree.proof(i), proof_from_world_tree.proof);
@@ -364,7 +897,491 @@ mod tests {
         for i in deleted_identity_idxs {
             let proof_from_world_tree =
                 tree_data.get_inclusion_proof(identities[*i], Some(root));
-            assert!(proof_from_world_tree.is_none());
+            assert!(proof_from_world_tree.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_inclusion_proof_history_limit() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        for (idx, identity) in identities.iter().enumerate().take(3) {
+            tree_data.insert_many_at(idx, &[*identity]);
+        }
+
+        // The root after the first insertion is two versions back in the tree history
+        let root = tree_data.tree_history[1].tree.root();
+
+        let proof = tree_data
+            .get_inclusion_proof_bounded(
+                identities[0],
+                Some(root),
+                Some(2),
+                None,
+            )
+            .unwrap();
+        assert!(proof.is_some());
+
+        let err = tree_data
+            .get_inclusion_proof_bounded(
+                identities[0],
+                Some(root),
+                Some(1),
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TreeError::HistoryLimitExceeded {
+                depth: 2,
+                max_depth: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn test_get_inclusion_proof_deadline() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+        tree_data.insert_many_at(0, &identities);
+
+        let err = tree_data
+            .get_inclusion_proof_bounded(
+                identities[1],
+                None,
+                None,
+                Some(Instant::now()),
+            )
+            .unwrap_err();
+        assert!(matches!(err, TreeError::ProofTimeout));
+
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let proof = tree_data
+            .get_inclusion_proof_bounded(
+                identities[1],
+                None,
+                None,
+                Some(deadline),
+            )
+            .unwrap();
+        assert!(proof.is_some());
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        tree_data.insert_many_at(0, &identities);
+
+        let mut local = identities.clone();
+        assert_eq!(
+            tree_data.first_divergence(&local, 0..NUM_IDENTITIES + 1),
+            None
+        );
+
+        local[4] = Hash::ZERO;
+        assert_eq!(
+            tree_data.first_divergence(&local, 0..NUM_IDENTITIES),
+            Some(LeafDivergence {
+                index: 4,
+                local: Hash::ZERO,
+                expected: identities[4],
+            })
+        );
+
+        // Missing local leaves are compared as empty leaves
+        assert_eq!(
+            tree_data
+                .first_divergence(&local[..2], 0..NUM_IDENTITIES)
+                .map(|divergence| divergence.index),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_get_inclusion_proof_for_evicted_root() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        let empty_root = tree_data.tree.root();
+
+        for (idx, identity) in identities.iter().enumerate() {
+            tree_data.insert_many_at(idx, &[*identity]);
+            tree_data.latest_root_block = idx as u64;
+        }
+
+        // The empty tree has been evicted from the history
+        let err = tree_data
+            .get_inclusion_proof(identities[0], Some(empty_root))
+            .unwrap_err();
+
+        let (oldest_root, oldest_block) = tree_data.oldest_root();
+        assert_eq!(
+            oldest_block,
+            (NUM_IDENTITIES - TREE_HISTORY_SIZE - 1) as u64
+        );
+        assert!(matches!(
+            err,
+            TreeError::RootEvicted { oldest_root: root, oldest_block: block }
+                if root == oldest_root && block == oldest_block
+        ));
+
+        // A root that never existed is not found rather than evicted
+        let proof = tree_data
+            .get_inclusion_proof(identities[0], Some(Hash::from(1)))
+            .unwrap();
+        assert!(proof.is_none());
+    }
+
+    #[test]
+    fn test_latest_only_history() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, 0, 4);
+
+        tree_data.insert_many_at(0, &identities[..2]);
+        let replaced_root = tree_data.tree.root();
+        tree_data.insert_many_at(2, &identities[2..]);
+
+        assert!(tree_data.tree_history.is_empty());
+
+        // Proofs are served against the latest root only
+        let proof = tree_data
+            .get_inclusion_proof(identities[3], Some(tree_data.tree.root()))
+            .unwrap();
+        assert!(proof.is_some());
+
+        assert!(matches!(
+            tree_data.get_inclusion_proof(identities[1], Some(replaced_root)),
+            Err(TreeError::RootEvicted { oldest_root, .. })
+                if oldest_root == tree_data.tree.root()
+        ));
+    }
+
+    #[test]
+    fn test_get_exclusion_proof() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, 4);
+
+        tree_data.insert_many_at(0, &identities);
+        tree_data.delete_many(&[1]);
+
+        let exclusion_proof =
+            tree_data.get_exclusion_proof(identities[1]).unwrap();
+        assert_eq!(exclusion_proof.leaf_index, 1);
+        assert_eq!(exclusion_proof.leaf, Hash::ZERO);
+        assert_eq!(exclusion_proof.root, tree_data.tree.root());
+        assert_eq!(
+            exclusion_proof.proof.root(exclusion_proof.leaf),
+            tree_data.tree.root()
+        );
+
+        assert!(matches!(
+            tree_data.get_exclusion_proof(identities[0]),
+            Err(TreeError::LeafPresent)
+        ));
+        assert!(matches!(
+            tree_data.get_exclusion_proof(Hash::from(12345)),
+            Err(TreeError::LeafIndexUnknown)
+        ));
+
+        // The deletion remains provable once the tree history no longer holds the identity
+        for idx in 4..4 + TREE_HISTORY_SIZE {
+            tree_data.insert_many_at(idx, &[Hash::from(idx as u64 + 100)]);
+        }
+        assert!(tree_data.tree_history.iter().all(|historical_tree| {
+            historical_tree.tree.get_leaf(1) == Hash::ZERO
+        }));
+        assert_eq!(
+            tree_data
+                .get_exclusion_proof(identities[1])
+                .unwrap()
+                .leaf_index,
+            1
+        );
+    }
+
+    #[test]
+    fn test_index_of() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, 4);
+
+        tree_data.insert_many_at(0, &identities);
+        tree_data.latest_root_block = 1;
+        assert_eq!(tree_data.index_of(identities[2]), Some(2));
+
+        tree_data.delete_many(&[2]);
+        tree_data.latest_root_block = 2;
+        assert_eq!(tree_data.index_of(identities[2]), None);
+        assert_eq!(tree_data.index_of(identities[3]), Some(3));
+
+        // Rolling back the deletion restores the index
+        assert!(tree_data.rollback_to(1));
+        assert_eq!(tree_data.index_of(identities[2]), Some(2));
+        assert_eq!(tree_data.index_of(Hash::from(12345)), None);
+    }
+
+    #[test]
+    fn test_leaves_page() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, 6);
+        // The first identity is zero, which is indistinguishable from an empty leaf
+        let identities = &identities[1..];
+
+        tree_data.insert_many_at(0, identities);
+        tree_data.delete_many(&[1]);
+
+        // Deleted leaves are skipped
+        let (leaves, next) = tree_data.leaves_page(0, 2);
+        assert_eq!(leaves, vec![(0, identities[0]), (2, identities[2])]);
+        assert_eq!(next, Some(3));
+
+        let (leaves, next) = tree_data.leaves_page(3, 2);
+        assert_eq!(leaves, vec![(3, identities[3]), (4, identities[4])]);
+        assert_eq!(next, None);
+
+        let (leaves, next) = tree_data.leaves_page(5, 2);
+        assert!(leaves.is_empty());
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn test_confirmed_root() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, 4);
+
+        for (idx, identity) in identities.iter().enumerate() {
+            tree_data.insert_many_at(idx, &[*identity]);
+            tree_data.latest_root_block = idx as u64;
+        }
+
+        // Without confirmations, the latest root is confirmed
+        assert_eq!(
+            tree_data.confirmed_root(3, 0),
+            Some((tree_data.tree.root(), 3))
+        );
+
+        // The history holds the roots committed at blocks 2, 1 and 0, most recent first
+        assert_eq!(
+            tree_data.confirmed_root(3, 2),
+            Some((tree_data.tree_history[1].tree.root(), 1))
+        );
+
+        assert_eq!(tree_data.confirmed_root(3, 4), None);
+    }
+
+    #[test]
+    fn test_rollback_to() {
+        let (mut tree_data, _, _) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, 0);
+        let identities: Vec<Hash> = (1..=4).map(Hash::from).collect();
+        tree_data.latest_root_block = 5;
+
+        for (idx, identity) in identities.iter().enumerate() {
+            tree_data.insert_many_at(idx, &[*identity]);
+            tree_data.latest_root_block = 10 * (idx as u64 + 1);
+        }
+
+        let root_at_20 = tree_data.tree_history[1].tree.root();
+
+        // Nothing to roll back past the latest root
+        assert!(tree_data.rollback_to(40));
+        assert_eq!(tree_data.latest_root_block, 40);
+
+        // Blocks 30 and 40 are reorged out
+        assert!(tree_data.rollback_to(25));
+        assert_eq!(tree_data.tree.root(), root_at_20);
+        assert_eq!(tree_data.latest_root_block, 20);
+        assert_eq!(tree_data.tree_history.len(), 2);
+        assert!(tree_data.leaves.contains_key(&identities[1]));
+        assert!(!tree_data.leaves.contains_key(&identities[2]));
+        assert!(!tree_data.leaves.contains_key(&identities[3]));
+
+        // The history does not reach back before block 5
+        assert!(!tree_data.rollback_to(0));
+        assert_eq!(tree_data.latest_root_block, 20);
+    }
+
+    #[test]
+    fn test_inclusion_proof_abi_encode() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        tree_data.insert_many_at(0, &identities);
+
+        let identity = identities[3];
+        let inclusion_proof = tree_data
+            .get_inclusion_proof(identity, None)
+            .unwrap()
+            .unwrap();
+
+        let tokens = abi::decode(
+            &[
+                abi::ParamType::Uint(256),
+                abi::ParamType::Uint(256),
+                abi::ParamType::Array(Box::new(abi::ParamType::Uint(256))),
+            ],
+            &inclusion_proof.abi_encode(),
+        )
+        .expect("Could not decode abi encoded proof");
+
+        let siblings: Vec<Hash> = tokens[2]
+            .clone()
+            .into_array()
+            .unwrap()
+            .into_iter()
+            .map(|token| Hash::from_limbs(token.into_uint().unwrap().0))
+            .collect();
+
+        assert_eq!(
+            tokens[0],
+            Token::Uint(U256(tree_data.tree.root().into_limbs()))
+        );
+        assert_eq!(tokens[1], Token::Uint(U256::from(3)));
+        assert_eq!(siblings.len(), TREE_DEPTH);
+
+        // Recompute the root from the decoded siblings and index
+        let root = siblings.iter().enumerate().fold(
+            identity,
+            |node, (level, sibling)| {
+                if (3 >> level) & 1 == 0 {
+                    PoseidonHash::hash_node(&node, sibling)
+                } else {
+                    PoseidonHash::hash_node(sibling, &node)
+                }
+            },
+        );
+
+        assert_eq!(root, tree_data.tree.root());
+    }
+
+    #[test]
+    fn test_restore_leaves() {
+        let (mut tree_data, mut ref_tree, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        // The leaf at index 2 was deleted
+        let mut leaves = identities.clone();
+        leaves[2] = Hash::ZERO;
+
+        tree_data.restore_leaves(&leaves);
+
+        for (idx, leaf) in leaves.iter().enumerate() {
+            ref_tree = ref_tree.update_with_mutation(idx, leaf);
+        }
+
+        assert_eq!(tree_data.tree.root(), ref_tree.root());
+        assert!(tree_data.tree_history.is_empty());
+        assert!(!tree_data.leaves.contains_key(&identities[2]));
+        assert!(tree_data.leaves.contains_key(&identities[3]));
+    }
+
+    #[test]
+    fn test_leaves_as_of() {
+        let (mut tree_data, _, _) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, 0);
+        let identities: Vec<Hash> = (1..=3).map(Hash::from).collect();
+        tree_data.latest_root_block = 5;
+
+        for (idx, identity) in identities.iter().enumerate() {
+            tree_data.insert_many_at(idx, &[*identity]);
+            tree_data.latest_root_block = 10 * (idx as u64 + 1);
+        }
+
+        assert_eq!(tree_data.leaves_as_of(35), Some((identities.clone(), 30)));
+        assert_eq!(
+            tree_data.leaves_as_of(29),
+            Some((identities[..2].to_vec(), 20))
+        );
+        assert_eq!(tree_data.leaves_as_of(5), Some((vec![], 5)));
+
+        // The history does not reach back before block 5
+        assert_eq!(tree_data.leaves_as_of(4), None);
+    }
+
+    #[test]
+    fn test_dense_leaves() {
+        let (mut tree_data, _, identities) =
+            initialize_tree_data(TREE_DEPTH, TREE_HISTORY_SIZE, NUM_IDENTITIES);
+
+        tree_data.insert_many_at(0, &identities);
+        tree_data.delete_many(&[1]);
+
+        let leaves = tree_data.dense_leaves();
+        assert_eq!(leaves.len(), NUM_IDENTITIES);
+        assert_eq!(leaves[0], identities[0]);
+        assert_eq!(leaves[1], Hash::ZERO);
+
+        let mut restored = TreeData::new(
+            PoseidonTree::<Canonical>::new_with_dense_prefix(
+                TREE_DEPTH,
+                TREE_DEPTH,
+                &Hash::ZERO,
+            ),
+            0,
+        );
+        restored.restore_leaves(&leaves);
+        assert_eq!(restored.tree.root(), tree_data.tree.root());
+        assert_eq!(restored.next_leaf_index, NUM_IDENTITIES);
+    }
+
+    #[test]
+    fn test_zero_hashes() {
+        let zero_hashes = zero_hashes(TREE_DEPTH);
+        let empty_tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            TREE_DEPTH,
+            TREE_DEPTH,
+            &Hash::ZERO,
+        );
+
+        assert_eq!(zero_hashes.len(), TREE_DEPTH + 1);
+        assert_eq!(zero_hashes[0], Hash::ZERO);
+        assert_eq!(zero_hashes[TREE_DEPTH], empty_tree.root());
+    }
+
+    #[tokio::test]
+    async fn test_reads_interleave_with_large_write() {
+        use std::sync::Arc;
+
+        use tokio::sync::{Barrier, RwLock};
+
+        const NUM_BATCHES: usize = 10;
+        const BATCH_SIZE: usize = 100;
+
+        let (tree_data, _, _) = initialize_tree_data(TREE_DEPTH, 0, 0);
+        let tree_data = Arc::new(RwLock::new(tree_data));
+        let barrier = Arc::new(Barrier::new(2));
+
+        // Applies a large write as one batch per write critical section, as the tree updater applies one transaction at a time. After each batch the writer waits for a read to complete before applying the next one
+        let writer = tokio::spawn({
+            let tree_data = tree_data.clone();
+            let barrier = barrier.clone();
+            async move {
+                for batch in 0..NUM_BATCHES {
+                    let identities: Vec<_> = (0..BATCH_SIZE)
+                        .map(|i| Hash::from(batch * BATCH_SIZE + i + 1))
+                        .collect();
+
+                    tree_data
+                        .write()
+                        .await
+                        .insert_many_at(batch * BATCH_SIZE, &identities);
+
+                    barrier.wait().await;
+                    barrier.wait().await;
+                }
+            }
+        });
+
+        // Reads are served between batches rather than waiting for the whole write, observing each batch as it is applied
+        for batch in 0..NUM_BATCHES {
+            barrier.wait().await;
+            let next_leaf_index = tree_data.read().await.next_leaf_index;
+            assert_eq!(next_leaf_index, (batch + 1) * BATCH_SIZE);
+            barrier.wait().await;
         }
+
+        writer.await.unwrap();
     }
 }
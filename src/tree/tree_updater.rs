@@ -1,33 +1,63 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::ops::DerefMut;
-use std::sync::atomic::AtomicU64;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use ethers::abi::{AbiDecode, AbiEncode};
-use ethers::contract::{EthCall, EthEvent};
+use ethers::contract::{parse_log, EthCall, EthEvent};
 use ethers::providers::{Middleware, StreamExt};
-use ethers::types::{Filter, Selector, Transaction, ValueOrArray, H160, U256, U64};
-use futures::stream::{FuturesUnordered, iter};
-use sea_orm::{DatabaseConnection, EntityTrait};
+use ethers::types::{
+    Filter, Log, Selector, Transaction, ValueOrArray, H160, H256, U256, U64,
+};
+use futures::stream::iter;
 use sea_orm::ActiveValue::Set;
 use sea_orm::prelude::DateTime;
-use tokio::sync::RwLock;
+use serde::Serialize;
+use tokio::sync::{broadcast, RwLock};
 use tracing::instrument;
 
-use super::block_scanner::BlockScanner;
-use super::error::TreeAvailabilityError;
+use super::batch_client::RpcBatchClient;
+use super::block_scanner::{BlockScanner, Reorg};
+use super::config::{
+    AnomalyAction, AppendOnly, ContractVersion, InsertionRateLimit,
+    LogConsistencyCheck, WorldTreeConfig,
+};
+use super::db_writer::{DbWrite, DbWriter};
+use super::error::{PreflightError, TreeAvailabilityError};
+use super::leaf_transform::{IdentityTransform, LeafTransform};
+use super::log_cache::LogCache;
+use super::metrics::{record_sync_progress, record_tree_updates};
+use super::onchain_root::OnchainRootCache;
+use super::preflight;
 use super::tree_data::TreeData;
 use crate::abi::{
     DeleteIdentitiesCall,
     DeleteIdentitiesWithDeletionProofAndBatchSizeAndPackedDeletionIndicesAndPreRootCall,
     RegisterIdentitiesCall, TreeChangedFilter,
 };
+use crate::audit::{AuditEvent, AuditLog, BatchKind};
 use crate::entities::batches;
 use crate::tree::Hash;
 
 use crate::entities::insertions::ActiveModel as InsertionActiveModel;
 use crate::entities::deletions::ActiveModel as DeletionActiveModel; 
-use crate::entities::prelude::{Batches, Deletions, Insertions};
+
+/// Number of root updates buffered for each subscriber. Subscribers lagging further behind miss the oldest updates.
+const ROOT_UPDATES_CAPACITY: usize = 16;
+/// Number of deletion batches buffered for each subscriber. Subscribers lagging further behind miss the oldest batches.
+const DELETION_UPDATES_CAPACITY: usize = 16;
+/// Maximum time to spend posting an alert to the anomaly webhook.
+const ALERT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Leaves zeroed by a deletion batch, broadcast once the batch is applied to the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletionUpdate {
+    /// Root of the tree after the deletion
+    pub root: Hash,
+    /// Indices of the deleted leaves, as unpacked from the calldata
+    pub deleted_indices: Vec<usize>,
+}
 
 /// Manages the synchronization of the World Tree with it's onchain representation.
 pub struct TreeUpdater<M: Middleware> {
@@ -35,6 +65,54 @@ pub struct TreeUpdater<M: Middleware> {
     pub address: H160,
     /// Latest block that has been synced.
     pub latest_synced_block: AtomicU64,
+    /// Unix timestamp in seconds of the latest successful sync.
+    pub latest_sync_timestamp: AtomicU64,
+    /// Total number of identities inserted into the tree while syncing.
+    pub identities_inserted: AtomicU64,
+    /// Total number of identities deleted from the tree while syncing.
+    pub identities_deleted: AtomicU64,
+    /// Indicates that the tree is being re-applied after a reorg and may not reflect the canonical chain yet.
+    pub reorg_in_progress: AtomicBool,
+    /// Chain tip at the moment the reorg was detected. Recovery completes once the tree is re-synced up to this block.
+    reorg_recovery_block: AtomicU64,
+    /// Limit on the number of identities inserted within a block window, along with the action to take when it is exceeded.
+    insertion_rate_limit: Option<InsertionRateLimit>,
+    /// Number of identities inserted per block within the insertion rate limit window.
+    recent_insertions: Mutex<VecDeque<(u64, usize)>>,
+    /// Client posting alerts to the anomaly webhook.
+    alert_client: reqwest::Client,
+    /// Audit log recording observed roots, applied batches and reorgs. Disabled if `None`.
+    pub audit_log: Option<AuditLog>,
+    /// Client used to fetch transactions through JSON-RPC batch requests. If `None`, transactions are fetched individually.
+    pub batch_client: Option<RpcBatchClient>,
+    /// Version of the `WorldIDIdentityManager`, selecting the calldata the tree is synced from.
+    contract_version: ContractVersion,
+    /// Set once the tree is synced to the chain head, switching transaction fetching from `backfill_concurrency` to `steadystate_concurrency`.
+    pub steady_state: AtomicBool,
+    /// Maximum number of concurrent transaction requests while catching up to the chain head.
+    backfill_concurrency: usize,
+    /// Maximum number of concurrent transaction requests once synced to the chain head.
+    steadystate_concurrency: usize,
+    /// Transform applied to decoded identity commitments before they are inserted as leaves.
+    pub leaf_transform: Arc<dyn LeafTransform>,
+    /// Refuses deletions when set, treating the tree as append-only.
+    append_only: Option<AppendOnly>,
+    /// Cross-check of the logs returned for each scanned window against the roots they commit to.
+    log_consistency_check: Option<LogConsistencyCheck>,
+    /// Provider used to re-fetch windows failing the log consistency check. If `None`, windows are re-fetched from `middleware`.
+    pub secondary_middleware: Option<Arc<M>>,
+    /// Cache of the onchain latest root, refreshed with the latest `TreeChanged` event observed while syncing to the chain head.
+    pub onchain_root: OnchainRootCache<M>,
+    /// Set when the root of the tree differs from the onchain latest root at the latest synced block, and cleared once a later check matches.
+    pub divergent: AtomicBool,
+    /// Interval between checks of the root of the tree against the onchain latest root. Disabled when zero.
+    root_check_interval: Duration,
+    /// Instant of the latest root check.
+    last_root_check: Mutex<Option<Instant>>,
+    /// Channel broadcasting the root of the tree after each applied batch that changed it.
+    root_updates: broadcast::Sender<Hash>,
+    /// Channel broadcasting the leaves zeroed by each applied deletion batch.
+    deletion_updates: broadcast::Sender<DeletionUpdate>,
     /// Scanner responsible for fetching logs and parsing calldata to decode tree updates.
     block_scanner: BlockScanner<Arc<M>>,
     /// Provider to interact with Ethereum.
@@ -42,90 +120,805 @@ pub struct TreeUpdater<M: Middleware> {
 }
 
 impl<M: Middleware> TreeUpdater<M> {
-    pub fn new(
-        address: H160,
-        creation_block: u64,
-        window_size: u64,
-        middleware: Arc<M>,
-    ) -> Self {
-        let filter = Filter::new()
-            .address(address)
-            .topic0(ValueOrArray::Value(TreeChangedFilter::signature()));
+    /// Initializes a new `TreeUpdater`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration of the World Tree, including the `WorldIDIdentityManager` address and creation block to sync from.
+    /// * `middleware` - Provider to interact with Ethereum.
+    pub fn new(config: &WorldTreeConfig, middleware: Arc<M>) -> Self {
+        let address = config.world_id_contract_address;
+        let creation_block = config.creation_block;
+
+        let mut block_scanner = BlockScanner::new(
+            middleware.clone(),
+            config.window_size,
+            creation_block,
+            tree_changed_filter(address),
+        )
+        .with_reorg_detection()
+        .with_window_concurrency(config.window_concurrency);
+        if let Some(window_tuning) = &config.window_tuning {
+            block_scanner =
+                block_scanner.with_window_tuning(window_tuning.clone());
+        }
+        if let Some(log_cache_dir) = &config.log_cache_dir {
+            block_scanner =
+                block_scanner.with_log_cache(LogCache::new(log_cache_dir));
+        }
 
         Self {
             address,
             latest_synced_block: AtomicU64::new(creation_block),
-            block_scanner: BlockScanner::new(
+            latest_sync_timestamp: AtomicU64::new(0),
+            identities_inserted: AtomicU64::new(0),
+            identities_deleted: AtomicU64::new(0),
+            reorg_in_progress: AtomicBool::new(false),
+            reorg_recovery_block: AtomicU64::new(0),
+            insertion_rate_limit: config.insertion_rate_limit.clone(),
+            recent_insertions: Mutex::new(VecDeque::new()),
+            alert_client: reqwest::Client::builder()
+                .timeout(ALERT_TIMEOUT)
+                .build()
+                .expect("Failed to build the alert client"),
+            audit_log: None,
+            batch_client: None,
+            contract_version: config.contract_version,
+            steady_state: AtomicBool::new(false),
+            backfill_concurrency: config.backfill_concurrency,
+            steadystate_concurrency: config.steadystate_concurrency,
+            leaf_transform: Arc::new(IdentityTransform),
+            append_only: config.append_only.clone(),
+            log_consistency_check: config.log_consistency_check.clone(),
+            secondary_middleware: None,
+            onchain_root: OnchainRootCache::new(
+                address,
+                Duration::from_millis(config.onchain_root_cache_ttl),
                 middleware.clone(),
-                window_size,
-                creation_block,
-                filter,
             ),
+            divergent: AtomicBool::new(false),
+            root_check_interval: Duration::from_secs(
+                config.root_check_interval_secs,
+            ),
+            last_root_check: Mutex::new(None),
+            root_updates: broadcast::channel(ROOT_UPDATES_CAPACITY).0,
+            deletion_updates: broadcast::channel(DELETION_UPDATES_CAPACITY).0,
+            block_scanner,
             middleware,
         }
     }
 
+    /// Subscribes to the root of the tree, broadcast after each applied batch that changed it.
+    pub fn subscribe_roots(&self) -> broadcast::Receiver<Hash> {
+        self.root_updates.subscribe()
+    }
+
+    /// Subscribes to the leaves zeroed by each deletion batch, broadcast as soon as the batch is applied. Insertions are not broadcast on this channel.
+    pub fn subscribe_deletions(&self) -> broadcast::Receiver<DeletionUpdate> {
+        self.deletion_updates.subscribe()
+    }
+
+    /// Resumes syncing after the given block, skipping the blocks whose changes were restored from the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - Last block whose changes are already applied to the tree.
+    pub fn resume_from(&self, block: u64) {
+        self.latest_synced_block.store(block, Ordering::SeqCst);
+        self.block_scanner.rewind_to(block);
+
+        tracing::info!(?block, "Resuming sync");
+    }
+
+    /// Marks the tree as recovering from a reorg. Until the tree has been re-applied up to `previous_tip`, `reorg_in_progress` stays set and proofs should not be served.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_tip` - The latest synced block at the time the reorg was detected.
+    pub fn start_reorg_recovery(&self, previous_tip: u64) {
+        tracing::warn!(?previous_tip, "Reorg recovery started");
+
+        self.reorg_recovery_block
+            .store(previous_tip, Ordering::SeqCst);
+        self.reorg_in_progress.store(true, Ordering::SeqCst);
+    }
+
+    /// Rolls the tree back to the fork point of a reorg detected by the block scanner, so that the changes following it are re-applied from the canonical chain by the next sync.
+    ///
+    /// If the fork point is unknown or the tree history does not reach back to it, the tree is marked as unservable and `TreeAvailabilityError::ReorgDetected` is returned, for the tree to be rebuilt by a resync.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree_data` - Instance of `TreeData` maintaining the current state of the tree and tree history.
+    /// * `db` - Writer persisting the applied batches to the database. The records indexed after the fork point are removed from it.
+    /// * `reorg` - The reorg detected by the block scanner.
+    async fn handle_reorg(
+        &self,
+        tree_data: &RwLock<TreeData>,
+        db: Option<&DbWriter>,
+        reorg: Reorg,
+    ) -> Result<(), TreeAvailabilityError<M>> {
+        tracing::warn!(?reorg, "Reorg detected");
+        metrics::increment_counter!("tree_availability.tree_updater.reorg");
+
+        self.audit(AuditEvent::ReorgDetected {
+            previous_tip: reorg.previous_tip,
+            fork_block: reorg.fork_block,
+            depth: reorg.depth,
+        });
+
+        if let Some(fork_block) = reorg.fork_block {
+            if tree_data.write().await.rollback_to(fork_block) {
+                if let Some(db) = db {
+                    db.write(DbWrite::Rollback {
+                        after_block: fork_block,
+                    });
+                }
+
+                self.resume_from(fork_block);
+                self.start_reorg_recovery(reorg.previous_tip);
+
+                return Ok(());
+            }
+        }
+
+        // The tree cannot be rolled back to the fork point, so it stays unservable until it is rebuilt
+        tracing::error!(?reorg, "Reorg reaches past the tree history");
+        self.block_scanner.forget_block_hashes();
+        self.reorg_recovery_block.store(u64::MAX, Ordering::SeqCst);
+        self.reorg_in_progress.store(true, Ordering::SeqCst);
+        self.divergent.store(true, Ordering::SeqCst);
+
+        Err(TreeAvailabilityError::ReorgDetected { depth: reorg.depth })
+    }
+
+    /// Marks the tree as servable again once it has been rebuilt and swapped in, clearing the reorg recovery and divergence left by a reorg reaching past the tree history.
+    pub fn finish_rebuild(&self) {
+        self.reorg_in_progress.store(false, Ordering::SeqCst);
+        self.divergent.store(false, Ordering::SeqCst);
+    }
+
+    /// Clears `reorg_in_progress` once the tree has been re-synced up to the tip observed when the reorg was detected.
+    fn check_reorg_recovery(&self) {
+        if !self.reorg_in_progress.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let latest_synced_block =
+            self.latest_synced_block.load(Ordering::SeqCst);
+        let recovery_block = self.reorg_recovery_block.load(Ordering::SeqCst);
+
+        if latest_synced_block >= recovery_block {
+            tracing::info!(
+                ?latest_synced_block,
+                ?recovery_block,
+                "Reorg recovery complete"
+            );
+            self.reorg_in_progress.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Records the number of identities inserted at a given block and checks the total inserted within the configured block window against the insertion rate limit, taking the configured action when it is exceeded.
+    ///
+    /// Called while the tree is locked, so webhook alerts are posted by a separate task rather than awaited.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_number` - Block at which the identities were inserted.
+    /// * `inserted` - Number of identities inserted.
+    fn check_insertion_rate(
+        &self,
+        block_number: u64,
+        inserted: usize,
+    ) -> Result<(), TreeAvailabilityError<M>> {
+        let Some(limit) = &self.insertion_rate_limit else {
+            return Ok(());
+        };
+
+        let insertions = {
+            let mut recent_insertions = self
+                .recent_insertions
+                .lock()
+                .expect("Recent insertions lock poisoned");
+
+            recent_insertions.push_back((block_number, inserted));
+
+            while let Some((block, _)) = recent_insertions.front() {
+                if block + limit.window_blocks > block_number {
+                    break;
+                }
+                recent_insertions.pop_front();
+            }
+
+            recent_insertions.iter().map(|(_, count)| count).sum::<usize>()
+        };
+
+        if insertions <= limit.max_insertions {
+            return Ok(());
+        }
+
+        metrics::increment_counter!(
+            "tree_availability.tree_updater.insertion_rate_exceeded"
+        );
+        tracing::error!(
+            ?block_number,
+            ?insertions,
+            max_insertions = limit.max_insertions,
+            window_blocks = limit.window_blocks,
+            "Insertion rate limit exceeded"
+        );
+
+        match &limit.action {
+            AnomalyAction::Log => Ok(()),
+            AnomalyAction::Webhook { url } => {
+                let alert = InsertionRateAlert {
+                    block_number,
+                    insertions,
+                    max_insertions: limit.max_insertions,
+                    window_blocks: limit.window_blocks,
+                };
+
+                let request = self.alert_client.post(url.clone()).json(&alert);
+                tokio::spawn(async move {
+                    let response = request
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status());
+
+                    if let Err(err) = response {
+                        tracing::error!(
+                            ?err,
+                            "Failed to post insertion rate alert"
+                        );
+                    }
+                });
+
+                Ok(())
+            }
+            AnomalyAction::Halt => {
+                Err(TreeAvailabilityError::InsertionRateExceeded {
+                    block_number,
+                    insertions,
+                })
+            }
+        }
+    }
+
     /// Updates the in-memory tree to reflect the latest state of the onchain tree.
     ///
     /// # Arguments
     ///
     /// * `tree_data` - Instance of `TreeData` maintaining the current state of the tree and tree history.
-    #[instrument(skip(self, tree_data))]
+    /// * `db` - Writer persisting the applied batches to the database. If `None`, the tree is updated without persisting anything.
+    #[instrument(skip(self, tree_data, db))]
     pub async fn sync_to_head(
         &self,
         tree_data: &RwLock<TreeData>,
-        db: &DatabaseConnection,
+        db: Option<&DbWriter>,
     ) -> Result<(), TreeAvailabilityError<M>> {
         tracing::info!("Syncing tree to chain head");
 
+        let latest_block = self
+            .middleware
+            .get_block_number()
+            .await
+            .map_err(TreeAvailabilityError::MiddlewareError)?
+            .as_u64();
+
+        // The synced range ends at the chain head, so its latest root is the current onchain root
+        if let Some(post_root) =
+            self.sync_window(tree_data, db, latest_block).await?
+        {
+            self.onchain_root.observe(post_root).await;
+        }
+
+        record_sync_progress(
+            self.latest_synced_block.load(Ordering::SeqCst),
+            latest_block,
+        );
+
+        self.check_root(tree_data).await;
+
+        Ok(())
+    }
+
+    /// Compares the root of the tree against the onchain latest root at the latest synced block, at most once per `root_check_interval`. A mismatch, such as one caused by wrongly decoded calldata, marks the tree as divergent until a later check matches.
+    async fn check_root(&self, tree_data: &RwLock<TreeData>) {
+        if self.root_check_interval.is_zero() {
+            return;
+        }
+
+        {
+            let mut last_root_check = self
+                .last_root_check
+                .lock()
+                .expect("Root check lock poisoned");
+            if last_root_check.is_some_and(|last_root_check| {
+                last_root_check.elapsed() < self.root_check_interval
+            }) {
+                return;
+            }
+            *last_root_check = Some(Instant::now());
+        }
+
+        let block = self.latest_synced_block.load(Ordering::SeqCst);
+        let root = tree_data.read().await.tree.root();
+
+        match preflight::verify_root(
+            self.middleware.clone(),
+            self.address,
+            root,
+            block,
+        )
+        .await
+        {
+            Ok(()) => {
+                if self.divergent.swap(false, Ordering::SeqCst) {
+                    tracing::info!(
+                        ?block,
+                        ?root,
+                        "Tree root matches the onchain root again"
+                    );
+                }
+            }
+            Err(PreflightError::RootMismatch { onchain, .. }) => {
+                tracing::error!(
+                    ?block,
+                    ?root,
+                    ?onchain,
+                    "Tree root diverged from the onchain root"
+                );
+                metrics::increment_counter!(
+                    "tree_availability.tree_updater.root_divergence"
+                );
+                self.divergent.store(true, Ordering::SeqCst);
+            }
+            Err(err) => {
+                tracing::warn!(?err, "Failed to check the tree root");
+            }
+        }
+    }
+
+    /// Updates the in-memory tree to reflect the state of the onchain tree at the given block.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree_data` - Instance of `TreeData` maintaining the current state of the tree and tree history.
+    /// * `db` - Writer persisting the applied batches to the database. If `None`, the tree is updated without persisting anything.
+    /// * `to_block` - Last block to sync (inclusive).
+    #[instrument(skip(self, tree_data, db))]
+    pub async fn sync_to_block(
+        &self,
+        tree_data: &RwLock<TreeData>,
+        db: Option<&DbWriter>,
+        to_block: u64,
+    ) -> Result<(), TreeAvailabilityError<M>> {
+        self.sync_window(tree_data, db, to_block).await?;
+
+        Ok(())
+    }
+
+    /// Syncs the tree up to `to_block` like `sync_to_block`, returning the `post_root` of the latest `TreeChanged` event applied, if any.
+    async fn sync_window(
+        &self,
+        tree_data: &RwLock<TreeData>,
+        db: Option<&DbWriter>,
+        to_block: u64,
+    ) -> Result<Option<U256>, TreeAvailabilityError<M>> {
+        if let Some(reorg) = self
+            .block_scanner
+            .detect_reorg()
+            .await
+            .map_err(TreeAvailabilityError::MiddlewareError)?
+        {
+            self.handle_reorg(tree_data, db, reorg).await?;
+        }
+
+        let from_block =
+            self.block_scanner.last_synced_block.load(Ordering::SeqCst) + 1;
+
         let logs = self
             .block_scanner
-            .next()
+            .next_until(to_block)
             .await
             .map_err(TreeAvailabilityError::MiddlewareError)?;
 
+        let last_synced_block =
+            self.block_scanner.last_synced_block.load(Ordering::SeqCst);
+
+        let latest_post_root = match self
+            .apply_window(tree_data, db, logs, from_block, last_synced_block)
+            .await
+        {
+            Ok(latest_post_root) => latest_post_root,
+            Err(err) => {
+                // The scanner has moved past the window, which must be scanned again by the next sync so that its batches are not skipped. Batches applied before the failure are skipped by `check_pre_root`
+                self.block_scanner.rewind_to(from_block - 1);
+                return Err(err);
+            }
+        };
+
+        self.latest_synced_block
+            .store(last_synced_block, Ordering::SeqCst);
+        self.record_sync();
+        self.check_reorg_recovery();
+
+        Ok(latest_post_root)
+    }
+
+    /// Applies the batches of the `TreeChanged` logs of a scanned window to the tree, returning the `post_root` of the latest `TreeChanged` event of the window, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree_data` - Instance of `TreeData` maintaining the current state of the tree and tree history.
+    /// * `db` - Writer persisting the applied batches to the database. If `None`, the tree is updated without persisting anything.
+    /// * `logs` - Logs returned for the window.
+    /// * `from_block` - First block of the window.
+    /// * `to_block` - Last block of the window (inclusive).
+    async fn apply_window(
+        &self,
+        tree_data: &RwLock<TreeData>,
+        db: Option<&DbWriter>,
+        mut logs: Vec<Log>,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Option<U256>, TreeAvailabilityError<M>> {
+        if let Some(check) = &self.log_consistency_check {
+            let local_root = tree_data.read().await.tree.root();
+
+            logs = self
+                .ensure_log_consistency(
+                    check, local_root, logs, from_block, to_block,
+                )
+                .await?;
+        }
+
         if logs.is_empty() {
             tracing::info!("No `TreeChanged` events found within block range");
-            return Ok(());
+            return Ok(None);
         }
 
-        let mut futures = FuturesUnordered::new();
+        let mut tx_hashes = Vec::with_capacity(logs.len());
+        let mut latest_post_root = None;
 
         for log in logs {
+            if let Ok(event) = parse_log::<TreeChangedFilter>(log.clone()) {
+                self.audit(AuditEvent::RootObserved {
+                    pre_root: event.pre_root,
+                    post_root: event.post_root,
+                    block_number: log.block_number.map(|block| block.as_u64()),
+                    tx_hash: log.transaction_hash,
+                });
+
+                latest_post_root = Some(event.post_root);
+            }
+
             let tx_hash = log
                 .transaction_hash
                 .ok_or(TreeAvailabilityError::TransactionHashNotFound)?;
 
-            tracing::info!(?tx_hash, "Getting transaction");
-
-            futures.push(self.middleware.get_transaction(tx_hash));
+            tx_hashes.push(tx_hash);
         }
 
+        // Transactions are ordered by their position in the chain, as several batches may land in the same block
         let mut sorted_transactions = BTreeMap::new();
 
-        let mut tree_data = tree_data.write().await;
+        for transaction in self.fetch_transactions(&tx_hashes).await? {
+            let tx_hash = transaction.hash;
+            tracing::info!(?tx_hash, "Transaction received");
+
+            let block_number = transaction
+                .block_number
+                .ok_or(TreeAvailabilityError::BlockNumberNotFound)?;
+            let transaction_index = transaction
+                .transaction_index
+                .ok_or(TreeAvailabilityError::TransactionIndexNotFound)?;
+
+            sorted_transactions
+                .insert((block_number, transaction_index), transaction);
+        }
+
+        // Blocks are fetched before locking the tree, so that no network round trip happens while readers are blocked
+        let block_numbers = sorted_transactions
+            .keys()
+            .map(|(block_number, _)| *block_number)
+            .collect::<BTreeSet<_>>();
+        let block_timestamps = self
+            .fetch_block_timestamps(block_numbers.into_iter())
+            .await?;
+
+        // The write lock is held for a single transaction at a time, letting proof requests interleave with the application of a large window
+        for ((block_number, _), tx) in &sorted_transactions {
+            let mut tree_data = tree_data.write().await;
+            let pre_root = tree_data.tree.root();
+            self.sync_from_transaction(
+                tree_data.deref_mut(),
+                tx,
+                block_timestamps[block_number],
+                db,
+            )
+            .await?;
+
+            let root = tree_data.tree.root();
+            if root != pre_root {
+                // Sending only fails when there are no subscribers
+                let _ = self.root_updates.send(root);
+            }
+        }
+
+        record_tree_updates(sorted_transactions.len() as u64);
+
+        Ok(latest_post_root)
+    }
+
+    /// Records the time of a successful sync, used to report the staleness of the tree.
+    fn record_sync(&self) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+
+        self.latest_sync_timestamp.store(timestamp, Ordering::SeqCst);
+    }
+
+    /// Records an event in the audit log, if enabled.
+    fn audit(&self, event: AuditEvent) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(event);
+        }
+    }
+
+    /// Seconds elapsed since the latest successful sync.
+    pub fn staleness(&self) -> u64 {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+
+        timestamp
+            .saturating_sub(self.latest_sync_timestamp.load(Ordering::SeqCst))
+    }
+
+    /// Verifies that the logs of a scanned window chain from the local root to the `post_root` of the last `TreeChanged` event, re-fetching the window when they don't. A provider returning an incomplete log set breaks the chain, which would otherwise silently build a wrong tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Number of times to re-fetch the window before failing.
+    /// * `local_root` - Root of the local tree before applying the window.
+    /// * `logs` - Logs returned for the window.
+    /// * `from_block` - First block of the window.
+    /// * `to_block` - Last block of the window (inclusive).
+    async fn ensure_log_consistency(
+        &self,
+        check: &LogConsistencyCheck,
+        local_root: Hash,
+        mut logs: Vec<Log>,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<Log>, TreeAvailabilityError<M>> {
+        let mut refetches = 0;
+
+        while let Some(index) = first_broken_link(local_root, &logs) {
+            metrics::increment_counter!(
+                "tree_availability.tree_updater.log_gap"
+            );
+            tracing::warn!(
+                ?from_block,
+                ?to_block,
+                ?index,
+                ?refetches,
+                "Logs do not chain from the local root, re-fetching window"
+            );
+
+            // The logs may have been read from the log cache, which must not serve them again
+            self.block_scanner.evict_cached_logs(from_block, to_block);
+
+            if refetches == check.max_refetches {
+                return Err(TreeAvailabilityError::LogGap {
+                    from_block,
+                    to_block,
+                });
+            }
+
+            let middleware =
+                self.secondary_middleware.as_ref().unwrap_or(&self.middleware);
+
+            logs = self
+                .block_scanner
+                .logs_in_range(middleware, from_block, to_block)
+                .await
+                .map_err(TreeAvailabilityError::MiddlewareError)?;
+
+            refetches += 1;
+        }
+
+        Ok(logs)
+    }
+
+    /// Maximum number of concurrent transaction requests, depending on whether the tree has caught up to the chain head.
+    fn concurrency(&self) -> usize {
+        let concurrency = if self.steady_state.load(Ordering::Relaxed) {
+            self.steadystate_concurrency
+        } else {
+            self.backfill_concurrency
+        };
+
+        concurrency.max(1)
+    }
+
+    /// Checks a deletion against the append-only invariant, returning whether the deletion should be applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_hash` - Hash of the transaction deleting identities.
+    fn check_append_only(
+        &self,
+        tx_hash: H256,
+    ) -> Result<bool, TreeAvailabilityError<M>> {
+        let Some(append_only) = &self.append_only else {
+            return Ok(true);
+        };
+
+        metrics::increment_counter!(
+            "tree_availability.tree_updater.unexpected_deletion"
+        );
+
+        if append_only.strict {
+            tracing::error!(?tx_hash, "Deletion encountered in append-only tree");
+            return Err(TreeAvailabilityError::UnexpectedDeletion { tx_hash });
+        }
+
+        tracing::warn!(
+            ?tx_hash,
+            "Deletion encountered in append-only tree, skipping"
+        );
+
+        Ok(false)
+    }
+
+    /// Checks that a batch applies on top of the local tree before it is applied. Returns `false` if the local root already is the `post_root` of the batch, meaning the batch was applied by an earlier sync, e.g. one that was interrupted mid-window, so that it is skipped instead of being applied twice.
+    ///
+    /// Returns `TreeAvailabilityError::RootMismatch` if the local root is neither the `pre_root` nor the `post_root` of the batch. The check is skipped for non-strict append-only trees, whose root diverges from the onchain root once a deletion is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree_data` - The tree the batch is about to be applied to.
+    /// * `tx_hash` - Hash of the transaction holding the batch.
+    /// * `pre_root` - Root the batch applies on top of.
+    /// * `post_root` - Root after the batch is applied.
+    fn check_pre_root(
+        &self,
+        tree_data: &TreeData,
+        tx_hash: H256,
+        pre_root: U256,
+        post_root: U256,
+    ) -> Result<bool, TreeAvailabilityError<M>> {
+        if self
+            .append_only
+            .as_ref()
+            .is_some_and(|append_only| !append_only.strict)
+        {
+            return Ok(true);
+        }
+
+        let local_root = tree_data.tree.root();
+        match batch_status(local_root, pre_root, post_root) {
+            BatchStatus::Pending => Ok(true),
+            BatchStatus::Applied => {
+                metrics::increment_counter!(
+                    "tree_availability.tree_updater.duplicate_batch"
+                );
+                tracing::warn!(
+                    ?tx_hash,
+                    ?local_root,
+                    "Batch already applied, skipping"
+                );
+
+                Ok(false)
+            }
+            BatchStatus::Mismatch => {
+                let pre_root = Hash::from_limbs(pre_root.0);
+                tracing::error!(
+                    ?tx_hash,
+                    ?pre_root,
+                    ?local_root,
+                    "Batch does not apply on top of the local root"
+                );
+
+                Err(TreeAvailabilityError::RootMismatch {
+                    tx_hash,
+                    expected: pre_root,
+                    actual: local_root,
+                })
+            }
+        }
+    }
+
+    /// Fetches the transactions for the given hashes. When an `RpcBatchClient` is configured, the transactions are fetched through JSON-RPC batch requests, falling back to individual requests if batching fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_hashes` - Hashes of the transactions to fetch.
+    async fn fetch_transactions(
+        &self,
+        tx_hashes: &[H256],
+    ) -> Result<Vec<Transaction>, TreeAvailabilityError<M>> {
+        if let Some(batch_client) = &self.batch_client {
+            match batch_client.get_transactions(tx_hashes).await {
+                Ok(transactions) => {
+                    return transactions
+                        .into_iter()
+                        .map(|transaction| {
+                            transaction
+                                .ok_or(TreeAvailabilityError::TransactionNotFound)
+                        })
+                        .collect();
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        ?err,
+                        "Batch request failed, falling back to individual requests"
+                    );
+                }
+            }
+        }
+
+        let mut futures = iter(tx_hashes)
+            .map(|tx_hash| {
+                tracing::info!(?tx_hash, "Getting transaction");
+
+                self.middleware.get_transaction(*tx_hash)
+            })
+            .buffer_unordered(self.concurrency());
+
+        let mut transactions = Vec::with_capacity(tx_hashes.len());
+
         while let Some(transaction) = futures.next().await {
             let transaction = transaction
                 .map_err(TreeAvailabilityError::MiddlewareError)?
                 .ok_or(TreeAvailabilityError::TransactionNotFound)?;
 
-            let tx_hash = transaction.hash;
-            tracing::info!(?tx_hash, "Transaction received");
-
-            sorted_transactions.insert(
-                transaction
-                    .block_number
-                    .ok_or(TreeAvailabilityError::BlockNumberNotFound)?,
-                transaction,
-            );
+            transactions.push(transaction);
         }
 
-        for tx in sorted_transactions.values() {
-            self.sync_from_transaction(tree_data.deref_mut(), tx, &db)
-                .await?;
+        Ok(transactions)
+    }
+
+    /// Fetches the timestamps of the given blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_numbers` - Numbers of the blocks to fetch.
+    async fn fetch_block_timestamps(
+        &self,
+        block_numbers: impl Iterator<Item = U64>,
+    ) -> Result<HashMap<U64, U256>, TreeAvailabilityError<M>> {
+        let mut futures = iter(block_numbers)
+            .map(|block_number| async move {
+                let block = self
+                    .middleware
+                    .get_block(block_number)
+                    .await
+                    .map_err(TreeAvailabilityError::MiddlewareError)?
+                    .ok_or(TreeAvailabilityError::BlockNotFound(
+                        block_number.as_u64(),
+                    ))?;
+
+                Ok::<_, TreeAvailabilityError<M>>((
+                    block_number,
+                    block.timestamp,
+                ))
+            })
+            .buffer_unordered(self.concurrency());
+
+        let mut block_timestamps = HashMap::new();
+
+        while let Some(result) = futures.next().await {
+            let (block_number, timestamp) = result?;
+            block_timestamps.insert(block_number, timestamp);
         }
 
-        Ok(())
+        Ok(block_timestamps)
     }
 
     /// Updates the in-memory tree based transaction calldata.
@@ -134,12 +927,15 @@ impl<M: Middleware> TreeUpdater<M> {
     ///
     /// * `tree_data` - Instance of `TreeData` maintaining the current state of the tree and tree history.
     /// * `transaction` - Transaction containing the calldata necessary to update the local tree.
-    #[instrument(skip(self, tree_data, transaction))]
+    /// * `block_timestamp` - Timestamp of the block containing the transaction.
+    /// * `db` - Writer persisting the applied batch to the database. If `None`, the tree is updated without persisting anything.
+    #[instrument(skip(self, tree_data, transaction, db))]
     pub async fn sync_from_transaction(
         &self,
         tree_data: &mut TreeData,
         transaction: &Transaction,
-        db: &DatabaseConnection,
+        block_timestamp: U256,
+        db: Option<&DbWriter>,
     ) -> Result<(), TreeAvailabilityError<M>> {
         let tx_hash = transaction.hash;
         tracing::info!(?tx_hash, "Syncing from transaction");
@@ -149,11 +945,21 @@ impl<M: Middleware> TreeUpdater<M> {
         let function_selector = Selector::try_from(&calldata[0..4])
             .expect("Transaction data does not contain a function selector");
 
-        let block = self.middleware
-            .get_block(transaction.block_number.expect("Transaction must belong to a block"))
-            .await
-            .expect("Failed to get block")
-            .unwrap();
+        if !self.contract_version.supports(function_selector) {
+            tracing::error!(
+                ?tx_hash,
+                ?function_selector,
+                contract_version = ?self.contract_version,
+                "Function selector not supported by the configured contract version"
+            );
+            return Err(TreeAvailabilityError::UnrecognizedFunctionSelector);
+        }
+
+        if function_selector != RegisterIdentitiesCall::selector()
+            && !self.check_append_only(tx_hash)?
+        {
+            return Ok(());
+        }
 
         if function_selector == RegisterIdentitiesCall::selector() {
             tracing::info!("Decoding registerIdentities calldata");
@@ -161,6 +967,15 @@ impl<M: Middleware> TreeUpdater<M> {
             let register_identities_call =
                 RegisterIdentitiesCall::decode(calldata.as_ref())?;
 
+            if !self.check_pre_root(
+                tree_data,
+                tx_hash,
+                register_identities_call.pre_root,
+                register_identities_call.post_root,
+            )? {
+                return Ok(());
+            }
+
             let start_index = register_identities_call.start_index;
             let batch_size = register_identities_call.identity_commitments.len();
             let identities = register_identities_call.identity_commitments;
@@ -168,8 +983,14 @@ impl<M: Middleware> TreeUpdater<M> {
             let identities: Vec<Hash> = identities
                 .into_iter().take_while(|x| *x != U256::zero())
                 .map(|u256: U256| Hash::from_limbs(u256.0))
+                .map(|commitment| self.leaf_transform.transform(commitment))
                 .collect();
 
+            self.check_insertion_rate(
+                transaction.block_number.unwrap_or_default().as_u64(),
+                identities.len(),
+            )?;
+
             metrics::increment_counter!(
                 "tree_availability.tree_updater.insertion"
             );
@@ -179,11 +1000,13 @@ impl<M: Middleware> TreeUpdater<M> {
                     pubkey: Set(id.to_string()),
                     inserted_in_block: Set(transaction.block_number.unwrap().as_u64() as i64),
                     inserted_in_tx: Set(transaction.hash.encode_hex()),
-                    created_at: Set(DateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
+                    created_at: Set(DateTime::from_timestamp_opt(block_timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
                     ..Default::default()
                 }
             }).collect();
-            Insertions::insert_many(entities).exec(db).await.expect("Failed to insert identities into db");
+            if let Some(db) = db {
+                db.write(DbWrite::Insertions(entities));
+            }
 
             let batch_entity = batches::ActiveModel {
                 tx: Set(transaction.hash.encode_hex()),
@@ -194,19 +1017,43 @@ impl<M: Middleware> TreeUpdater<M> {
                 proof: Set(register_identities_call.insertion_proof.encode_hex().into()),
                 preroot: Set(register_identities_call.pre_root.encode_hex()),
                 postroot: Set(register_identities_call.post_root.encode_hex()),
-                created_at: Set(DateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
+                created_at: Set(DateTime::from_timestamp_opt(block_timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
                 ..Default::default()
             };
-            Batches::insert(batch_entity).exec(db).await.expect("Failed to insert batch into db");
+            if let Some(db) = db {
+                db.write(DbWrite::Batch(batch_entity));
+            }
 
             tree_data
                 .insert_many_at(start_index as usize, &identities);
+            self.identities_inserted
+                .fetch_add(identities.len() as u64, Ordering::SeqCst);
+
+            self.audit(AuditEvent::BatchApplied {
+                kind: BatchKind::Insertion,
+                pre_root: register_identities_call.pre_root,
+                post_root: register_identities_call.post_root,
+                batch_size: identities.len(),
+                block_number: transaction
+                    .block_number
+                    .map(|block| block.as_u64()),
+                tx_hash: transaction.hash,
+            });
         } else if function_selector == DeleteIdentitiesCall::selector() {
             tracing::info!("Decoding deleteIdentities calldata");
 
             let delete_identities_call =
                 DeleteIdentitiesCall::decode(calldata.as_ref())?;
 
+            if !self.check_pre_root(
+                tree_data,
+                tx_hash,
+                delete_identities_call.pre_root,
+                delete_identities_call.post_root,
+            )? {
+                return Ok(());
+            }
+
             let indices = unpack_indices(
                 delete_identities_call.packed_deletion_indices.as_ref(),
             );
@@ -224,11 +1071,13 @@ impl<M: Middleware> TreeUpdater<M> {
                     pubkey: Set(id.to_string()),
                     deleted_at_block: Set(transaction.block_number.unwrap().as_u64() as i64),
                     deleted_in_tx: Set(transaction.hash.encode_hex()),
-                    created_at: Set(DateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
+                    created_at: Set(DateTime::from_timestamp_opt(block_timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
                     ..Default::default()
                 }
             }).collect();
-            Deletions::insert_many(entities).exec(db).await.expect("Failed to insert identities into db");
+            if let Some(db) = db {
+                db.write(DbWrite::Deletions(entities));
+            }
 
             let batch_entity = batches::ActiveModel {
                 tx: Set(transaction.hash.encode_hex()),
@@ -239,18 +1088,48 @@ impl<M: Middleware> TreeUpdater<M> {
                 proof: Set(delete_identities_call.deletion_proof.encode_hex().into()),
                 preroot: Set(delete_identities_call.pre_root.encode_hex()),
                 postroot: Set(delete_identities_call.post_root.encode_hex()),
-                created_at: Set(DateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
+                created_at: Set(DateTime::from_timestamp_opt(block_timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
                 ..Default::default()
             };
-            Batches::insert(batch_entity).exec(db).await.expect("Failed to insert batch into db");
+            if let Some(db) = db {
+                db.write(DbWrite::Batch(batch_entity));
+            }
             
             tree_data.delete_many(&indices);
+            self.identities_deleted
+                .fetch_add(indices.len() as u64, Ordering::SeqCst);
+
+            self.audit(AuditEvent::BatchApplied {
+                kind: BatchKind::Deletion,
+                pre_root: delete_identities_call.pre_root,
+                post_root: delete_identities_call.post_root,
+                batch_size: indices.len(),
+                block_number: transaction
+                    .block_number
+                    .map(|block| block.as_u64()),
+                tx_hash: transaction.hash,
+            });
+
+            // Sending only fails when there are no subscribers
+            let _ = self.deletion_updates.send(DeletionUpdate {
+                root: tree_data.tree.root(),
+                deleted_indices: indices,
+            });
         } else if function_selector == DeleteIdentitiesWithDeletionProofAndBatchSizeAndPackedDeletionIndicesAndPreRootCall::selector() {
             tracing::info!("Decoding deleteIdentities calldata");
 
             // @dev This is a type that is generated by abigen!() since there is a function defined with a conflicting function name but different params
             let delete_identities_call =
                 DeleteIdentitiesWithDeletionProofAndBatchSizeAndPackedDeletionIndicesAndPreRootCall::decode(calldata.as_ref())?;
+
+            if !self.check_pre_root(
+                tree_data,
+                tx_hash,
+                delete_identities_call.pre_root,
+                delete_identities_call.post_root,
+            )? {
+                return Ok(());
+            }
             let indices = unpack_indices(
                 delete_identities_call.packed_deletion_indices.as_ref(),
             );
@@ -269,11 +1148,13 @@ impl<M: Middleware> TreeUpdater<M> {
                     pubkey: Set(id.to_string()),
                     deleted_at_block: Set(transaction.block_number.unwrap().as_u64() as i64),
                     deleted_in_tx: Set(transaction.hash.encode_hex()),
-                    created_at: Set(DateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
+                    created_at: Set(DateTime::from_timestamp_opt(block_timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
                     ..Default::default()
                 }
             }).collect();
-            Deletions::insert_many(entities).exec(db).await.expect("Failed to insert identities into db");
+            if let Some(db) = db {
+                db.write(DbWrite::Deletions(entities));
+            }
 
             let batch_entity = batches::ActiveModel {
                 tx: Set(transaction.hash.encode_hex()),
@@ -284,21 +1165,63 @@ impl<M: Middleware> TreeUpdater<M> {
                 proof: Set(delete_identities_call.deletion_proof.encode_hex().into()),
                 preroot: Set(delete_identities_call.pre_root.encode_hex()),
                 postroot: Set(delete_identities_call.post_root.encode_hex()),
-                created_at: Set(DateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
+                created_at: Set(DateTime::from_timestamp_opt(block_timestamp.as_u64() as i64, 0).expect("Failed to parse datetime from block timestamp").and_utc().into()),
                 ..Default::default()
             };
-            Batches::insert(batch_entity).exec(db).await.expect("Failed to insert batch into db");
+            if let Some(db) = db {
+                db.write(DbWrite::Batch(batch_entity));
+            }
             
             tree_data.delete_many(&indices);
+            self.identities_deleted
+                .fetch_add(indices.len() as u64, Ordering::SeqCst);
+
+            self.audit(AuditEvent::BatchApplied {
+                kind: BatchKind::Deletion,
+                pre_root: delete_identities_call.pre_root,
+                post_root: delete_identities_call.post_root,
+                batch_size: indices.len(),
+                block_number: transaction
+                    .block_number
+                    .map(|block| block.as_u64()),
+                tx_hash: transaction.hash,
+            });
+
+            // Sending only fails when there are no subscribers
+            let _ = self.deletion_updates.send(DeletionUpdate {
+                root: tree_data.tree.root(),
+                deleted_indices: indices,
+            });
             
         } else {
             return Err(TreeAvailabilityError::UnrecognizedFunctionSelector);
         }
 
+        if let Some(block_number) = transaction.block_number {
+            tree_data.latest_root_block = block_number.as_u64();
+        }
+
         Ok(())
     }
 }
 
+/// Filter matching the `TreeChanged` events emitted by the `WorldIDIdentityManager` at `address`.
+pub fn tree_changed_filter(address: H160) -> Filter {
+    Filter::new()
+        .address(address)
+        .topic0(ValueOrArray::Value(TreeChangedFilter::signature()))
+}
+
+/// Alert posted to the configured webhook when the insertion rate limit is exceeded.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InsertionRateAlert {
+    block_number: u64,
+    insertions: usize,
+    max_insertions: usize,
+    window_blocks: u64,
+}
+
 /// Packs an array of 32-bit indices into a contiguous byte vector.
 ///
 /// # Arguments
@@ -333,9 +1256,394 @@ pub fn unpack_indices(packed: &[u8]) -> Vec<u32> {
     indices
 }
 
+/// Returns the index of the first `TreeChanged` log whose `pre_root` does not match the `post_root` of the previous log, starting from `local_root`. Returns `None` if the logs form an unbroken chain.
+///
+/// # Arguments
+///
+/// * `local_root` - Root of the local tree before applying the logs.
+/// * `logs` - `TreeChanged` logs, ordered as emitted onchain.
+pub fn first_broken_link(local_root: Hash, logs: &[Log]) -> Option<usize> {
+    let mut expected_pre_root = local_root;
+
+    for (index, log) in logs.iter().enumerate() {
+        let Ok(event) = parse_log::<TreeChangedFilter>(log.clone()) else {
+            continue;
+        };
+
+        if Hash::from_limbs(event.pre_root.0) != expected_pre_root {
+            return Some(index);
+        }
+
+        expected_pre_root = Hash::from_limbs(event.post_root.0);
+    }
+
+    None
+}
+
+/// Relation of a batch to the local tree, as determined by its roots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchStatus {
+    /// The batch applies on top of the local root.
+    Pending,
+    /// The local root is the post-root of the batch, which is already applied.
+    Applied,
+    /// The local root is neither the pre-root nor the post-root of the batch.
+    Mismatch,
+}
+
+/// Determines whether a batch is yet to be applied to the local tree, already applied, or does not apply on top of it.
+///
+/// # Arguments
+///
+/// * `local_root` - Root of the local tree.
+/// * `pre_root` - Root the batch applies on top of.
+/// * `post_root` - Root after the batch is applied.
+pub fn batch_status(
+    local_root: Hash,
+    pre_root: U256,
+    post_root: U256,
+) -> BatchStatus {
+    if Hash::from_limbs(pre_root.0) == local_root {
+        BatchStatus::Pending
+    } else if Hash::from_limbs(post_root.0) == local_root {
+        BatchStatus::Applied
+    } else {
+        BatchStatus::Mismatch
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use ethers::providers::{
+        JsonRpcError, MockProvider, MockResponse, Provider,
+    };
+    use ethers::types::{Block, Bytes};
+    use semaphore::lazy_merkle_tree::Canonical;
+
     use super::*;
+    use crate::tree::PoseidonTree;
+
+    const TREE_DEPTH: usize = 10;
+
+    fn tree_changed_log(pre_root: u64, post_root: u64) -> Log {
+        Log {
+            topics: vec![
+                TreeChangedFilter::signature(),
+                H256::from_low_u64_be(pre_root),
+                H256::zero(),
+                H256::from_low_u64_be(post_root),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_first_broken_link() {
+        let logs = vec![
+            tree_changed_log(1, 2),
+            tree_changed_log(2, 3),
+            tree_changed_log(3, 4),
+        ];
+
+        assert_eq!(first_broken_link(Hash::from(1_u64), &logs), None);
+        assert_eq!(first_broken_link(Hash::from(2_u64), &logs), Some(0));
+
+        // The log taking the tree from root 2 to root 3 is missing
+        let logs = vec![tree_changed_log(1, 2), tree_changed_log(3, 4)];
+
+        assert_eq!(first_broken_link(Hash::from(1_u64), &logs), Some(1));
+    }
+
+    /// Pushes the responses to a sync of blocks 1-10 holding a single `registerIdentities` transaction in block 5, up to and including the transaction itself.
+    fn push_window(mock: &MockProvider, transaction: MockResponse) {
+        let mut log = tree_changed_log(1, 2);
+        log.transaction_hash = Some(H256::repeat_byte(2));
+
+        // Responses are popped in reverse order of being pushed
+        mock.push_response(transaction);
+        mock.push(vec![log]).unwrap();
+        mock.push(Block::<H256> {
+            hash: Some(H256::repeat_byte(1)),
+            ..Default::default()
+        })
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_window_is_reapplied() {
+        let (provider, mock) = Provider::mocked();
+        let config = WorldTreeConfig::new(H160::zero(), 0, TREE_DEPTH, 0, 0);
+        let tree_updater = TreeUpdater::new(&config, Arc::new(provider));
+
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            TREE_DEPTH,
+            0,
+            &Hash::ZERO,
+        );
+        let pre_root = U256(*tree.root().as_limbs());
+        let tree_data = RwLock::new(TreeData::new(tree, 0));
+
+        let identity = U256::from(42);
+        let transaction = Transaction {
+            hash: H256::repeat_byte(2),
+            block_number: Some(U64::from(5)),
+            transaction_index: Some(U64::zero()),
+            input: RegisterIdentitiesCall {
+                insertion_proof: [U256::zero(); 8],
+                pre_root,
+                start_index: 0,
+                identity_commitments: vec![identity],
+                post_root: U256::from(2),
+            }
+            .encode()
+            .into(),
+            ..Default::default()
+        };
+
+        // Fetching the transaction fails after the window has been scanned
+        push_window(
+            &mock,
+            MockResponse::Error(JsonRpcError {
+                code: -32000,
+                message: "header not found".to_owned(),
+                data: None,
+            }),
+        );
+        assert!(tree_updater
+            .sync_to_block(&tree_data, None, 10)
+            .await
+            .is_err());
+        assert_eq!(tree_updater.latest_synced_block.load(Ordering::SeqCst), 0);
+
+        // The restarted sync scans the window again and applies its batch
+        mock.push(Block::<H256> {
+            timestamp: U256::from(1),
+            ..Default::default()
+        })
+        .unwrap();
+        push_window(
+            &mock,
+            MockResponse::Value(serde_json::to_value(&transaction).unwrap()),
+        );
+        tree_updater
+            .sync_to_block(&tree_data, None, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(tree_updater.latest_synced_block.load(Ordering::SeqCst), 10);
+        assert_eq!(
+            tree_data.read().await.tree.get_leaf(0),
+            Hash::from_limbs(identity.0)
+        );
+
+        // The window may not end at the chain head, so its root is not cached as the onchain root
+        mock.push(Bytes::from(U256::from(3).encode())).unwrap();
+        assert_eq!(
+            tree_updater.onchain_root.latest_root().await.unwrap(),
+            Hash::from(3)
+        );
+    }
+
+    /// `registerIdentities` transaction inserting `identity` at `start_index` at the given position in block 5.
+    fn register_identity(
+        transaction_index: u64,
+        start_index: u32,
+        identity: U256,
+        pre_root: Hash,
+        post_root: Hash,
+    ) -> Transaction {
+        Transaction {
+            hash: H256::from_low_u64_be(transaction_index + 1),
+            block_number: Some(U64::from(5)),
+            transaction_index: Some(U64::from(transaction_index)),
+            input: RegisterIdentitiesCall {
+                insertion_proof: [U256::zero(); 8],
+                pre_root: U256(*pre_root.as_limbs()),
+                start_index,
+                identity_commitments: vec![identity],
+                post_root: U256(*post_root.as_limbs()),
+            }
+            .encode()
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    /// Two batches landing in block 5, the second applying on top of the first, along with the roots of the tree before and after each of them.
+    fn same_block_batches() -> ([Transaction; 2], [Hash; 3]) {
+        let tree = PoseidonTree::<Canonical>::new_with_dense_prefix(
+            TREE_DEPTH,
+            0,
+            &Hash::ZERO,
+        );
+        let (first, second) = (U256::from(42), U256::from(43));
+
+        let empty_root = tree.root();
+        let tree = tree.update_with_mutation(0, &Hash::from_limbs(first.0));
+        let first_root = tree.root();
+        let tree = tree.update_with_mutation(1, &Hash::from_limbs(second.0));
+        let second_root = tree.root();
+
+        (
+            [
+                register_identity(0, 0, first, empty_root, first_root),
+                register_identity(1, 1, second, first_root, second_root),
+            ],
+            [empty_root, first_root, second_root],
+        )
+    }
+
+    fn transaction_log(transaction: &Transaction) -> Log {
+        Log {
+            transaction_hash: Some(transaction.hash),
+            block_number: transaction.block_number,
+            ..tree_changed_log(1, 2)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_in_same_block_are_applied_in_order() {
+        let (provider, mock) = Provider::mocked();
+        let config = WorldTreeConfig::new(H160::zero(), 0, TREE_DEPTH, 0, 0);
+        let tree_updater = TreeUpdater::new(&config, Arc::new(provider));
+
+        let ([first, second], [empty_root, _, second_root]) =
+            same_block_batches();
+        let tree_data = RwLock::new(TreeData::new(
+            PoseidonTree::<Canonical>::new_with_dense_prefix(
+                TREE_DEPTH,
+                0,
+                &Hash::ZERO,
+            ),
+            0,
+        ));
+        assert_eq!(tree_data.read().await.tree.root(), empty_root);
+
+        // Responses are popped in reverse order of being pushed. The second batch is returned first, and must still be applied after the first one
+        mock.push(Block::<H256> {
+            timestamp: U256::from(1),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(first.clone()).unwrap();
+        mock.push(second.clone()).unwrap();
+
+        let logs = vec![transaction_log(&first), transaction_log(&second)];
+        tree_updater
+            .apply_window(&tree_data, None, logs, 1, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(tree_data.read().await.tree.root(), second_root);
+    }
+
+    #[tokio::test]
+    async fn test_same_block_batches_sync_without_root_mismatch() {
+        let (provider, mock) = Provider::mocked();
+        let config = WorldTreeConfig::new(H160::zero(), 0, TREE_DEPTH, 0, 0);
+        let tree_updater = TreeUpdater::new(&config, Arc::new(provider));
+
+        let ([first, second], [_, _, second_root]) = same_block_batches();
+        let tree_data = RwLock::new(TreeData::new(
+            PoseidonTree::<Canonical>::new_with_dense_prefix(
+                TREE_DEPTH,
+                0,
+                &Hash::ZERO,
+            ),
+            0,
+        ));
+
+        // Responses are popped in reverse order of being pushed
+        mock.push(Block::<H256> {
+            timestamp: U256::from(1),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(first.clone()).unwrap();
+        mock.push(second.clone()).unwrap();
+        mock.push(vec![transaction_log(&first), transaction_log(&second)])
+            .unwrap();
+        mock.push(Block::<H256> {
+            hash: Some(H256::repeat_byte(1)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        tree_updater
+            .sync_to_block(&tree_data, None, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(tree_updater.latest_synced_block.load(Ordering::SeqCst), 10);
+        assert_eq!(tree_data.read().await.tree.root(), second_root);
+    }
+
+    fn rate_limited_updater(
+        action: AnomalyAction,
+    ) -> TreeUpdater<Provider<MockProvider>> {
+        let (provider, _mock) = Provider::mocked();
+        let mut config =
+            WorldTreeConfig::new(H160::zero(), 0, TREE_DEPTH, 0, 0);
+        config.insertion_rate_limit = Some(InsertionRateLimit {
+            max_insertions: 10,
+            window_blocks: 5,
+            action,
+        });
+
+        TreeUpdater::new(&config, Arc::new(provider))
+    }
+
+    #[tokio::test]
+    async fn test_check_insertion_rate() {
+        let tree_updater = rate_limited_updater(AnomalyAction::Halt);
+
+        assert!(tree_updater.check_insertion_rate(1, 6).is_ok());
+        assert!(tree_updater.check_insertion_rate(3, 4).is_ok());
+
+        // 11 insertions within blocks 1-5
+        assert!(matches!(
+            tree_updater.check_insertion_rate(5, 1),
+            Err(TreeAvailabilityError::InsertionRateExceeded {
+                block_number: 5,
+                insertions: 11,
+            })
+        ));
+
+        // Block 1 has left the window
+        assert!(tree_updater.check_insertion_rate(6, 0).is_ok());
+
+        // Exceeding the limit is only logged
+        let tree_updater = rate_limited_updater(AnomalyAction::Log);
+        assert!(tree_updater.check_insertion_rate(1, 11).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_insertion_rate_webhook() {
+        // Nothing listens on the webhook, which must not hold up the sync
+        let tree_updater = rate_limited_updater(AnomalyAction::Webhook {
+            url: "http://127.0.0.1:9/alert".parse().unwrap(),
+        });
+
+        assert!(tree_updater.check_insertion_rate(1, 11).is_ok());
+    }
+
+    #[test]
+    fn test_batch_status() {
+        let local_root = Hash::from(2_u64);
+
+        assert_eq!(
+            batch_status(local_root, U256::from(2), U256::from(3)),
+            BatchStatus::Pending
+        );
+        assert_eq!(
+            batch_status(local_root, U256::from(1), U256::from(2)),
+            BatchStatus::Applied
+        );
+        assert_eq!(
+            batch_status(local_root, U256::from(3), U256::from(4)),
+            BatchStatus::Mismatch
+        );
+    }
 
     #[test]
     fn test_pack_indices() {
@@ -1,3 +1,4 @@
+use std::net::SocketAddr;
 use std::str::FromStr;
 
 use common::test_utilities::chain_mock::{spawn_mock_chain, MockChain};
@@ -6,6 +7,8 @@ use ethers::types::U256;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use hyper::StatusCode;
+use tokio_util::sync::CancellationToken;
+use world_tree::tree::config::WorldTreeConfig;
 use world_tree::tree::error::TreeAvailabilityError;
 use world_tree::tree::service::{
     InclusionProofRequest, TreeAvailabilityService,
@@ -57,22 +60,26 @@ async fn test_inclusion_proof() -> eyre::Result<()> {
 
     // Initialize the tree availability service
     let world_tree_address = mock_world_id.address();
-    let tree_availability_service = TreeAvailabilityService::new(
+    let config = WorldTreeConfig::new(
+        world_tree_address,
+        world_tree_creation_block,
         3,
         1,
         5,
-        world_tree_address,
-        world_tree_creation_block,
-        1000,
-        middleware,
     );
+    let tree_availability_service =
+        TreeAvailabilityService::new(&config, middleware);
 
-    let world_tree = tree_availability_service.await.world_tree.clone();
+    let world_tree = tree_availability_service.world_tree.clone();
+    let shutdown = CancellationToken::new();
 
     // Spawn the service in a separate task
+    let server_shutdown = shutdown.clone();
     let server_handle = tokio::spawn(async move {
-        let handles =
-            tree_availability_service.await.serve(([127, 0, 0, 1], 8080).into());
+        let handles = tree_availability_service.serve(
+            vec![SocketAddr::from(([127, 0, 0, 1], 8080)).into()],
+            server_shutdown,
+        );
 
         let mut handles = handles.into_iter().collect::<FuturesUnordered<_>>();
         while let Some(result) = handles.next().await {
@@ -141,8 +148,9 @@ async fn test_inclusion_proof() -> eyre::Result<()> {
     let proof: Option<InclusionProof> = response.json().await?;
     assert!(proof.is_none());
 
-    // Cleanup the server resources
-    server_handle.abort();
+    // Shut the server and sync task down
+    shutdown.cancel();
+    server_handle.await??;
 
     Ok(())
 }